@@ -1,227 +1,20 @@
 //! Core types and logic shared between server and TUI.
+//!
+//! API types live in a module per version. The crate root re-exports the
+//! current version; clients pinned to an older one import it by name.
 
-use chrono::{DateTime, NaiveDate, Utc};
-use serde::{Deserialize, Serialize};
+use chrono::{Datelike, NaiveDate};
 
-// ============================================================================
-// Exercises
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct Exercise {
-    pub id: i64,
-    pub name: String,
-    pub description: Option<String>,
-    pub archived: bool,
-    pub owner: Owner,
-}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum Owner {
-    User,
-    Admin,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CreateExercise {
-    pub name: String,
-    pub description: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UpdateExercise {
-    pub name: Option<String>,
-    pub description: Option<String>,
-    pub archived: Option<bool>,
-}
-
-// ============================================================================
-// Workout sets
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct WorkoutSet {
-    pub id: i64,
-    pub exercise_id: i64,
-    pub day: String,
-    pub set_number: i32,
-    pub weight: f64,
-    pub reps: i32,
-    pub completed_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SetInput {
-    pub weight: f64,
-    pub reps: i32,
-    pub completed_at: Option<DateTime<Utc>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DaySets {
-    pub sets: Vec<WorkoutSet>,
-}
-
-// ============================================================================
-// Plans & templates
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Template {
-    pub id: i64,
-    pub name: String,
-    pub day_of_week: Option<i32>,
-    pub exercises: Vec<TemplateExercise>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TemplateExercise {
-    pub exercise_id: i64,
-    pub name: String,
-    pub target_sets: Option<i32>,
-    pub last_session: Option<LastSession>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LastSession {
-    pub date: DateTime<Utc>,
-    pub sets: Vec<CompactSet>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct CompactSet {
-    pub weight: f64,
-    pub reps: i32,
-}
-
-// ============================================================================
-// Activity
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ActivityQuery {
-    pub start: Option<DateTime<Utc>>,
-    pub end: Option<DateTime<Utc>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Activity {
-    pub days: Vec<ActivityDay>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ActivityDay {
-    pub date: String,
-    pub sets_completed: i64,
-}
-
-// ============================================================================
-// Graphs
-// ============================================================================
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum Metric {
-    MaxWeight,
-    SessionTotalVolume,
-    BestSetVolume,
-    #[serde(rename = "est_1rm")]
-    Est1Rm,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GraphQuery {
-    pub start: Option<DateTime<Utc>>,
-    pub end: Option<DateTime<Utc>>,
-    pub metric: Option<Metric>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Graph {
-    pub exercise_id: i64,
-    pub exercise_name: String,
-    pub points: Vec<GraphPoint>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GraphPoint {
-    pub date: String,
-    pub value: f64,
-}
-
-// ============================================================================
-// Body Weight
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct WeightEntry {
-    pub id: i64,
-    pub day: String,
-    pub weight_kg: f64,
-    pub recorded_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WeightInput {
-    pub weight_kg: f64,
-    pub recorded_at: Option<DateTime<Utc>>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WeightHistory {
-    pub entries: Vec<WeightEntry>,
-}
-
-// ============================================================================
-// Auth
-// ============================================================================
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RegisterInput {
-    pub username: String,
-    pub password: String,
-    pub totp_secret: String,
-    pub totp_code: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LoginInput {
-    pub username: String,
-    pub password: String,
-    pub totp: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Session {
-    pub user_id: i64,
-    pub username: String,
-    pub expires_at: DateTime<Utc>,
-}
+pub mod v1;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct User {
-    pub user_id: i64,
-    pub username: String,
-    pub totp_enabled: bool,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TotpSetup {
-    pub secret: String,
-    pub otpauth_url: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TotpVerify {
-    pub code: String,
-}
+pub use v1::*;
 
 // ============================================================================
 // Graph computation logic
 // ============================================================================
 
 /// Data point for graph computation.
+#[derive(Debug, Clone)]
 pub struct SetData {
     pub date: NaiveDate,
     pub weight: f64,
@@ -303,6 +96,256 @@ pub fn build_graph(sets: Vec<SetData>, metric: Metric, max_points: usize) -> Vec
         .collect()
 }
 
+/// Trailing moving average over the last `window` points.
+pub fn moving_average(points: &[GraphPoint], window: usize) -> Vec<GraphPoint> {
+    let window = window.max(1);
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let slice = &points[(i + 1).saturating_sub(window)..=i];
+            GraphPoint {
+                date: p.date.clone(),
+                value: slice.iter().map(|p| p.value).sum::<f64>() / slice.len() as f64,
+            }
+        })
+        .collect()
+}
+
+/// Least-squares line through the points, using calendar days as x so gaps
+/// between sessions are weighted correctly.
+pub fn linear_trend(points: &[GraphPoint]) -> Vec<GraphPoint> {
+    if points.is_empty() {
+        return Vec::new();
+    }
+
+    let xs: Vec<f64> = points
+        .iter()
+        .map(|p| {
+            NaiveDate::parse_from_str(&p.date, "%Y-%m-%d")
+                .map(|d| d.num_days_from_ce() as f64)
+                .unwrap_or(0.0)
+        })
+        .collect();
+
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.value).sum::<f64>() / n;
+    let (cov, var) = xs
+        .iter()
+        .zip(points)
+        .fold((0.0, 0.0), |(cov, var), (x, p)| {
+            (
+                cov + (x - mean_x) * (p.value - mean_y),
+                var + (x - mean_x).powi(2),
+            )
+        });
+    let slope = if var > 0.0 { cov / var } else { 0.0 };
+
+    xs.iter()
+        .zip(points)
+        .map(|(x, p)| GraphPoint {
+            date: p.date.clone(),
+            value: mean_y + slope * (x - mean_x),
+        })
+        .collect()
+}
+
+/// Computes an overlay series for already-built graph points.
+pub fn overlay_series(overlay: Overlay, points: &[GraphPoint], window: usize) -> Vec<GraphPoint> {
+    match overlay {
+        Overlay::MovingAverage => moving_average(points, window),
+        Overlay::Trend => linear_trend(points),
+    }
+}
+
+// ============================================================================
+// Body weight trend logic
+// ============================================================================
+
+/// Daily smoothing factor for the trend weight.
+pub const WEIGHT_SMOOTHING: f64 = 0.1;
+/// Days of trend used to compute the weekly rate.
+pub const WEIGHT_RATE_DAYS: i64 = 28;
+/// Weekly rates smaller than this (kg) count as flat, with no projection.
+pub const WEIGHT_FLAT_RATE: f64 = 0.001;
+
+/// Exponentially smoothed trend over daily weigh-ins. Gaps between entries
+/// are treated as repeated smoothing steps so sparse logging does not make
+/// the trend jumpy.
+pub fn smooth_weights(entries: &[(NaiveDate, f64)]) -> Vec<WeightTrendPoint> {
+    let mut sorted = entries.to_vec();
+    sorted.sort_by_key(|(day, _)| *day);
+
+    let mut points: Vec<WeightTrendPoint> = Vec::with_capacity(sorted.len());
+    for (day, weight_kg) in sorted {
+        let trend_kg = match points.last() {
+            Some(prev) => {
+                let gap = (day - prev.day).num_days().max(1) as i32;
+                let alpha = 1.0 - (1.0 - WEIGHT_SMOOTHING).powi(gap);
+                prev.trend_kg + alpha * (weight_kg - prev.trend_kg)
+            }
+            None => weight_kg,
+        };
+        points.push(WeightTrendPoint {
+            day,
+            weight_kg,
+            trend_kg,
+        });
+    }
+    points
+}
+
+/// Computes trend points, the weekly rate of change and an optional goal
+/// projection.
+pub fn weight_trend(entries: &[(NaiveDate, f64)], goal_kg: Option<f64>) -> WeightTrend {
+    let points = smooth_weights(entries);
+    let Some(last) = points.last() else {
+        return WeightTrend {
+            points,
+            trend_kg: None,
+            weekly_rate_kg: None,
+            goal: None,
+        };
+    };
+
+    // Regress the trend over the recent window
+    let since = last.day - chrono::Duration::days(WEIGHT_RATE_DAYS);
+    let recent: Vec<GraphPoint> = points
+        .iter()
+        .filter(|p| p.day >= since)
+        .map(|p| GraphPoint {
+            date: p.day.format("%Y-%m-%d").to_string(),
+            value: p.trend_kg,
+        })
+        .collect();
+    let first_day = points.iter().find(|p| p.day >= since).map(|p| p.day);
+    let span_days = first_day.map_or(0, |d| (last.day - d).num_days());
+    let weekly_rate_kg = match linear_trend(&recent).as_slice() {
+        [first, .., end] if span_days > 0 => {
+            Some((end.value - first.value) / span_days as f64 * 7.0)
+        }
+        _ => None,
+    };
+
+    let goal = goal_kg.map(|goal_kg| {
+        let remaining_kg = goal_kg - last.trend_kg;
+        let projected_date = weekly_rate_kg
+            .filter(|rate| rate.abs() >= WEIGHT_FLAT_RATE && rate.signum() == remaining_kg.signum())
+            .and_then(|rate| {
+                // Slow rates can put the date past what chrono represents
+                let days = (remaining_kg / rate * 7.0).ceil() as i64;
+                last.day
+                    .checked_add_signed(chrono::Duration::try_days(days)?)
+            });
+        GoalProjection {
+            goal_kg,
+            remaining_kg,
+            projected_date,
+        }
+    });
+
+    WeightTrend {
+        trend_kg: Some(last.trend_kg),
+        weekly_rate_kg,
+        goal,
+        points,
+    }
+}
+
+// ============================================================================
+// Strength scoring
+// ============================================================================
+
+fn polynomial(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+}
+
+/// Wilks score, with the original Schwartz/Malone coefficients rather than
+/// the 2020 revision.
+pub fn wilks(total_kg: f64, body_weight_kg: f64, sex: Sex) -> f64 {
+    let (coefficients, bw): (&[f64], f64) = match sex {
+        Sex::Male => (
+            &[
+                -216.0475144,
+                16.2606339,
+                -0.002388645,
+                -0.00113732,
+                7.01863e-06,
+                -1.291e-08,
+            ],
+            body_weight_kg.clamp(40.0, 201.9),
+        ),
+        Sex::Female => (
+            &[
+                594.31747775582,
+                -27.23842536447,
+                0.82112226871,
+                -0.00930733913,
+                4.731582e-05,
+                -9.054e-08,
+            ],
+            body_weight_kg.clamp(26.51, 154.53),
+        ),
+    };
+    total_kg * 500.0 / polynomial(coefficients, bw)
+}
+
+/// DOTS score.
+pub fn dots(total_kg: f64, body_weight_kg: f64, sex: Sex) -> f64 {
+    let (coefficients, bw): (&[f64], f64) = match sex {
+        Sex::Male => (
+            &[
+                -307.75076,
+                24.0900756,
+                -0.1918759221,
+                0.0007391293,
+                -0.000001093,
+            ],
+            body_weight_kg.clamp(40.0, 210.0),
+        ),
+        Sex::Female => (
+            &[
+                -57.96288,
+                13.6175032,
+                -0.1126655495,
+                0.0005158568,
+                -0.0000010706,
+            ],
+            body_weight_kg.clamp(40.0, 150.0),
+        ),
+    };
+    total_kg * 500.0 / polynomial(coefficients, bw)
+}
+
+/// IPF GL points for classic (raw) powerlifting.
+pub fn ipf_gl(total_kg: f64, body_weight_kg: f64, sex: Sex) -> f64 {
+    let (a, b, c) = match sex {
+        Sex::Male => (1199.72839, 1025.18162, 0.00921),
+        Sex::Female => (610.32796, 1045.59282, 0.03048),
+    };
+    total_kg * 100.0 / (a - b * (-c * body_weight_kg).exp())
+}
+
+pub fn bodyweight_multiple(lift_kg: f64, body_weight_kg: f64) -> f64 {
+    lift_kg / body_weight_kg
+}
+
+/// Summarizes one day's sets into a history entry.
+pub fn history_session(date: NaiveDate, sets: Vec<CompactSet>) -> HistorySession {
+    let est_1rm = sets
+        .iter()
+        .map(|s| estimate_1rm(s.weight, s.reps))
+        .fold(0.0, f64::max);
+    let volume = sets.iter().map(|s| s.weight * s.reps as f64).sum();
+    HistorySession {
+        date,
+        sets,
+        est_1rm,
+        volume,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -347,15 +390,122 @@ mod tests {
         assert_eq!(points.len(), 2);
     }
 
+    fn points(values: &[f64]) -> Vec<GraphPoint> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| GraphPoint {
+                date: format!("2024-01-{:02}", i + 1),
+                value: *v,
+            })
+            .collect()
+    }
+
     #[test]
-    fn test_metric_serde() {
-        assert_eq!(
-            serde_json::to_string(&Metric::Est1Rm).unwrap(),
-            "\"est_1rm\""
-        );
-        assert_eq!(
-            serde_json::to_string(&Metric::MaxWeight).unwrap(),
-            "\"max_weight\""
+    fn test_moving_average() {
+        let avg = moving_average(&points(&[1.0, 3.0, 5.0, 7.0]), 2);
+        let values: Vec<_> = avg.iter().map(|p| p.value).collect();
+        assert_eq!(values, vec![1.0, 2.0, 4.0, 6.0]);
+        assert_eq!(avg[3].date, "2024-01-04");
+    }
+
+    #[test]
+    fn test_linear_trend() {
+        let trend = linear_trend(&points(&[10.0, 12.0, 14.0]));
+        for (p, expected) in trend.iter().zip([10.0, 12.0, 14.0]) {
+            assert!((p.value - expected).abs() < 1e-9);
+        }
+        assert_eq!(linear_trend(&points(&[5.0]))[0].value, 5.0);
+        assert!(linear_trend(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_smooth_weights_gap() {
+        let d = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        let daily = smooth_weights(&[(d(1), 80.0), (d(2), 90.0), (d(3), 90.0)]);
+        let gapped = smooth_weights(&[(d(1), 80.0), (d(3), 90.0)]);
+        assert_eq!(daily[0].trend_kg, 80.0);
+        assert!((daily[1].trend_kg - 81.0).abs() < 1e-9);
+        // A two-day gap smooths like two daily steps toward the new weight
+        assert!((gapped[1].trend_kg - 81.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_weight_trend_cut() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let entries: Vec<_> = (0..60)
+            .map(|i| (start + chrono::Duration::days(i), 90.0 - i as f64 * 0.1))
+            .collect();
+
+        let trend = weight_trend(&entries, Some(80.0));
+        let rate = trend.weekly_rate_kg.unwrap();
+        assert!((rate + 0.7).abs() < 0.05, "rate {rate}");
+
+        let goal = trend.goal.unwrap();
+        assert!(goal.remaining_kg < 0.0);
+        assert!(goal.projected_date.unwrap() > start + chrono::Duration::days(59));
+
+        // Bulking goal while cutting never gets reached
+        let away = weight_trend(&entries, Some(100.0)).goal.unwrap();
+        assert_eq!(away.projected_date, None);
+    }
+
+    #[test]
+    fn test_weight_trend_flat() {
+        // The trend creeps towards 80 kg at a tiny but nonzero rate
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let entries: Vec<_> = (0..300)
+            .map(|i| {
+                (
+                    start + chrono::Duration::days(i),
+                    if i == 0 { 81.0 } else { 80.0 },
+                )
+            })
+            .collect();
+
+        let trend = weight_trend(&entries, Some(70.0));
+        let rate = trend.weekly_rate_kg.unwrap();
+        assert!(rate != 0.0 && rate.abs() < WEIGHT_FLAT_RATE, "rate {rate}");
+        assert_eq!(trend.goal.unwrap().projected_date, None);
+    }
+
+    #[test]
+    fn test_weight_trend_empty() {
+        let trend = weight_trend(&[], Some(70.0));
+        assert!(trend.points.is_empty());
+        assert_eq!(trend.trend_kg, None);
+        assert!(trend.goal.is_none());
+    }
+
+    #[test]
+    fn test_strength_scores() {
+        let close = |a: f64, b: f64| (a - b).abs() < 0.5;
+        assert!(close(wilks(700.0, 100.0, Sex::Male), 425.9));
+        assert!(close(dots(700.0, 100.0, Sex::Male), 430.9));
+        assert!(close(ipf_gl(700.0, 100.0, Sex::Male), 88.4));
+        assert!(close(dots(400.0, 60.0, Sex::Female), 443.4));
+        assert!(close(ipf_gl(400.0, 60.0, Sex::Female), 90.4));
+        assert_eq!(bodyweight_multiple(200.0, 80.0), 2.5);
+    }
+
+    #[test]
+    fn test_history_session() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let session = history_session(
+            date,
+            vec![
+                CompactSet {
+                    weight: 100.0,
+                    reps: 5,
+                },
+                CompactSet {
+                    weight: 110.0,
+                    reps: 1,
+                },
+            ],
         );
+        assert_eq!(session.volume, 610.0);
+        assert!((session.est_1rm - 116.666).abs() < 0.01);
+        assert_eq!(history_session(date, Vec::new()).volume, 0.0);
     }
 }
@@ -0,0 +1,1001 @@
+//! Version 1 of the API: request and response types for `/api/v1`.
+//!
+//! Types here only change in backwards compatible ways (new optional
+//! fields, new enum values older clients read as `Unknown`). Anything else
+//! goes in a new version module, and this one stays as is for older clients.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+// ============================================================================
+// Exercises
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Exercise {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub archived: bool,
+    pub owner: Owner,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Owner {
+    User,
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateExercise {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct UpdateExercise {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub archived: Option<bool>,
+}
+
+// ============================================================================
+// Workout sets
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WorkoutSet {
+    pub id: i64,
+    pub exercise_id: i64,
+    pub day: String,
+    pub set_number: i32,
+    pub weight: f64,
+    pub reps: i32,
+    pub completed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SetInput {
+    pub weight: f64,
+    pub reps: i32,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DaySets {
+    pub sets: Vec<WorkoutSet>,
+}
+
+// ============================================================================
+// Plans & templates
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Template {
+    pub id: i64,
+    pub name: String,
+    pub day_of_week: Option<i32>,
+    pub exercises: Vec<TemplateExercise>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TemplateExercise {
+    pub exercise_id: i64,
+    pub name: String,
+    pub target_sets: Option<i32>,
+    pub last_session: Option<LastSession>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LastSession {
+    pub date: DateTime<Utc>,
+    pub sets: Vec<CompactSet>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CompactSet {
+    pub weight: f64,
+    pub reps: i32,
+}
+
+// ============================================================================
+// Activity
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct ActivityQuery {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Activity {
+    pub days: Vec<ActivityDay>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ActivityDay {
+    pub date: String,
+    pub sets_completed: i64,
+}
+
+// ============================================================================
+// Graphs
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    MaxWeight,
+    SessionTotalVolume,
+    BestSetVolume,
+    #[serde(rename = "est_1rm")]
+    Est1Rm,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::MaxWeight,
+        Metric::SessionTotalVolume,
+        Metric::BestSetVolume,
+        Metric::Est1Rm,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Metric::MaxWeight => "max_weight",
+            Metric::SessionTotalVolume => "session_total_volume",
+            Metric::BestSetVolume => "best_set_volume",
+            Metric::Est1Rm => "est_1rm",
+        }
+    }
+
+    /// Human-readable axis title.
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::MaxWeight => "Max weight (kg)",
+            Metric::SessionTotalVolume => "Session volume (kg)",
+            Metric::BestSetVolume => "Best set volume (kg)",
+            Metric::Est1Rm => "Est. 1RM (kg)",
+        }
+    }
+
+    /// The next metric in `ALL`, wrapping around.
+    pub fn next(self) -> Metric {
+        let idx = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| format!("unknown metric '{s}'"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct GraphQuery {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub metric: Option<Metric>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Graph {
+    pub exercise_id: i64,
+    pub exercise_name: String,
+    pub points: Vec<GraphPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GraphPoint {
+    pub date: String,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Overlay {
+    MovingAverage,
+    Trend,
+}
+
+/// Query for `GET /api/v1/graphs`. Lists are comma-separated so they survive
+/// query-string encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct MultiGraphQuery {
+    pub exercise_ids: String,
+    pub metrics: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub overlay: Option<Overlay>,
+    /// Moving average window in sessions, 1-100.
+    pub window: Option<usize>,
+}
+
+impl MultiGraphQuery {
+    pub fn new(exercise_ids: &[i64], metrics: &[Metric]) -> Self {
+        Self {
+            exercise_ids: join(exercise_ids.iter()),
+            metrics: Some(join(metrics.iter().map(|m| m.as_str()))),
+            start: None,
+            end: None,
+            overlay: None,
+            window: None,
+        }
+    }
+
+    pub fn parse_exercise_ids(&self) -> Result<Vec<i64>, String> {
+        split(&self.exercise_ids)
+            .map(|s| s.parse().map_err(|_| format!("invalid exercise id '{s}'")))
+            .collect()
+    }
+
+    /// Requested metrics, defaulting to max weight.
+    pub fn parse_metrics(&self) -> Result<Vec<Metric>, String> {
+        match self.metrics.as_deref().map(split) {
+            Some(parts) => parts.map(str::parse).collect(),
+            None => Ok(vec![Metric::MaxWeight]),
+        }
+    }
+}
+
+fn join<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    items.map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn split(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim).filter(|p| !p.is_empty())
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SeriesKind {
+    Raw,
+    MovingAverage,
+    Trend,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GraphSeries {
+    pub exercise_id: i64,
+    pub exercise_name: String,
+    pub metric: Metric,
+    pub kind: SeriesKind,
+    pub points: Vec<GraphPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MultiGraph {
+    pub series: Vec<GraphSeries>,
+}
+
+// ============================================================================
+// Exercise history
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct HistoryQuery {
+    /// Only return sessions strictly before this day (cursor from `next_before`).
+    pub before: Option<NaiveDate>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ExerciseHistory {
+    pub exercise_id: i64,
+    pub exercise_name: String,
+    pub sessions: Vec<HistorySession>,
+    /// Cursor for the next page, `None` when there are no older sessions.
+    pub next_before: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HistorySession {
+    pub date: NaiveDate,
+    pub sets: Vec<CompactSet>,
+    pub est_1rm: f64,
+    pub volume: f64,
+}
+
+// ============================================================================
+// Body Weight
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WeightEntry {
+    pub id: i64,
+    pub day: String,
+    pub weight_kg: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WeightInput {
+    pub weight_kg: f64,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WeightHistory {
+    pub entries: Vec<WeightEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct WeightTrendQuery {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub goal_kg: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WeightTrend {
+    pub points: Vec<WeightTrendPoint>,
+    pub trend_kg: Option<f64>,
+    /// Change of the trend weight per week; negative while cutting.
+    pub weekly_rate_kg: Option<f64>,
+    pub goal: Option<GoalProjection>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct WeightTrendPoint {
+    pub day: NaiveDate,
+    pub weight_kg: f64,
+    pub trend_kg: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct GoalProjection {
+    pub goal_kg: f64,
+    pub remaining_kg: f64,
+    /// `None` when the current rate is flat or heading away from the goal.
+    pub projected_date: Option<NaiveDate>,
+}
+
+// ============================================================================
+// Body measurements
+// ============================================================================
+
+/// Built-in measurement kinds; users may log any other valid kind too.
+pub const MEASUREMENT_KINDS: [&str; 4] = ["waist", "arm", "chest", "body_fat"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MeasurementEntry {
+    pub id: i64,
+    pub kind: String,
+    pub day: String,
+    pub value: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MeasurementInput {
+    pub value: f64,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct MeasurementQuery {
+    pub kind: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MeasurementHistory {
+    pub entries: Vec<MeasurementEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MeasurementKind {
+    pub kind: String,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct MeasurementGraph {
+    pub kind: String,
+    pub unit: String,
+    pub points: Vec<GraphPoint>,
+}
+
+/// Unit shown for a measurement kind.
+pub fn measurement_unit(kind: &str) -> &'static str {
+    if kind == "body_fat" { "%" } else { "cm" }
+}
+
+/// Kinds are lowercase slugs so they are safe in paths, e.g. `left_thigh`.
+pub fn validate_measurement_kind(kind: &str) -> Result<(), String> {
+    let valid = !kind.is_empty()
+        && kind.len() <= 32
+        && kind
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid measurement kind '{kind}', use up to 32 of a-z, 0-9 and _"
+        ))
+    }
+}
+
+// ============================================================================
+// Profile & strength reports
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Sex {
+    Male,
+    Female,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Lift {
+    Squat,
+    Bench,
+    Deadlift,
+}
+
+impl Lift {
+    pub const ALL: [Lift; 3] = [Lift::Squat, Lift::Bench, Lift::Deadlift];
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Profile {
+    pub sex: Option<Sex>,
+    pub birth_year: Option<i32>,
+    pub squat_exercise_id: Option<i64>,
+    pub bench_exercise_id: Option<i64>,
+    pub deadlift_exercise_id: Option<i64>,
+}
+
+impl Profile {
+    pub fn lift_exercise(&self, lift: Lift) -> Option<i64> {
+        match lift {
+            Lift::Squat => self.squat_exercise_id,
+            Lift::Bench => self.bench_exercise_id,
+            Lift::Deadlift => self.deadlift_exercise_id,
+        }
+    }
+
+    pub fn set_lift_exercise(&mut self, lift: Lift, exercise_id: Option<i64>) {
+        match lift {
+            Lift::Squat => self.squat_exercise_id = exercise_id,
+            Lift::Bench => self.bench_exercise_id = exercise_id,
+            Lift::Deadlift => self.deadlift_exercise_id = exercise_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "openapi",
+    derive(utoipa::IntoParams),
+    into_params(parameter_in = Query)
+)]
+pub struct StrengthQuery {
+    /// Report as of this day, defaults to today.
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LiftBest {
+    pub lift: Lift,
+    pub exercise_id: i64,
+    pub exercise_name: String,
+    pub est_1rm: f64,
+    pub day: NaiveDate,
+    pub bodyweight_multiple: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StrengthReport {
+    pub sex: Option<Sex>,
+    pub age: Option<i32>,
+    pub body_weight_kg: Option<f64>,
+    pub body_weight_day: Option<NaiveDate>,
+    pub lifts: Vec<LiftBest>,
+    /// Sum of the best squat, bench and deadlift; `None` until all three exist.
+    pub total_kg: Option<f64>,
+    pub wilks: Option<f64>,
+    pub dots: Option<f64>,
+    pub ipf_gl: Option<f64>,
+}
+
+// ============================================================================
+// Auth
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RegisterInput {
+    pub username: String,
+    pub password: String,
+    pub totp_secret: String,
+    pub totp_code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LoginInput {
+    pub username: String,
+    pub password: String,
+    /// 6-digit TOTP code, or a recovery code.
+    pub totp: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionInfo {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// True for the session making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChangePasswordInput {
+    pub old_password: String,
+    pub new_password: String,
+    pub totp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeleteAccountInput {
+    pub password: String,
+    pub totp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Session {
+    pub user_id: i64,
+    pub username: String,
+    pub expires_at: DateTime<Utc>,
+    /// Only set on registration; shown once and never retrievable again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecoveryCodes {
+    pub codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RegenerateRecoveryCodes {
+    pub password: String,
+    pub totp: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TokenScope {
+    /// GET requests only.
+    Read,
+    ReadWrite,
+}
+
+/// Personal API token, sent as `Authorization: Bearer <token>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreateApiToken {
+    pub name: String,
+    pub scope: TokenScope,
+    /// Never expires when omitted.
+    #[serde(default)]
+    pub expires_in_days: Option<u32>,
+    pub password: String,
+    pub totp: String,
+}
+
+/// The secret is only returned here; the server keeps a hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: ApiToken,
+    pub token: String,
+}
+
+/// Passkey (WebAuthn) types. Binary fields are unpadded base64url, matching
+/// what browsers hand out from `navigator.credentials`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Passkey {
+    pub id: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Adding a passkey grants login, so it needs the same proof as a password change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PasskeyRegistrationStart {
+    pub password: String,
+    pub totp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub rp_name: String,
+    pub user_id: String,
+    pub user_name: String,
+    /// Credentials already registered, so the authenticator doesn't create a duplicate.
+    pub exclude_credentials: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PasskeyRegistration {
+    pub name: String,
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PasskeyAssertion {
+    pub credential_id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct User {
+    pub user_id: i64,
+    pub username: String,
+    pub totp_enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TotpSetup {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TotpVerify {
+    pub code: String,
+}
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+/// Stable, machine-readable error code. Match on this rather than on the
+/// human-readable message, which may change.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // 400
+    BadRequest,
+    // 401
+    Unauthorized,
+    TotpInvalid,
+    // 403
+    Forbidden,
+    // 404
+    NotFound,
+    #[serde(rename = "not_found.exercise")]
+    ExerciseNotFound,
+    #[serde(rename = "not_found.set")]
+    SetNotFound,
+    #[serde(rename = "not_found.plan")]
+    PlanNotFound,
+    #[serde(rename = "not_found.plan_exercise")]
+    PlanExerciseNotFound,
+    #[serde(rename = "not_found.weight_entry")]
+    WeightEntryNotFound,
+    #[serde(rename = "not_found.measurement")]
+    MeasurementNotFound,
+    #[serde(rename = "not_found.session")]
+    SessionNotFound,
+    #[serde(rename = "not_found.api_token")]
+    ApiTokenNotFound,
+    #[serde(rename = "not_found.passkey")]
+    PasskeyNotFound,
+    // 409
+    UsernameTaken,
+    PlanExistsForDay,
+    PasskeyExists,
+    // 422, with per-field codes in `ErrorBody::details`
+    ValidationFailed,
+    Required,
+    InvalidDate,
+    InvalidFormat,
+    OutOfRange,
+    // 429
+    RateLimited,
+    // 500
+    Internal,
+    /// A code this client doesn't know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    /// Every code the server sends.
+    pub const ALL: [ErrorCode; 24] = [
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::TotpInvalid,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::ExerciseNotFound,
+        ErrorCode::SetNotFound,
+        ErrorCode::PlanNotFound,
+        ErrorCode::PlanExerciseNotFound,
+        ErrorCode::WeightEntryNotFound,
+        ErrorCode::MeasurementNotFound,
+        ErrorCode::SessionNotFound,
+        ErrorCode::ApiTokenNotFound,
+        ErrorCode::PasskeyNotFound,
+        ErrorCode::UsernameTaken,
+        ErrorCode::PlanExistsForDay,
+        ErrorCode::PasskeyExists,
+        ErrorCode::ValidationFailed,
+        ErrorCode::Required,
+        ErrorCode::InvalidDate,
+        ErrorCode::InvalidFormat,
+        ErrorCode::OutOfRange,
+        ErrorCode::RateLimited,
+        ErrorCode::Internal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::TotpInvalid => "totp_invalid",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::ExerciseNotFound => "not_found.exercise",
+            ErrorCode::SetNotFound => "not_found.set",
+            ErrorCode::PlanNotFound => "not_found.plan",
+            ErrorCode::PlanExerciseNotFound => "not_found.plan_exercise",
+            ErrorCode::WeightEntryNotFound => "not_found.weight_entry",
+            ErrorCode::MeasurementNotFound => "not_found.measurement",
+            ErrorCode::SessionNotFound => "not_found.session",
+            ErrorCode::ApiTokenNotFound => "not_found.api_token",
+            ErrorCode::PasskeyNotFound => "not_found.passkey",
+            ErrorCode::UsernameTaken => "username_taken",
+            ErrorCode::PlanExistsForDay => "plan_exists_for_day",
+            ErrorCode::PasskeyExists => "passkey_exists",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::Required => "required",
+            ErrorCode::InvalidDate => "invalid_date",
+            ErrorCode::InvalidFormat => "invalid_format",
+            ErrorCode::OutOfRange => "out_of_range",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Internal => "internal",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One invalid input field of a `validation_failed` error.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FieldError {
+    /// Body, query or path field name, e.g. `reps` or `date`.
+    pub field: String,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+/// Body of every error response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    /// Human-readable message.
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+    /// Quote this when reporting a problem; it appears in the server logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+// ============================================================================
+// Admin
+// ============================================================================
+
+/// A database snapshot in the server's backup directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Backup {
+    pub file: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_graph_query() {
+        let query = MultiGraphQuery::new(&[3, 7], &[Metric::MaxWeight, Metric::Est1Rm]);
+        assert_eq!(query.parse_exercise_ids().unwrap(), vec![3, 7]);
+        assert_eq!(
+            query.parse_metrics().unwrap(),
+            vec![Metric::MaxWeight, Metric::Est1Rm]
+        );
+
+        let encoded = serde_urlencoded::to_string(&query).unwrap();
+        assert_eq!(encoded, "exercise_ids=3%2C7&metrics=max_weight%2Cest_1rm");
+
+        let bad: MultiGraphQuery =
+            serde_urlencoded::from_str("exercise_ids=1&metrics=bogus").unwrap();
+        assert!(bad.parse_metrics().is_err());
+        assert_eq!(Metric::Est1Rm.next(), Metric::MaxWeight);
+    }
+
+    #[test]
+    fn test_measurement_kind() {
+        assert!(validate_measurement_kind("body_fat").is_ok());
+        assert!(validate_measurement_kind("left_thigh2").is_ok());
+        assert!(validate_measurement_kind("").is_err());
+        assert!(validate_measurement_kind("Waist").is_err());
+        assert!(validate_measurement_kind("a/b").is_err());
+        assert_eq!(measurement_unit("body_fat"), "%");
+        assert_eq!(measurement_unit("waist"), "cm");
+    }
+
+    #[test]
+    fn test_history_query_serde() {
+        let query: HistoryQuery = serde_urlencoded::from_str("before=2024-02-01&limit=10").unwrap();
+        assert_eq!(query.before, NaiveDate::from_ymd_opt(2024, 2, 1));
+        assert_eq!(query.limit, Some(10));
+    }
+
+    #[test]
+    fn test_metric_serde() {
+        assert_eq!(
+            serde_json::to_string(&Metric::Est1Rm).unwrap(),
+            "\"est_1rm\""
+        );
+        assert_eq!(
+            serde_json::to_string(&Metric::MaxWeight).unwrap(),
+            "\"max_weight\""
+        );
+    }
+
+    #[test]
+    fn test_error_code_serde() {
+        for code in ErrorCode::ALL {
+            let json = serde_json::to_string(&code).unwrap();
+            assert_eq!(json, format!("\"{}\"", code.as_str()));
+            assert_eq!(serde_json::from_str::<ErrorCode>(&json).unwrap(), code);
+        }
+        // Codes added later don't break older clients
+        let body: ErrorBody =
+            serde_json::from_str(r#"{"code":"not_found.rocket","error":"not found"}"#).unwrap();
+        assert_eq!(body.code, ErrorCode::Unknown);
+        assert!(body.details.is_empty());
+    }
+}
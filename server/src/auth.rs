@@ -2,16 +2,35 @@ use argon2::{
     Argon2,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
-use axum::http::{HeaderMap, HeaderValue, header};
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderValue, header, request::Parts},
+};
 use base32::{Alphabet, encode as b32_encode};
 use chrono::{DateTime, Duration, Utc};
+use ekman_core::TokenScope;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::OnceLock;
 use totp_rs::{Algorithm, Secret, TOTP};
-use turso::Connection;
-
-use crate::{Error, Result, db};
+use tracing::Span;
+use turso::{Connection, Value};
+
+use crate::{
+    Error, Result, State,
+    db::{self, Conn, Db},
+    secrets::Keyring,
+    tls,
+};
 
 const COOKIE_NAME: &str = "ekman_session";
-const SESSION_DAYS: i64 = 30;
+const API_TOKEN_PREFIX: &str = "ekm_";
+/// Skip the sliding-expiry write when the session was used this recently.
+const SESSION_TOUCH_MINUTES: i64 = 5;
+const TOTP_STEP_SECS: i64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Lowercase letters and digits minus look-alikes (i, l, o, 0, 1).
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
 
 #[derive(Debug, Clone)]
 pub struct AuthUser {
@@ -20,36 +39,225 @@ pub struct AuthUser {
     pub password_hash: String,
     pub totp_secret: String,
     pub totp_enabled: bool,
+    /// Last TOTP time step accepted, codes at or before it are replays.
+    pub totp_last_step: Option<i64>,
+    /// Session the request came in on, when authenticated by cookie.
+    pub session_id: Option<i64>,
+    /// Scope of the API token the request came in on, when authenticated by bearer token.
+    pub token_scope: Option<TokenScope>,
+}
+
+/// Who opened a session, recorded for the session list.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn new(headers: &HeaderMap, addr: Option<SocketAddr>) -> Self {
+        Self {
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.chars().take(256).collect()),
+            ip: addr.map(|a| a.ip().to_string()),
+        }
+    }
+}
+
+/// Authenticates by API token when an `Authorization: Bearer` header is
+/// present, otherwise by session cookie. Read-only tokens can only make safe
+/// (GET/HEAD) requests.
+impl FromRequestParts<State> for AuthUser {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &State) -> Result<Self> {
+        let Db(mut conn) = Db::from_request_parts(parts, state).await?;
+
+        let user = match bearer_token(&parts.headers) {
+            Some(token) => user_from_api_token(&mut conn, &token).await?,
+            None => {
+                let lifetime = state.config.auth.session_lifetime();
+                user_from_session(&mut conn, &parts.headers, lifetime).await?
+            }
+        };
+        if user.token_scope == Some(TokenScope::Read) && !parts.method.is_safe() {
+            return Err(Error::Forbidden("token is read-only".into()));
+        }
+
+        Span::current().record("user_id", user.id);
+        Ok(user)
+    }
 }
 
-/// Extract user from session cookie.
-pub async fn user_from_headers(conn: &mut Connection, headers: &HeaderMap) -> Result<AuthUser> {
+/// Signed in with a session cookie. Credentials, sessions, tokens and the
+/// account itself are only managed this way, never through an API token.
+pub struct SessionUser(pub AuthUser);
+
+impl FromRequestParts<State> for SessionUser {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &State) -> Result<Self> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if user.session_id.is_none() {
+            return Err(Error::Forbidden("requires a signed-in session".into()));
+        }
+        Ok(Self(user))
+    }
+}
+
+/// A user listed in `admin.users`. Usernames are case-sensitive, so the
+/// match is exact.
+pub struct AdminUser(pub AuthUser);
+
+impl FromRequestParts<State> for AdminUser {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &State) -> Result<Self> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        let admins = &state.config.admin.users;
+        if !admins.contains(&user.username) {
+            return Err(Error::Forbidden("admin only".into()));
+        }
+        Ok(Self(user))
+    }
+}
+
+/// Whether the client reached us over HTTPS, which decides if cookies are
+/// marked `Secure`.
+pub struct SecureTransport(pub bool);
+
+impl FromRequestParts<State> for SecureTransport {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &State) -> Result<Self> {
+        Ok(Self(tls::is_secure(
+            state.config.server.tls(),
+            state.config.server.trust_proxy,
+            &parts.headers,
+        )))
+    }
+}
+
+/// Extract user from session cookie, extending it to `lifetime` from now.
+pub async fn user_from_session(
+    conn: &mut Conn,
+    headers: &HeaderMap,
+    lifetime: Duration,
+) -> Result<AuthUser> {
     let token = extract_token(headers).ok_or(Error::Unauthorized)?;
 
     let mut stmt = conn
-        .prepare("SELECT user_id, expires_at FROM sessions WHERE token = ?")
+        .prepare("SELECT id, user_id, expires_at, last_used_at FROM sessions WHERE token_hash = ?")
         .await?;
 
     let row = stmt
-        .query_row([token.as_str()])
+        .query_row([hash_token(&token)])
         .await
         .map_err(|_| Error::Unauthorized)?;
-    let user_id: i64 = row.get(0)?;
-    let expires: String = row.get(1)?;
-    let expires_at = db::parse_timestamp(&expires)?;
-
-    if expires_at < db::now() {
-        conn.execute("DELETE FROM sessions WHERE token = ?", [token.as_str()])
+    let session_id: i64 = row.get(0)?;
+    let user_id: i64 = row.get(1)?;
+    let expires_at = db::parse_timestamp(&row.get::<String>(2)?)?;
+    let last_used_at = row
+        .get::<Option<String>>(3)?
+        .map(|s| db::parse_timestamp(&s))
+        .transpose()?;
+
+    let now = db::now();
+    if expires_at < now {
+        conn.execute("DELETE FROM sessions WHERE id = ?", [session_id])
             .await?;
         return Err(Error::Unauthorized);
     }
 
-    user_by_id(conn, user_id).await
+    // Sliding expiry
+    if last_used_at.is_none_or(|t| now - t > Duration::minutes(SESSION_TOUCH_MINUTES)) {
+        conn.execute(
+            "UPDATE sessions SET last_used_at = ?, expires_at = ? WHERE id = ?",
+            (
+                db::timestamp(now),
+                db::timestamp(now + lifetime),
+                session_id,
+            ),
+        )
+        .await?;
+    }
+
+    let mut user = user_by_id(conn, user_id).await?;
+    user.session_id = Some(session_id);
+    Ok(user)
 }
 
-pub async fn user_by_username(conn: &mut Connection, username: &str) -> Result<AuthUser> {
+/// Extract user from a personal API token.
+pub async fn user_from_api_token(conn: &mut Conn, token: &str) -> Result<AuthUser> {
     let mut stmt = conn
-        .prepare("SELECT id, username, password_hash, totp_secret, totp_enabled FROM users WHERE username = ?")
+        .prepare(
+            "SELECT id, user_id, scope, expires_at, last_used_at FROM api_tokens
+             WHERE token_hash = ?",
+        )
+        .await?;
+
+    let row = stmt
+        .query_row([hash_token(token)])
+        .await
+        .map_err(|_| Error::Unauthorized)?;
+    let token_id: i64 = row.get(0)?;
+    let user_id: i64 = row.get(1)?;
+    let scope = parse_token_scope(&row.get::<String>(2)?)?;
+    let expires_at = row
+        .get::<Option<String>>(3)?
+        .map(|s| db::parse_timestamp(&s))
+        .transpose()?;
+    let last_used_at = row
+        .get::<Option<String>>(4)?
+        .map(|s| db::parse_timestamp(&s))
+        .transpose()?;
+
+    let now = db::now();
+    if expires_at.is_some_and(|t| t < now) {
+        return Err(Error::Unauthorized);
+    }
+
+    if last_used_at.is_none_or(|t| now - t > Duration::minutes(SESSION_TOUCH_MINUTES)) {
+        conn.execute(
+            "UPDATE api_tokens SET last_used_at = ? WHERE id = ?",
+            (db::timestamp(now), token_id),
+        )
+        .await?;
+    }
+
+    let mut user = user_by_id(conn, user_id).await?;
+    user.token_scope = Some(scope);
+    Ok(user)
+}
+
+pub fn token_scope_as_str(scope: TokenScope) -> &'static str {
+    match scope {
+        TokenScope::Read => "read",
+        TokenScope::ReadWrite => "read_write",
+    }
+}
+
+pub fn parse_token_scope(s: &str) -> Result<TokenScope> {
+    match s {
+        "read" => Ok(TokenScope::Read),
+        "read_write" => Ok(TokenScope::ReadWrite),
+        _ => Err(Error::Internal(format!("invalid token scope: {s}"))),
+    }
+}
+
+/// New API token secret; the prefix makes leaked tokens easy to grep for.
+pub fn generate_api_token() -> String {
+    format!("{API_TOKEN_PREFIX}{}", generate_token())
+}
+
+pub async fn user_by_username(conn: &mut Conn, username: &str) -> Result<AuthUser> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, username, password_hash, totp_secret, totp_enabled, totp_last_step
+             FROM users WHERE username = ?",
+        )
         .await?;
 
     let row = stmt
@@ -62,13 +270,17 @@ pub async fn user_by_username(conn: &mut Connection, username: &str) -> Result<A
         password_hash: row.get(2)?,
         totp_secret: row.get(3)?,
         totp_enabled: row.get::<bool>(4)?,
+        totp_last_step: row.get(5)?,
+        session_id: None,
+        token_scope: None,
     })
 }
 
-pub async fn user_by_id(conn: &mut Connection, id: i64) -> Result<AuthUser> {
+pub async fn user_by_id(conn: &mut Conn, id: i64) -> Result<AuthUser> {
     let mut stmt = conn
         .prepare(
-            "SELECT id, username, password_hash, totp_secret, totp_enabled FROM users WHERE id = ?",
+            "SELECT id, username, password_hash, totp_secret, totp_enabled, totp_last_step
+             FROM users WHERE id = ?",
         )
         .await?;
 
@@ -79,31 +291,70 @@ pub async fn user_by_id(conn: &mut Connection, id: i64) -> Result<AuthUser> {
         password_hash: row.get(2)?,
         totp_secret: row.get(3)?,
         totp_enabled: row.get::<bool>(4)?,
+        totp_last_step: row.get(5)?,
+        session_id: None,
+        token_scope: None,
     })
 }
 
 pub async fn create_session(
-    conn: &mut Connection,
+    conn: &mut Conn,
     user_id: i64,
+    client: &ClientInfo,
+    lifetime: Duration,
 ) -> Result<(String, DateTime<Utc>)> {
     let token = generate_token();
-    let expires_at = db::now() + Duration::days(SESSION_DAYS);
+    let now = db::now();
+    let expires_at = now + lifetime;
 
     conn.execute(
-        "INSERT INTO sessions (user_id, token, expires_at) VALUES (?, ?, ?)",
-        (user_id, token.as_str(), db::timestamp(expires_at)),
+        "INSERT INTO sessions (user_id, token_hash, expires_at, created_at, last_used_at, user_agent, ip)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        vec![
+            Value::from(user_id),
+            hash_token(&token).into(),
+            db::timestamp(expires_at).into(),
+            db::timestamp(now).into(),
+            db::timestamp(now).into(),
+            client.user_agent.clone().into(),
+            client.ip.clone().into(),
+        ],
     )
     .await?;
 
     Ok((token, expires_at))
 }
 
-pub async fn delete_session(conn: &mut Connection, token: &str) -> Result<()> {
-    conn.execute("DELETE FROM sessions WHERE token = ?", [token])
-        .await?;
+pub async fn delete_session(conn: &mut Conn, token: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM sessions WHERE token_hash = ?",
+        [hash_token(token)],
+    )
+    .await?;
     Ok(())
 }
 
+/// Drop every session of the user except the one holding `keep`.
+pub async fn delete_other_sessions(conn: &mut Conn, user_id: i64, keep: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM sessions WHERE user_id = ? AND token_hash != ?",
+        (user_id, hash_token(keep)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Remove sessions past their expiry, returning how many were dropped.
+pub async fn purge_expired_sessions(conn: &Connection) -> Result<u64> {
+    let purged = conn
+        .execute(
+            "DELETE FROM sessions WHERE expires_at < ?",
+            [db::timestamp(db::now())],
+        )
+        .await?;
+    Ok(purged)
+}
+
 pub fn hash_password(password: &str) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
     Argon2::default()
@@ -121,31 +372,174 @@ pub fn verify_password(password: &str, hash: &str) -> Result<()> {
         .map_err(|_| Error::Unauthorized)
 }
 
-pub fn verify_totp(secret: &str, code: &str) -> Result<()> {
-    let bytes = Secret::Encoded(secret.into())
+/// Spend the same Argon2 work as a real check, so unknown usernames don't
+/// answer measurably faster than wrong passwords.
+pub fn verify_password_dummy(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let hash = DUMMY_HASH.get_or_init(|| hash_password("dummy").unwrap_or_default());
+    let _ = verify_password(password, hash);
+}
+
+/// Check a code against a stored secret, decrypting it first if sealed.
+/// Codes up to `skew` steps either side of `now` are accepted, except steps
+/// at or before `last_step`. Returns the matching step.
+pub fn verify_totp(
+    keys: &Keyring,
+    secret: &str,
+    code: &str,
+    skew: u8,
+    last_step: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<i64> {
+    let bytes = Secret::Encoded(keys.open(secret)?)
         .to_bytes()
         .map_err(|e| Error::Internal(format!("invalid totp secret: {e}")))?;
 
+    // Skew is applied here, one step at a time, so the match can be recorded
     let totp = TOTP::new(
         Algorithm::SHA1,
         6,
-        1,
-        30,
+        0,
+        TOTP_STEP_SECS as u64,
         bytes,
         Some("ekman".into()),
         "ekman".into(),
     )
     .map_err(|e| Error::Internal(format!("totp error: {e}")))?;
 
-    let valid = totp
-        .check_current(code)
-        .map_err(|e| Error::Internal(format!("totp check error: {e}")))?;
+    let current = now.timestamp().div_euclid(TOTP_STEP_SECS);
+    let skew = i64::from(skew);
+
+    (current - skew..=current + skew)
+        .filter(|&step| step >= 0 && last_step.is_none_or(|last| step > last))
+        .find(|&step| totp.check(code, (step * TOTP_STEP_SECS) as u64))
+        .ok_or(Error::TotpInvalid)
+}
+
+/// Verify a user's code and record its step so it can't be used again.
+pub async fn accept_totp(
+    conn: &mut Conn,
+    state: &State,
+    user: &AuthUser,
+    code: &str,
+) -> Result<()> {
+    accept_totp_at(conn, state, user, code, db::now()).await
+}
 
-    if valid {
-        Ok(())
-    } else {
-        Err(Error::Unauthorized)
+async fn accept_totp_at(
+    conn: &mut Conn,
+    state: &State,
+    user: &AuthUser,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let step = verify_totp(
+        &state.keys,
+        &user.totp_secret,
+        code,
+        state.config.auth.totp_skew,
+        user.totp_last_step,
+        now,
+    )?;
+
+    // Conditional so two concurrent requests can't both spend the same code
+    let updated = conn
+        .execute(
+            "UPDATE users SET totp_last_step = ?
+             WHERE id = ? AND (totp_last_step IS NULL OR totp_last_step < ?)",
+            (step, user.id, step),
+        )
+        .await?;
+
+    if updated == 0 {
+        return Err(Error::TotpInvalid);
+    }
+    Ok(())
+}
+
+/// TOTP codes are six digits; anything else is treated as a recovery code.
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == 6 && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Replace the user's recovery codes with a fresh set, returning them in
+/// plaintext. Only Argon2 hashes are stored.
+pub async fn replace_recovery_codes(conn: &mut Conn, user_id: i64) -> Result<Vec<String>> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes = codes
+        .iter()
+        .map(|code| hash_password(&normalize_recovery_code(code)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let tx = conn.transaction().await?;
+    tx.execute("DELETE FROM recovery_codes WHERE user_id = ?", [user_id])
+        .await?;
+    for hash in hashes {
+        tx.execute(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)",
+            (user_id, hash),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(codes)
+}
+
+/// Spend one of the user's unused recovery codes.
+pub async fn use_recovery_code(conn: &mut Conn, user_id: i64, code: &str) -> Result<()> {
+    let code = normalize_recovery_code(code);
+
+    let mut rows = conn
+        .query(
+            "SELECT id, code_hash FROM recovery_codes WHERE user_id = ? AND used_at IS NULL",
+            [user_id],
+        )
+        .await?;
+    let mut candidates = Vec::new();
+    while let Some(row) = rows.next().await? {
+        candidates.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+
+    let id = candidates
+        .into_iter()
+        .find(|(_, hash)| verify_password(&code, hash).is_ok())
+        .map(|(id, _)| id)
+        .ok_or(Error::TotpInvalid)?;
+
+    let updated = conn
+        .execute(
+            "UPDATE recovery_codes SET used_at = ? WHERE id = ? AND used_at IS NULL",
+            (db::timestamp(db::now()), id),
+        )
+        .await?;
+    if updated == 0 {
+        return Err(Error::TotpInvalid);
     }
+
+    tracing::warn!(user_id, "recovery code used");
+    Ok(())
+}
+
+fn generate_recovery_code() -> String {
+    use argon2::password_hash::rand_core::RngCore;
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    // 256 % 31 leaves a tiny bias; negligible for 10 characters
+    let chars: String = bytes
+        .iter()
+        .map(|b| RECOVERY_ALPHABET[*b as usize % RECOVERY_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
 }
 
 pub fn generate_totp_secret(username: &str) -> Result<(String, String)> {
@@ -169,21 +563,41 @@ pub fn generate_totp_secret(username: &str) -> Result<(String, String)> {
     Ok((secret, totp.get_url()))
 }
 
-pub fn session_cookie(token: &str, expires_at: DateTime<Utc>) -> HeaderValue {
+pub fn session_cookie(token: &str, expires_at: DateTime<Utc>, secure: bool) -> HeaderValue {
     let max_age = expires_at
         .signed_duration_since(db::now())
         .num_seconds()
         .max(0);
-    let value = format!("{COOKIE_NAME}={token}; HttpOnly; SameSite=Lax; Path=/; Max-Age={max_age}");
+    let value = format!(
+        "{COOKIE_NAME}={token}; HttpOnly; SameSite=Lax; Path=/; Max-Age={max_age}{}",
+        secure_attr(secure)
+    );
     HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static(""))
 }
 
-pub fn clear_cookie() -> HeaderValue {
-    let value = format!("{COOKIE_NAME}=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax");
+/// Re-issue the request's session cookie with a fresh sliding expiry.
+pub fn refresh_cookie(
+    headers: &HeaderMap,
+    lifetime: Duration,
+    secure: bool,
+) -> Option<HeaderValue> {
+    let token = extract_token(headers)?;
+    Some(session_cookie(&token, db::now() + lifetime, secure))
+}
+
+pub fn clear_cookie(secure: bool) -> HeaderValue {
+    let value = format!(
+        "{COOKIE_NAME}=; Max-Age=0; Path=/; HttpOnly; SameSite=Lax{}",
+        secure_attr(secure)
+    );
     HeaderValue::from_str(&value).unwrap_or_else(|_| HeaderValue::from_static(""))
 }
 
-fn extract_token(headers: &HeaderMap) -> Option<String> {
+fn secure_attr(secure: bool) -> &'static str {
+    if secure { "; Secure" } else { "" }
+}
+
+pub fn extract_token(headers: &HeaderMap) -> Option<String> {
     let raw = headers.get(header::COOKIE)?.to_str().ok()?;
     for part in raw.split(';') {
         let mut iter = part.trim().splitn(2, '=');
@@ -196,6 +610,14 @@ fn extract_token(headers: &HeaderMap) -> Option<String> {
     None
 }
 
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let raw = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = raw.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_owned())
+}
+
 fn generate_token() -> String {
     use argon2::password_hash::rand_core::RngCore;
     let mut bytes = [0u8; 32];
@@ -203,13 +625,334 @@ fn generate_token() -> String {
     hex::encode(bytes)
 }
 
-mod hex {
-    pub fn encode(bytes: [u8; 32]) -> String {
-        let mut s = String::with_capacity(64);
+/// Only this digest is stored, so a database dump can't be replayed as a cookie.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub(crate) mod hex {
+    pub fn encode(bytes: impl AsRef<[u8]>) -> String {
+        let bytes = bytes.as_ref();
+        let mut s = String::with_capacity(bytes.len() * 2);
         for b in bytes {
             use std::fmt::Write;
             let _ = write!(s, "{b:02x}");
         }
         s
     }
+
+    pub fn decode(s: &str) -> Option<Vec<u8>> {
+        if !s.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIFETIME: Duration = Duration::days(30);
+
+    fn cookie(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("{COOKIE_NAME}={token}")).unwrap(),
+        );
+        headers
+    }
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    fn code_at(secs: i64) -> String {
+        let bytes = Secret::Encoded(SECRET.into()).to_bytes().unwrap();
+        TOTP::new_unchecked(Algorithm::SHA1, 6, 0, 30, bytes, None, String::new())
+            .generate(secs as u64)
+    }
+
+    async fn insert_user(conn: &Conn) -> i64 {
+        conn.execute(
+            "INSERT INTO users (username, password_hash, totp_secret) VALUES ('u', 'x', ?)",
+            [SECRET],
+        )
+        .await
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[tokio::test]
+    async fn test_db_dump_cannot_authenticate() {
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        let user_id = insert_user(&conn).await;
+
+        let (token, _) = create_session(&mut conn, user_id, &ClientInfo::default(), LIFETIME)
+            .await
+            .unwrap();
+
+        // Everything an attacker could read from the sessions table
+        let mut rows = conn.query("SELECT * FROM sessions", ()).await.unwrap();
+        let row = rows.next().await.unwrap().unwrap();
+        let dumped: Vec<String> = (0..row.column_count())
+            .filter_map(|i| match row.get_value(i) {
+                Ok(Value::Text(s)) => Some(s),
+                _ => None,
+            })
+            .collect();
+        drop(rows);
+
+        assert!(!dumped.contains(&token));
+        for value in &dumped {
+            assert!(matches!(
+                user_from_session(&mut conn, &cookie(value), LIFETIME).await,
+                Err(Error::Unauthorized)
+            ));
+        }
+
+        let user = user_from_session(&mut conn, &cookie(&token), LIFETIME)
+            .await
+            .unwrap();
+        assert_eq!(user.id, user_id);
+    }
+
+    #[tokio::test]
+    async fn test_logout_removes_hashed_session() {
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        let user_id = insert_user(&conn).await;
+
+        let (token, _) = create_session(&mut conn, user_id, &ClientInfo::default(), LIFETIME)
+            .await
+            .unwrap();
+        delete_session(&mut conn, &token).await.unwrap();
+
+        assert!(matches!(
+            user_from_session(&mut conn, &cookie(&token), LIFETIME).await,
+            Err(Error::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_totp_skew_window() {
+        let keys = Keyring::default();
+        let now = at(1_700_000_010);
+        let step = 1_700_000_010 / 30;
+
+        let verify = |code: &str, skew| verify_totp(&keys, SECRET, code, skew, None, now);
+
+        assert_eq!(verify(&code_at(1_700_000_010), 0).unwrap(), step);
+        assert_eq!(verify(&code_at(1_700_000_010 - 30), 1).unwrap(), step - 1);
+        assert_eq!(verify(&code_at(1_700_000_010 + 30), 1).unwrap(), step + 1);
+        assert!(verify(&code_at(1_700_000_010 - 30), 0).is_err());
+        assert!(verify(&code_at(1_700_000_010 - 60), 1).is_err());
+        assert_eq!(verify(&code_at(1_700_000_010 - 60), 2).unwrap(), step - 2);
+    }
+
+    #[test]
+    fn test_totp_rejects_used_steps() {
+        let keys = Keyring::default();
+        let now = at(1_700_000_010);
+        let step = 1_700_000_010 / 30;
+        let code = code_at(1_700_000_010);
+
+        assert!(verify_totp(&keys, SECRET, &code, 1, Some(step - 1), now).is_ok());
+        assert!(verify_totp(&keys, SECRET, &code, 1, Some(step), now).is_err());
+        // An older code can't be slipped in after a newer one was used
+        let older = code_at(1_700_000_010 - 30);
+        assert!(verify_totp(&keys, SECRET, &older, 1, Some(step), now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_accept_totp_blocks_replay() {
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        let user_id = insert_user(&conn).await;
+        let state = State::test(db.clone());
+
+        let now = at(1_700_000_010);
+        let code = code_at(1_700_000_010);
+
+        let user = user_by_id(&mut conn, user_id).await.unwrap();
+        accept_totp_at(&mut conn, &state, &user, &code, now)
+            .await
+            .unwrap();
+
+        // Same code, a few seconds later, within the same step
+        let user = user_by_id(&mut conn, user_id).await.unwrap();
+        assert!(matches!(
+            accept_totp_at(&mut conn, &state, &user, &code, at(1_700_000_020)).await,
+            Err(Error::TotpInvalid)
+        ));
+
+        // A stale user row (concurrent request) is caught by the update guard
+        let stale = AuthUser {
+            totp_last_step: None,
+            ..user.clone()
+        };
+        assert!(matches!(
+            accept_totp_at(&mut conn, &state, &stale, &code, now).await,
+            Err(Error::TotpInvalid)
+        ));
+
+        let next = code_at(1_700_000_040);
+        accept_totp_at(&mut conn, &state, &user, &next, at(1_700_000_040))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recovery_codes_are_single_use() {
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        let user_id = insert_user(&conn).await;
+
+        let codes = replace_recovery_codes(&mut conn, user_id).await.unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|c| !is_totp_code(c)));
+
+        // Formatting is forgiving
+        let typed = codes[3].to_uppercase().replace('-', " ");
+        use_recovery_code(&mut conn, user_id, &typed).await.unwrap();
+        assert!(
+            use_recovery_code(&mut conn, user_id, &codes[3])
+                .await
+                .is_err()
+        );
+        assert!(
+            use_recovery_code(&mut conn, user_id, "aaaaa-aaaaa")
+                .await
+                .is_err()
+        );
+
+        // Regenerating invalidates the old set
+        replace_recovery_codes(&mut conn, user_id).await.unwrap();
+        assert!(
+            use_recovery_code(&mut conn, user_id, &codes[0])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_token_scopes() {
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        conn.execute(
+            "INSERT INTO users (username, password_hash, totp_secret) VALUES ('u', ?, ?)",
+            (hash_password("p").unwrap(), SECRET),
+        )
+        .await
+        .unwrap();
+        let (session, _) = create_session(&mut conn, 1, &ClientInfo::default(), LIFETIME)
+            .await
+            .unwrap();
+        let app = crate::router(State::test(db));
+
+        let call = |method: &str, uri: &str, auth: (header::HeaderName, String), body: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(auth.0, auth.1)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_owned()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let by_cookie = || (header::COOKIE, format!("{COOKIE_NAME}={session}"));
+        let bearer = |token: &str| (header::AUTHORIZATION, format!("Bearer {token}"));
+
+        // Expiry past what a timestamp can hold is rejected up front
+        let too_long = serde_json::json!({
+            "name": "forever",
+            "scope": "read",
+            "expires_in_days": 4_000_000_000u32,
+            "password": "p",
+            "totp": "000000",
+        });
+        let response = call(
+            "POST",
+            "/api/v1/auth/tokens",
+            by_cookie(),
+            &too_long.to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 422);
+
+        let body = serde_json::json!({
+            "name": "export script",
+            "scope": "read",
+            "password": "p",
+            "totp": code_at(db::now().timestamp()),
+        });
+        let response = call(
+            "POST",
+            "/api/v1/auth/tokens",
+            by_cookie(),
+            &body.to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), 200);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: ekman_core::CreatedApiToken = serde_json::from_slice(&bytes).unwrap();
+        let token = created.token;
+        assert!(token.starts_with(API_TOKEN_PREFIX));
+
+        let status = |r: std::result::Result<axum::response::Response, _>| r.unwrap().status();
+        assert_eq!(
+            status(call("GET", "/api/v1/exercises", bearer(&token), "").await),
+            200
+        );
+        assert_eq!(
+            status(
+                call(
+                    "POST",
+                    "/api/v1/exercises",
+                    bearer(&token),
+                    r#"{"name":"x"}"#
+                )
+                .await
+            ),
+            403
+        );
+        // Tokens can't manage credentials, not even their own
+        assert_eq!(
+            status(call("GET", "/api/v1/auth/tokens", bearer(&token), "").await),
+            403
+        );
+        assert_eq!(
+            status(call("GET", "/api/v1/exercises", bearer("ekm_nope"), "").await),
+            401
+        );
+
+        conn.execute(
+            "UPDATE api_tokens SET expires_at = ?",
+            [db::timestamp(db::now() - Duration::minutes(1))],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            status(call("GET", "/api/v1/exercises", bearer(&token), "").await),
+            401
+        );
+
+        let uri = format!("/api/v1/auth/tokens/{}", created.info.id);
+        assert_eq!(status(call("DELETE", &uri, by_cookie(), "").await), 204);
+        assert!(user_from_api_token(&mut conn, &token).await.is_err());
+    }
 }
@@ -0,0 +1,466 @@
+//! Database snapshots.
+//!
+//! A snapshot is a logical copy of every table taken inside one read
+//! transaction, so it is consistent while requests keep writing. Snapshots
+//! are written next to their final name and renamed into place, so the
+//! backup directory never holds a partial file.
+//!
+//! With `backup.dir` set, a snapshot is taken whenever the newest one is
+//! older than `backup.interval_hours`, then old ones are pruned down to the
+//! newest per day for `backup.keep_daily` days and per ISO week for
+//! `backup.keep_weekly` weeks.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use ekman_core::Backup;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use turso::{Builder, Database};
+
+use crate::{Error, Result, config::BackupConfig, db, shutdown::Shutdown};
+
+const FILE_PREFIX: &str = "ekman-";
+const FILE_SUFFIX: &str = ".db";
+const STAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+/// How often the scheduler checks whether a snapshot is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Files SQLite keeps next to a database.
+const SIDE_FILES: &[&str] = &["-wal", "-shm"];
+
+pub struct Backups {
+    dir: PathBuf,
+    config: BackupConfig,
+    /// Scheduled and on-demand snapshots never overlap.
+    lock: Mutex<()>,
+}
+
+impl Backups {
+    /// `None` when no backup directory is configured.
+    pub fn from_config(config: &BackupConfig) -> Option<Self> {
+        Some(Self {
+            dir: config.dir.clone()?,
+            config: config.clone(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Snapshot `db` into the backup directory and prune old snapshots.
+    pub async fn create(&self, db: &Database) -> Result<Backup> {
+        let _guard = self.lock.lock().await;
+        std::fs::create_dir_all(&self.dir)?;
+
+        let now = db::now();
+        let created_at = DateTime::from_timestamp(now.timestamp(), 0).unwrap_or(now);
+        let file = format!(
+            "{FILE_PREFIX}{}{FILE_SUFFIX}",
+            created_at.format(STAMP_FORMAT)
+        );
+        let path = self.dir.join(&file);
+        if path.exists() {
+            return Err(Error::TooManyRequests(1));
+        }
+
+        snapshot(db, &path).await?;
+        let size_bytes = std::fs::metadata(&path)?.len();
+
+        let pruned = self.prune()?;
+        if pruned > 0 {
+            info!("pruned {pruned} old backups");
+        }
+
+        Ok(Backup {
+            file,
+            size_bytes,
+            created_at,
+        })
+    }
+
+    /// Snapshots in the backup directory by the time in their name.
+    fn list(&self) -> Result<Vec<(DateTime<Utc>, PathBuf)>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let stamp = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(FILE_PREFIX))
+                .and_then(|n| n.strip_suffix(FILE_SUFFIX))
+                .and_then(|s| NaiveDateTime::parse_from_str(s, STAMP_FORMAT).ok());
+            if let Some(stamp) = stamp {
+                snapshots.push((stamp.and_utc(), path));
+            }
+        }
+        Ok(snapshots)
+    }
+
+    fn prune(&self) -> Result<usize> {
+        let snapshots = self.list()?;
+        let taken: Vec<_> = snapshots.iter().map(|(t, _)| *t).collect();
+        let expired = expired(taken, self.config.keep_daily, self.config.keep_weekly);
+
+        for (_, path) in snapshots.iter().filter(|(t, _)| expired.contains(t)) {
+            remove_db_files(path)?;
+        }
+        Ok(expired.len())
+    }
+
+    fn due(&self) -> Result<bool> {
+        let interval = chrono::Duration::hours(self.config.interval_hours.into());
+        let newest = self.list()?.into_iter().map(|(t, _)| t).max();
+        Ok(newest.is_none_or(|t| db::now() - t >= interval))
+    }
+
+    /// Take scheduled snapshots until `shutdown` fires. A snapshot already
+    /// being written is finished first.
+    pub async fn run(&self, db: Database, shutdown: Shutdown) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        let stop = shutdown.requested();
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = &mut stop => return,
+                _ = interval.tick() => {}
+            }
+            match self.due() {
+                Ok(false) => {}
+                Ok(true) => match self.create(&db).await {
+                    Ok(backup) => info!(file = %backup.file, "scheduled backup written"),
+                    Err(e) => warn!("scheduled backup failed: {e}"),
+                },
+                Err(e) => warn!("failed to read backup directory: {e}"),
+            }
+        }
+    }
+}
+
+/// Snapshots to delete: all but the newest of each of the latest
+/// `keep_daily` days and of each of the latest `keep_weekly` ISO weeks.
+fn expired(
+    mut taken: Vec<DateTime<Utc>>,
+    keep_daily: usize,
+    keep_weekly: usize,
+) -> Vec<DateTime<Utc>> {
+    taken.sort_unstable_by(|a, b| b.cmp(a));
+
+    let (mut days, mut weeks) = (Vec::new(), Vec::new());
+    let mut keep = HashSet::new();
+    for &t in &taken {
+        let day = t.date_naive();
+        if days.len() < keep_daily && !days.contains(&day) {
+            days.push(day);
+            keep.insert(t);
+        }
+        let week = t.iso_week();
+        if weeks.len() < keep_weekly && !weeks.contains(&week) {
+            weeks.push(week);
+            keep.insert(t);
+        }
+    }
+
+    taken.retain(|t| !keep.contains(t));
+    taken
+}
+
+/// Write a consistent copy of `db` to `dest`.
+pub async fn snapshot(db: &Database, dest: &Path) -> Result<()> {
+    let staged = with_suffix(dest, ".tmp");
+    remove_db_files(&staged)?;
+
+    if let Err(e) = copy_into(db, &staged).await {
+        let _ = remove_db_files(&staged);
+        return Err(e);
+    }
+    std::fs::rename(&staged, dest)?;
+    Ok(())
+}
+
+async fn copy_into(db: &Database, dest: &Path) -> Result<()> {
+    let target = db::init(path_str(dest)?).await?;
+    let out = target.connect()?;
+    let source = db.connect()?;
+
+    // One read transaction so every table comes from the same moment
+    let read = source.unchecked_transaction().await?;
+    let write = out.unchecked_transaction().await?;
+    for table in db::schema_tables() {
+        let columns = db::columns(&read, table).await?;
+        let list = columns.join(", ");
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let insert = format!("INSERT INTO {table} ({list}) VALUES ({placeholders})");
+
+        let mut rows = read
+            .query(&format!("SELECT {list} FROM {table}"), ())
+            .await?;
+        while let Some(row) = rows.next().await? {
+            let values = (0..columns.len())
+                .map(|i| row.get_value(i))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            write.execute(&insert, values).await?;
+        }
+    }
+    write.commit().await?;
+    read.commit().await?;
+
+    db::checkpoint(&target).await?;
+    drop((out, target));
+    remove_empty_side_files(dest)
+}
+
+/// Check that `path` is an intact database with the current schema.
+pub async fn verify(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Err(Error::BadRequest(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    }
+
+    let db = Builder::new_local(path_str(path)?).build().await?;
+    let conn = db.connect()?;
+    let mut rows = conn.query("PRAGMA integrity_check", ()).await?;
+    let mut problems = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let line = row.get::<String>(0)?;
+        if line != "ok" {
+            problems.push(line);
+        }
+    }
+    if !problems.is_empty() {
+        return Err(Error::Internal(format!(
+            "{} failed the integrity check: {}",
+            path.display(),
+            problems.join("; ")
+        )));
+    }
+    if !db::schema_ready(&conn).await? {
+        return Err(Error::Internal(format!(
+            "{} is missing tables or columns",
+            path.display()
+        )));
+    }
+
+    drop((conn, db));
+    remove_empty_side_files(path)
+}
+
+/// Replace the database at `db_path` with `snapshot`, after verifying both
+/// the snapshot and the staged copy. The replaced database is moved aside,
+/// and its new path is returned. Run with the server stopped.
+pub async fn restore(snapshot: &Path, db_path: &Path) -> Result<Option<PathBuf>> {
+    verify(snapshot).await?;
+
+    let staged = with_suffix(db_path, ".restore");
+    remove_db_files(&staged)?;
+    std::fs::copy(snapshot, &staged)?;
+    if let Err(e) = verify(&staged).await {
+        let _ = remove_db_files(&staged);
+        return Err(e);
+    }
+
+    let previous = if db_path.exists() {
+        let stamp = db::now().format(STAMP_FORMAT);
+        let aside = with_suffix(db_path, &format!(".pre-restore-{stamp}"));
+        rename_db_files(db_path, &aside)?;
+        Some(aside)
+    } else {
+        None
+    };
+    std::fs::rename(&staged, db_path)?;
+    Ok(previous)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| Error::Internal(format!("non-utf8 path {}", path.display())))
+}
+
+fn rename_db_files(from: &Path, to: &Path) -> Result<()> {
+    std::fs::rename(from, to)?;
+    for side in SIDE_FILES {
+        let file = with_suffix(from, side);
+        if file.exists() {
+            std::fs::rename(file, with_suffix(to, side))?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_db_files(path: &Path) -> Result<()> {
+    for file in
+        std::iter::once(path.to_owned()).chain(SIDE_FILES.iter().map(|s| with_suffix(path, s)))
+    {
+        match std::fs::remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Drop the empty WAL a checkpointed or read-only open leaves behind, so
+/// the database is a single file.
+fn remove_empty_side_files(path: &Path) -> Result<()> {
+    for side in SIDE_FILES {
+        let file = with_suffix(path, side);
+        if std::fs::metadata(&file).is_ok_and(|m| m.len() == 0) {
+            std::fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::Arc;
+
+    async fn usernames(path: &Path) -> Vec<String> {
+        let db = Builder::new_local(path.to_str().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let conn = db.connect().unwrap();
+        let mut rows = conn
+            .query("SELECT username FROM users ORDER BY id", ())
+            .await
+            .unwrap();
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            names.push(row.get::<String>(0).unwrap());
+        }
+        names
+    }
+
+    fn backups_in(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    async fn add_user(db: &Database, name: &str) {
+        db.connect()
+            .unwrap()
+            .execute(
+                "INSERT INTO users (username, password_hash, totp_secret) VALUES (?, 'h', 's')",
+                [name],
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let dir = crate::test_dir("backup");
+        let live = db::test_db().await;
+        add_user(&live, "alice").await;
+
+        let backups = Backups::from_config(&BackupConfig {
+            dir: Some(dir.join("backups")),
+            ..BackupConfig::default()
+        })
+        .unwrap();
+        assert!(backups.due().unwrap());
+        let backup = backups.create(&live).await.unwrap();
+        assert!(!backups.due().unwrap());
+        let snapshot = dir.join("backups").join(&backup.file);
+        verify(&snapshot).await.unwrap();
+
+        // Restore over a database that has since diverged
+        let db_path = dir.join("ekman.db");
+        let current = db::init(db_path.to_str().unwrap()).await.unwrap();
+        add_user(&current, "mallory").await;
+        db::checkpoint(&current).await.unwrap();
+        drop(current);
+
+        let previous = restore(&snapshot, &db_path).await.unwrap().unwrap();
+        assert_eq!(usernames(&db_path).await, ["alice"]);
+        assert_eq!(usernames(&previous).await, ["mallory"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_bad_snapshot() {
+        let dir = crate::test_dir("bad-backup");
+        let garbage = dir.join("garbage.db");
+        std::fs::write(&garbage, vec![0x42; 8192]).unwrap();
+        let db_path = dir.join("ekman.db");
+        std::fs::write(&db_path, b"untouched").unwrap();
+
+        assert!(restore(&garbage, &db_path).await.is_err());
+        assert!(restore(&dir.join("missing.db"), &db_path).await.is_err());
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"untouched");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admin_endpoint() {
+        use crate::{State, auth};
+        use axum::{body::Body, http::Request};
+        use tower::ServiceExt;
+
+        let dir = crate::test_dir("admin-backup");
+        let db = db::test_db().await;
+        add_user(&db, "alice").await;
+        add_user(&db, "bob").await;
+        add_user(&db, "Alice").await;
+
+        let mut state = State::test(db.clone());
+        let mut config = crate::config::Config::default();
+        config.admin.users = vec!["alice".into()];
+        config.backup.dir = Some(dir.clone());
+        state.backups = Backups::from_config(&config.backup).map(Arc::new);
+        state.config = Arc::new(config);
+        let app = crate::router(state);
+
+        let mut conn = db::Conn::from(db.connect().unwrap());
+        let post = |token: &str| {
+            Request::post("/api/v1/admin/backup")
+                .header("cookie", format!("ekman_session={token}"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        // A case variant of an admin's name is a different user
+        for (user_id, expected) in [(2, 403), (3, 403), (1, 200)] {
+            let (token, _) = auth::create_session(
+                &mut conn,
+                user_id,
+                &auth::ClientInfo::default(),
+                chrono::Duration::days(1),
+            )
+            .await
+            .unwrap();
+            let response = app.clone().oneshot(post(&token)).await.unwrap();
+            assert_eq!(response.status().as_u16(), expected);
+        }
+        assert_eq!(backups_in(&dir), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retention() {
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2026, 3, d, h, 0, 0).unwrap();
+        // Two a day for most of March; the 2nd is a Monday
+        let taken: Vec<_> = (1..=28).flat_map(|d| [at(d, 3), at(d, 15)]).collect();
+
+        let expired = expired(taken.clone(), 3, 2);
+        let kept: Vec<_> = taken.iter().filter(|t| !expired.contains(t)).collect();
+        // Newest of the last three days, plus the newest of the previous week
+        assert_eq!(kept, [&at(22, 15), &at(26, 15), &at(27, 15), &at(28, 15)]);
+    }
+}
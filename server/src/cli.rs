@@ -0,0 +1,69 @@
+//! Admin subcommands run instead of the server.
+
+use std::path::Path;
+use tracing::info;
+
+use crate::{Error, Result, backup, config::Config, db, secrets};
+
+const USAGE: &str = "usage: ekman-server [serve]
+       ekman-server config print
+       ekman-server restore <snapshot>
+       ekman-server secrets generate-key
+       ekman-server secrets rotate";
+
+pub async fn run(args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] | ["serve"] => crate::run(Config::load()?).await,
+        ["config", "print"] => {
+            print!("{}", Config::load()?.redacted_toml()?);
+            Ok(())
+        }
+        ["restore", snapshot] => restore(Path::new(snapshot)).await,
+        ["secrets", "generate-key"] => {
+            println!("{}", secrets::generate_key());
+            Ok(())
+        }
+        ["secrets", "rotate"] => rotate_secrets().await,
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Re-wrap every TOTP secret under `secrets.totp_key`, reading older values
+/// with the keys in `secrets.totp_key_previous`.
+async fn rotate_secrets() -> Result<()> {
+    let config = Config::load()?;
+    crate::init_tracing(config.log.format);
+
+    let keys = secrets::Keyring::from_config(&config.secrets)?;
+    if !keys.is_enabled() {
+        return Err(Error::BadRequest("secrets.totp_key is not set".into()));
+    }
+
+    let db = db::init(&config.database.path).await?;
+    let updated = secrets::reseal_totp_secrets(&db.connect()?, &keys, true).await?;
+    info!("re-wrapped {updated} totp secrets");
+    Ok(())
+}
+
+/// Swap `database.path` for a verified snapshot. The server must be stopped.
+async fn restore(snapshot: &Path) -> Result<()> {
+    let config = Config::load()?;
+    crate::init_tracing(config.log.format);
+
+    let db_path = Path::new(&config.database.path);
+    match backup::restore(snapshot, db_path).await? {
+        Some(previous) => info!(
+            "restored {} to {}, previous database kept at {}",
+            snapshot.display(),
+            db_path.display(),
+            previous.display()
+        ),
+        None => info!("restored {} to {}", snapshot.display(), db_path.display()),
+    }
+    Ok(())
+}
@@ -0,0 +1,513 @@
+//! Server configuration.
+//!
+//! Settings come from a TOML file (`EKMAN_CONFIG`, or `ekman.toml` in the
+//! working directory when present), then `EKMAN__SECTION__KEY` environment
+//! variables override single values. List values are comma separated in the
+//! environment. Every problem is reported at once at startup.
+
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+const PATH_VAR: &str = "EKMAN_CONFIG";
+const DEFAULT_PATH: &str = "ekman.toml";
+const ENV_PREFIX: &str = "EKMAN__";
+const REDACTED: &str = "<redacted>";
+
+const MAX_TOTP_SKEW: u8 = 10;
+const MAX_SESSION_DAYS: u32 = 365;
+const MAX_SHUTDOWN_TIMEOUT_SECS: u64 = 600;
+const MAX_GRAPH_POINTS: usize = 1000;
+const MAX_BACKUP_INTERVAL_HOURS: u32 = 7 * 24;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub webauthn: WebauthnConfig,
+    pub secrets: SecretsConfig,
+    pub graphs: GraphsConfig,
+    pub log: LogConfig,
+    pub backup: BackupConfig,
+    pub admin: AdminConfig,
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// Only needed when the web app is hosted somewhere other than this server.
+    pub cors_origins: Vec<String>,
+    /// Believe `X-Forwarded-Proto` and `X-Forwarded-For` from a reverse proxy
+    /// in front of us.
+    pub trust_proxy: bool,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Port for the HTTP listener that redirects to HTTPS.
+    pub tls_redirect_port: Option<u16>,
+    /// How long in-flight requests may finish after SIGTERM/SIGINT.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            cors_origins: Vec::new(),
+            trust_proxy: false,
+            tls_cert: None,
+            tls_key: None,
+            tls_redirect_port: None,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn tls(&self) -> bool {
+        self.tls_cert.is_some()
+    }
+
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: "ekman.db".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// TOTP steps accepted either side of the current one.
+    pub totp_skew: u8,
+    /// Sessions expire after this many days without use.
+    pub session_days: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            totp_skew: 1,
+            session_days: 30,
+        }
+    }
+}
+
+impl AuthConfig {
+    pub fn session_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.session_days.into())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebauthnConfig {
+    pub rp_id: String,
+    /// Defaults to the web app served by this server.
+    pub origin: Option<String>,
+}
+
+impl Default for WebauthnConfig {
+    fn default() -> Self {
+        Self {
+            rp_id: "localhost".into(),
+            origin: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SecretsConfig {
+    /// Key for TOTP secrets at rest (64 hex chars).
+    pub totp_key: Option<String>,
+    /// Older keys still accepted while rotating.
+    pub totp_key_previous: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphsConfig {
+    /// Points per graph series; longer histories are downsampled.
+    pub max_points: usize,
+}
+
+impl Default for GraphsConfig {
+    fn default() -> Self {
+        Self { max_points: 50 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Where snapshots go; scheduled backups are off without it.
+    pub dir: Option<PathBuf>,
+    /// Take a snapshot when the newest is older than this.
+    pub interval_hours: u32,
+    /// Newest snapshot of each of this many recent days is kept.
+    pub keep_daily: usize,
+    /// Newest snapshot of each of this many recent ISO weeks is kept.
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            interval_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Usernames allowed to use the admin endpoints, matched exactly.
+    pub users: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Bearer token scrapers send for `/metrics`; it isn't served without one.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Config {
+    /// Load from the config file and process environment.
+    pub fn load() -> Result<Self> {
+        let path = match std::env::var(PATH_VAR) {
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => Some(PathBuf::from(DEFAULT_PATH)).filter(|p| p.exists()),
+        };
+        Self::from_sources(path.as_deref(), std::env::vars())
+    }
+
+    fn from_sources(
+        path: Option<&Path>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|e| {
+                    Error::Internal(format!("failed to read {}: {e}", path.display()))
+                })?;
+                toml::from_str(&text)
+                    .map_err(|e| Error::Internal(format!("invalid {}: {e}", path.display())))?
+            }
+            None => Self::default(),
+        };
+
+        let mut problems = Vec::new();
+        let mut env: Vec<_> = env
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        env.sort();
+        for (name, value) in env {
+            if let Err(problem) = config.set(&name, &value) {
+                problems.push(format!("{name}: {problem}"));
+            }
+        }
+        problems.extend(config.problems());
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::Internal(format!(
+                "invalid configuration:\n  {}",
+                problems.join("\n  ")
+            )))
+        }
+    }
+
+    /// Apply one `EKMAN__SECTION__KEY` override.
+    fn set(&mut self, name: &str, value: &str) -> std::result::Result<(), String> {
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        let value = value.trim();
+        match key.as_str() {
+            "server__bind" => self.server.bind = parse(value)?,
+            "server__port" => self.server.port = parse(value)?,
+            "server__cors_origins" => self.server.cors_origins = list(value),
+            "server__trust_proxy" => self.server.trust_proxy = parse(value)?,
+            "server__tls_cert" => self.server.tls_cert = optional(value).map(Into::into),
+            "server__tls_key" => self.server.tls_key = optional(value).map(Into::into),
+            "server__tls_redirect_port" => {
+                self.server.tls_redirect_port = optional(value).map(parse).transpose()?
+            }
+            "server__shutdown_timeout_secs" => self.server.shutdown_timeout_secs = parse(value)?,
+            "database__path" => self.database.path = value.into(),
+            "auth__totp_skew" => self.auth.totp_skew = parse(value)?,
+            "auth__session_days" => self.auth.session_days = parse(value)?,
+            "webauthn__rp_id" => self.webauthn.rp_id = value.into(),
+            "webauthn__origin" => self.webauthn.origin = optional(value).map(Into::into),
+            "secrets__totp_key" => self.secrets.totp_key = optional(value).map(Into::into),
+            "secrets__totp_key_previous" => self.secrets.totp_key_previous = list(value),
+            "graphs__max_points" => self.graphs.max_points = parse(value)?,
+            "log__format" => self.log.format = parse(value)?,
+            "backup__dir" => self.backup.dir = optional(value).map(Into::into),
+            "backup__interval_hours" => self.backup.interval_hours = parse(value)?,
+            "backup__keep_daily" => self.backup.keep_daily = parse(value)?,
+            "backup__keep_weekly" => self.backup.keep_weekly = parse(value)?,
+            "admin__users" => self.admin.users = list(value),
+            "metrics__token" => self.metrics.token = optional(value).map(Into::into),
+            _ => return Err("unknown setting".into()),
+        }
+        Ok(())
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let server = &self.server;
+
+        if server.port == 0 {
+            problems.push("server.port must not be 0".into());
+        }
+        for origin in &server.cors_origins {
+            if !is_origin(origin) {
+                problems.push(format!("server.cors_origins: '{origin}' is not an origin"));
+            }
+        }
+        if server.tls_cert.is_some() != server.tls_key.is_some() {
+            problems.push("server.tls_cert and server.tls_key must be set together".into());
+        }
+        if let Some(port) = server.tls_redirect_port {
+            if !server.tls() {
+                problems.push("server.tls_redirect_port requires tls".into());
+            } else if port == server.port {
+                problems.push("server.tls_redirect_port must differ from server.port".into());
+            }
+        }
+        if server.shutdown_timeout_secs > MAX_SHUTDOWN_TIMEOUT_SECS {
+            problems.push(format!(
+                "server.shutdown_timeout_secs must be at most {MAX_SHUTDOWN_TIMEOUT_SECS}"
+            ));
+        }
+        if self.database.path.is_empty() {
+            problems.push("database.path must not be empty".into());
+        }
+        if self.auth.totp_skew > MAX_TOTP_SKEW {
+            problems.push(format!(
+                "auth.totp_skew must be between 0 and {MAX_TOTP_SKEW}"
+            ));
+        }
+        if !(1..=MAX_SESSION_DAYS).contains(&self.auth.session_days) {
+            problems.push(format!(
+                "auth.session_days must be between 1 and {MAX_SESSION_DAYS}"
+            ));
+        }
+        if self.webauthn.rp_id.is_empty() {
+            problems.push("webauthn.rp_id must not be empty".into());
+        }
+        if let Some(origin) = &self.webauthn.origin
+            && !is_origin(origin)
+        {
+            problems.push(format!("webauthn.origin: '{origin}' is not an origin"));
+        }
+        if self.secrets.totp_key.is_none() && !self.secrets.totp_key_previous.is_empty() {
+            problems.push("secrets.totp_key_previous is set without secrets.totp_key".into());
+        }
+        if !(2..=MAX_GRAPH_POINTS).contains(&self.graphs.max_points) {
+            problems.push(format!(
+                "graphs.max_points must be between 2 and {MAX_GRAPH_POINTS}"
+            ));
+        }
+        if !(1..=MAX_BACKUP_INTERVAL_HOURS).contains(&self.backup.interval_hours) {
+            problems.push(format!(
+                "backup.interval_hours must be between 1 and {MAX_BACKUP_INTERVAL_HOURS}"
+            ));
+        }
+        if self.backup.keep_daily == 0 {
+            problems.push("backup.keep_daily must be at least 1".into());
+        }
+        if self.metrics.token.as_deref() == Some("") {
+            problems.push("metrics.token must not be empty".into());
+        }
+
+        problems
+    }
+
+    /// Origin of the web app served by this server.
+    pub fn own_origin(&self) -> String {
+        let scheme = if self.server.tls() { "https" } else { "http" };
+        format!("{scheme}://localhost:{}", self.server.port)
+    }
+
+    /// TOML for `config print`, with secrets replaced.
+    pub fn redacted_toml(&self) -> Result<String> {
+        let mut config = self.clone();
+        if config.secrets.totp_key.is_some() {
+            config.secrets.totp_key = Some(REDACTED.into());
+        }
+        for key in &mut config.secrets.totp_key_previous {
+            *key = REDACTED.into();
+        }
+        if config.metrics.token.is_some() {
+            config.metrics.token = Some(REDACTED.into());
+        }
+        toml::to_string_pretty(&config)
+            .map_err(|e| Error::Internal(format!("failed to print config: {e}")))
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> std::result::Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}'"))
+}
+
+fn optional(value: &str) -> Option<&str> {
+    Some(value).filter(|v| !v.is_empty())
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(Into::into)
+        .collect()
+}
+
+fn is_origin(s: &str) -> bool {
+    s.parse::<axum::http::Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http" | "https"))
+            && uri.host().is_some()
+            && uri.path() == "/"
+            && !s.ends_with('/')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(k, v)| (k.into(), v.into())).collect()
+    }
+
+    fn load(toml: &str, vars: &[(&str, &str)]) -> Result<Config> {
+        let dir = crate::test_dir("config");
+        let path = dir.join("ekman.toml");
+        std::fs::write(&path, toml).unwrap();
+        let config = Config::from_sources(Some(&path), env(vars));
+        std::fs::remove_dir_all(&dir).unwrap();
+        config
+    }
+
+    #[test]
+    fn test_file_and_env_overrides() {
+        let config = load(
+            "[server]\nport = 8080\nbind = \"127.0.0.1\"\n\n[graphs]\nmax_points = 200\n",
+            &[
+                ("EKMAN__SERVER__PORT", "9090"),
+                (
+                    "EKMAN__SERVER__CORS_ORIGINS",
+                    "https://a.example, https://b.example",
+                ),
+                ("EKMAN__LOG__FORMAT", "json"),
+                ("HOME", "/root"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.server.bind, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.server.cors_origins.len(), 2);
+        assert_eq!(config.graphs.max_points, 200);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.auth.session_days, 30);
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let err = Config::from_sources(
+            None,
+            env(&[
+                ("EKMAN__SERVER__PORT", "abc"),
+                ("EKMAN__SERVER__PROT", "1"),
+                ("EKMAN__AUTH__TOTP_SKEW", "11"),
+                ("EKMAN__SERVER__TLS_CERT", "/tmp/cert.pem"),
+            ]),
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("EKMAN__SERVER__PORT: invalid value 'abc'"));
+        assert!(err.contains("EKMAN__SERVER__PROT: unknown setting"));
+        assert!(err.contains("auth.totp_skew"));
+        assert!(err.contains("tls_key must be set together"));
+
+        assert!(load("[server]\nprot = 1\n", &[]).is_err());
+    }
+
+    #[test]
+    fn test_print_redacts_secrets() {
+        let key = "ab".repeat(32);
+        let config = Config::from_sources(
+            None,
+            env(&[
+                ("EKMAN__SECRETS__TOTP_KEY", &key),
+                ("EKMAN__SECRETS__TOTP_KEY_PREVIOUS", &key),
+            ]),
+        )
+        .unwrap();
+
+        let printed = config.redacted_toml().unwrap();
+        assert!(!printed.contains(&key));
+        assert!(printed.contains(REDACTED));
+
+        // The printed form loads back
+        let reloaded: Config = toml::from_str(&printed).unwrap();
+        assert_eq!(reloaded.server.port, config.server.port);
+    }
+}
@@ -1,7 +1,13 @@
+use axum::{
+    extract::{FromRequestParts, MatchedPath},
+    http::request::Parts,
+};
 use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
-use turso::{Builder, Database};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use turso::{Builder, Connection, Database, IntoParams, Row};
 
-use crate::{Error, Result};
+use crate::{Error, Result, State, auth, metrics::Metrics};
 
 const SCHEMA: &str = r#"
 CREATE TABLE IF NOT EXISTS users (
@@ -10,6 +16,7 @@ CREATE TABLE IF NOT EXISTS users (
     password_hash TEXT NOT NULL,
     totp_secret TEXT NOT NULL,
     totp_enabled INTEGER NOT NULL DEFAULT 1,
+    totp_last_step INTEGER,
     created_at TEXT DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -57,12 +64,15 @@ CREATE INDEX IF NOT EXISTS idx_sets_exercise_time ON workout_sets(exercise_id, c
 CREATE TABLE IF NOT EXISTS sessions (
     id INTEGER PRIMARY KEY,
     user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-    token TEXT NOT NULL UNIQUE,
+    token_hash TEXT NOT NULL UNIQUE,
     expires_at TEXT NOT NULL,
-    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TEXT,
+    user_agent TEXT,
+    ip TEXT
 );
 
-CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(token);
+CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
 
 CREATE TABLE IF NOT EXISTS weight_entries (
     id INTEGER PRIMARY KEY,
@@ -74,14 +84,358 @@ CREATE TABLE IF NOT EXISTS weight_entries (
 );
 
 CREATE INDEX IF NOT EXISTS idx_weight_user_day ON weight_entries(user_id, day);
+
+CREATE TABLE IF NOT EXISTS measurement_entries (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    day TEXT NOT NULL,
+    value REAL NOT NULL,
+    recorded_at TEXT NOT NULL,
+    UNIQUE(user_id, kind, day)
+);
+
+CREATE INDEX IF NOT EXISTS idx_measurement_user_kind_day ON measurement_entries(user_id, kind, day);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user ON recovery_codes(user_id);
+
+CREATE TABLE IF NOT EXISTS user_profiles (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    sex TEXT,
+    birth_year INTEGER,
+    squat_exercise_id INTEGER REFERENCES exercises(id),
+    bench_exercise_id INTEGER REFERENCES exercises(id),
+    deadlift_exercise_id INTEGER REFERENCES exercises(id)
+);
+
+CREATE TABLE IF NOT EXISTS passkeys (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id TEXT NOT NULL UNIQUE,
+    public_key TEXT NOT NULL,
+    sign_count INTEGER NOT NULL DEFAULT 0,
+    name TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_passkeys_user ON passkeys(user_id);
+
+CREATE TABLE IF NOT EXISTS webauthn_challenges (
+    challenge TEXT PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TEXT,
+    expires_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_tokens_user ON api_tokens(user_id);
 "#;
 
+/// Columns added after a table first shipped: (table, column, definition).
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("sessions", "last_used_at", "TEXT"),
+    ("sessions", "user_agent", "TEXT"),
+    ("sessions", "ip", "TEXT"),
+    ("users", "totp_last_step", "INTEGER"),
+];
+
+/// Per-request database connection. Extractors share the one connection, so
+/// auth lookups and the handler's queries don't each open their own.
+pub struct Db(pub Conn);
+
+impl FromRequestParts<State> for Db {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &State) -> Result<Self> {
+        if let Some(conn) = parts.extensions.get::<Conn>() {
+            return Ok(Self(conn.clone()));
+        }
+        let route = parts
+            .extensions
+            .get::<MatchedPath>()
+            .map_or(crate::metrics::UNMATCHED, |p| p.as_str());
+        let timer = Timer {
+            metrics: state.metrics.clone(),
+            key: (parts.method.to_string(), route.to_owned()),
+        };
+        let conn = Conn {
+            conn: state.db.connect()?,
+            timer: Some(Arc::new(timer)),
+        };
+        parts.extensions.insert(conn.clone());
+        Ok(Self(conn))
+    }
+}
+
+/// Where a connection's statement timings go: the request's method and route.
+struct Timer {
+    metrics: Arc<Metrics>,
+    key: (String, String),
+}
+
+fn observe(timer: &Option<Arc<Timer>>, elapsed: Duration) {
+    if let Some(timer) = timer {
+        timer.metrics.observe_db_query(&timer.key, elapsed);
+    }
+}
+
+/// A connection that times every statement run through it. Rows are stepped
+/// lazily, so a query is recorded when its `Rows` is dropped.
+#[derive(Clone)]
+pub struct Conn {
+    conn: Connection,
+    timer: Option<Arc<Timer>>,
+}
+
+/// Untimed, for tests and callers outside a request.
+impl From<Connection> for Conn {
+    fn from(conn: Connection) -> Self {
+        Self { conn, timer: None }
+    }
+}
+
+impl Conn {
+    pub async fn query(&self, sql: &str, params: impl IntoParams) -> turso::Result<Rows> {
+        let started = Instant::now();
+        let rows = self.conn.query(sql, params).await;
+        let elapsed = started.elapsed();
+        rows.map(|rows| Rows {
+            rows,
+            timer: self.timer.clone(),
+            elapsed,
+        })
+        .inspect_err(|_| observe(&self.timer, elapsed))
+    }
+
+    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> turso::Result<u64> {
+        let started = Instant::now();
+        let result = self.conn.execute(sql, params).await;
+        observe(&self.timer, started.elapsed());
+        result
+    }
+
+    pub async fn prepare(&self, sql: &str) -> turso::Result<Statement> {
+        let started = Instant::now();
+        let stmt = self.conn.prepare(sql).await?;
+        Ok(Statement {
+            stmt,
+            timer: self.timer.clone(),
+            prepared: started.elapsed(),
+        })
+    }
+
+    pub async fn transaction(&mut self) -> turso::Result<Transaction<'_>> {
+        let started = Instant::now();
+        let tx = self.conn.transaction().await;
+        observe(&self.timer, started.elapsed());
+        Ok(Transaction {
+            tx: tx?,
+            timer: &self.timer,
+        })
+    }
+
+    pub fn last_insert_rowid(&self) -> i64 {
+        self.conn.last_insert_rowid()
+    }
+}
+
+pub struct Rows {
+    rows: turso::Rows,
+    timer: Option<Arc<Timer>>,
+    elapsed: Duration,
+}
+
+impl Rows {
+    pub async fn next(&mut self) -> turso::Result<Option<Row>> {
+        let started = Instant::now();
+        let row = self.rows.next().await;
+        self.elapsed += started.elapsed();
+        row
+    }
+}
+
+impl Drop for Rows {
+    fn drop(&mut self) {
+        observe(&self.timer, self.elapsed);
+    }
+}
+
+/// The prepare time is counted with the statement's first run.
+pub struct Statement {
+    stmt: turso::Statement,
+    timer: Option<Arc<Timer>>,
+    prepared: Duration,
+}
+
+impl Statement {
+    pub async fn query_row(&mut self, params: impl IntoParams) -> turso::Result<Row> {
+        let started = Instant::now();
+        let row = self.stmt.query_row(params).await;
+        observe(
+            &self.timer,
+            std::mem::take(&mut self.prepared) + started.elapsed(),
+        );
+        row
+    }
+}
+
+pub struct Transaction<'a> {
+    tx: turso::transaction::Transaction<'a>,
+    timer: &'a Option<Arc<Timer>>,
+}
+
+impl Transaction<'_> {
+    pub async fn query(&self, sql: &str, params: impl IntoParams) -> turso::Result<Rows> {
+        let started = Instant::now();
+        let rows = self.tx.query(sql, params).await;
+        let elapsed = started.elapsed();
+        rows.map(|rows| Rows {
+            rows,
+            timer: self.timer.clone(),
+            elapsed,
+        })
+        .inspect_err(|_| observe(self.timer, elapsed))
+    }
+
+    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> turso::Result<u64> {
+        let started = Instant::now();
+        let result = self.tx.execute(sql, params).await;
+        observe(self.timer, started.elapsed());
+        result
+    }
+
+    pub async fn commit(self) -> turso::Result<()> {
+        let started = Instant::now();
+        let result = self.tx.commit().await;
+        observe(self.timer, started.elapsed());
+        result
+    }
+}
+
 pub async fn init(path: &str) -> Result<Database> {
     let db = Builder::new_local(path).build().await?;
-    db.connect()?.execute_batch(SCHEMA).await?;
+    apply_schema(&db.connect()?).await?;
     Ok(db)
 }
 
+async fn apply_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(SCHEMA).await?;
+    migrate(conn).await
+}
+
+/// Bring databases created by older versions up to the current schema.
+async fn migrate(conn: &Connection) -> Result<()> {
+    // Sessions used to store the raw cookie token
+    if has_column(conn, "sessions", "token").await? {
+        hash_session_tokens(conn).await?;
+    }
+
+    for &(table, column, definition) in ADDED_COLUMNS {
+        if !has_column(conn, table, column).await? {
+            conn.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+                (),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Tables in `SCHEMA`, parents before the tables referencing them.
+pub fn schema_tables() -> impl Iterator<Item = &'static str> {
+    SCHEMA
+        .split("CREATE TABLE IF NOT EXISTS ")
+        .skip(1)
+        .filter_map(|s| s.split_whitespace().next())
+}
+
+/// Whether every table exists and all migrations have run.
+pub async fn schema_ready(conn: &Connection) -> Result<bool> {
+    let mut rows = conn
+        .query("SELECT name FROM sqlite_schema WHERE type = 'table'", ())
+        .await?;
+    let mut tables = Vec::new();
+    while let Some(row) = rows.next().await? {
+        tables.push(row.get::<String>(0)?);
+    }
+    let all_tables = schema_tables().all(|table| tables.iter().any(|t| t == table));
+    if !all_tables || has_column(conn, "sessions", "token").await? {
+        return Ok(false);
+    }
+
+    for &(table, column, _) in ADDED_COLUMNS {
+        if !has_column(conn, table, column).await? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+async fn hash_session_tokens(conn: &Connection) -> Result<()> {
+    let mut rows = conn.query("SELECT id, token FROM sessions", ()).await?;
+    let mut sessions = Vec::new();
+    while let Some(row) = rows.next().await? {
+        sessions.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+
+    // Hash and rename together so a rerun can never hash twice
+    let tx = conn.unchecked_transaction().await?;
+    for (id, token) in sessions {
+        tx.execute(
+            "UPDATE sessions SET token = ? WHERE id = ?",
+            (auth::hash_token(&token), id),
+        )
+        .await?;
+    }
+    tx.execute("ALTER TABLE sessions RENAME COLUMN token TO token_hash", ())
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    Ok(columns(conn, table).await?.iter().any(|c| c == column))
+}
+
+pub async fn columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut rows = conn
+        .query(&format!("PRAGMA table_info({table})"), ())
+        .await?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next().await? {
+        columns.push(row.get::<String>(1)?);
+    }
+    Ok(columns)
+}
+
+/// Fold the WAL back into the main database file, so a stopped server
+/// leaves a single consistent file behind.
+pub async fn checkpoint(db: &Database) -> Result<()> {
+    let conn = db.connect()?;
+    let mut rows = conn.query("PRAGMA wal_checkpoint(TRUNCATE)", ()).await?;
+    while rows.next().await?.is_some() {}
+    Ok(())
+}
+
 pub fn timestamp(dt: DateTime<Utc>) -> String {
     dt.to_rfc3339_opts(SecondsFormat::Secs, true)
 }
@@ -100,3 +454,49 @@ pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>> {
 pub fn now() -> DateTime<Utc> {
     Utc::now()
 }
+
+#[cfg(test)]
+pub(crate) async fn test_db() -> Database {
+    let db = Builder::new_local(":memory:").build().await.unwrap();
+    apply_schema(&db.connect().unwrap()).await.unwrap();
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrates_plaintext_session_tokens() {
+        let db = Builder::new_local(":memory:").build().await.unwrap();
+        let conn = db.connect().unwrap();
+
+        // Sessions table as shipped before tokens were hashed
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                id INTEGER PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                token TEXT NOT NULL UNIQUE,
+                expires_at TEXT NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO sessions (user_id, token, expires_at)
+            VALUES (1, 'plaintext-token', '2999-01-01T00:00:00Z');",
+        )
+        .await
+        .unwrap();
+
+        apply_schema(&conn).await.unwrap();
+
+        assert!(!has_column(&conn, "sessions", "token").await.unwrap());
+        let mut stmt = conn
+            .prepare("SELECT token_hash FROM sessions WHERE id = 1")
+            .await
+            .unwrap();
+        let stored: String = stmt.query_row(()).await.unwrap().get(0).unwrap();
+        assert_eq!(stored, auth::hash_token("plaintext-token"));
+
+        // Running again is a no-op
+        apply_schema(&conn).await.unwrap();
+    }
+}
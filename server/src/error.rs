@@ -1,26 +1,91 @@
 use axum::{
     Json,
-    http::StatusCode,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
-use serde::Serialize;
+use ekman_core::{ErrorBody, ErrorCode, FieldError};
+
+use crate::request_id;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
     Unauthorized,
-    NotFound(String),
+    /// Wrong or already used TOTP or recovery code.
+    TotpInvalid,
+    Forbidden(String),
+    /// One of the `not_found.*` codes, or plain `NotFound`.
+    NotFound(ErrorCode),
     BadRequest(String),
+    /// The request clashes with existing data.
+    Conflict(ErrorCode),
+    /// Well-formed request with invalid values, one entry per field.
+    Invalid(Vec<FieldError>),
+    /// Throttled; seconds until the client may retry.
+    TooManyRequests(u64),
     Internal(String),
 }
 
+impl Error {
+    /// A single invalid field.
+    pub fn invalid(field: &str, code: ErrorCode, message: impl Into<String>) -> Self {
+        Error::Invalid(vec![FieldError {
+            field: field.into(),
+            code,
+            message: message.into(),
+        }])
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::Unauthorized => ErrorCode::Unauthorized,
+            Error::TotpInvalid => ErrorCode::TotpInvalid,
+            Error::Forbidden(_) => ErrorCode::Forbidden,
+            Error::NotFound(code) | Error::Conflict(code) => *code,
+            Error::BadRequest(_) => ErrorCode::BadRequest,
+            Error::Invalid(_) => ErrorCode::ValidationFailed,
+            Error::TooManyRequests(_) => ErrorCode::RateLimited,
+            Error::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::Unauthorized | Error::TotpInvalid => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Unauthorized => write!(f, "unauthorized"),
-            Self::NotFound(msg) => write!(f, "not found: {msg}"),
+            Self::TotpInvalid => write!(f, "invalid or already used code"),
+            Self::Forbidden(msg) => write!(f, "forbidden: {msg}"),
+            Self::NotFound(code) => match code.as_str().strip_prefix("not_found.") {
+                Some(what) => write!(f, "not found: {}", what.replace('_', " ")),
+                None => write!(f, "not found"),
+            },
             Self::BadRequest(msg) => write!(f, "bad request: {msg}"),
+            Self::Conflict(code) => write!(f, "conflict: {}", code.as_str().replace('_', " ")),
+            Self::Invalid(fields) => {
+                write!(f, "invalid input: ")?;
+                for (i, field) in fields.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { "; " };
+                    write!(f, "{sep}{}: {}", field.field, field.message)?;
+                }
+                Ok(())
+            }
+            Self::TooManyRequests(secs) => write!(f, "too many requests, retry in {secs}s"),
             Self::Internal(msg) => write!(f, "internal error: {msg}"),
         }
     }
@@ -28,30 +93,34 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-#[derive(Serialize)]
-struct ErrorBody {
-    error: String,
-}
-
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        let status = match &self {
-            Error::Unauthorized => StatusCode::UNAUTHORIZED,
-            Error::NotFound(_) => StatusCode::NOT_FOUND,
-            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
-            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-
+        let status = self.status();
+        let code = self.code();
         if status.is_server_error() {
-            tracing::error!(%status, error = %self);
+            tracing::error!(%status, %code, error = %self);
         } else {
-            tracing::warn!(%status, error = %self);
+            tracing::warn!(%status, %code, error = %self);
         }
 
+        let retry_after = match self {
+            Error::TooManyRequests(secs) => Some([(header::RETRY_AFTER, secs.to_string())]),
+            _ => None,
+        };
+        let error = self.to_string();
+        let details = match self {
+            Error::Invalid(fields) => fields,
+            _ => Vec::new(),
+        };
+
         (
             status,
+            retry_after,
             Json(ErrorBody {
-                error: self.to_string(),
+                code,
+                error,
+                details,
+                request_id: request_id::current(),
             }),
         )
             .into_response()
@@ -61,7 +130,7 @@ impl IntoResponse for Error {
 impl From<turso::Error> for Error {
     fn from(e: turso::Error) -> Self {
         match e {
-            turso::Error::QueryReturnedNoRows => Error::NotFound("not found".into()),
+            turso::Error::QueryReturnedNoRows => Error::NotFound(ErrorCode::NotFound),
             other => Error::Internal(other.to_string()),
         }
     }
@@ -72,3 +141,120 @@ impl From<std::io::Error> for Error {
         Error::Internal(e.to_string())
     }
 }
+
+impl From<JsonRejection> for Error {
+    fn from(e: JsonRejection) -> Self {
+        match e {
+            JsonRejection::JsonDataError(e) => {
+                Error::invalid("body", ErrorCode::InvalidFormat, e.body_text())
+            }
+            other => Error::BadRequest(other.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for Error {
+    fn from(e: PathRejection) -> Self {
+        Error::invalid("path", ErrorCode::InvalidFormat, e.body_text())
+    }
+}
+
+impl From<QueryRejection> for Error {
+    fn from(e: QueryRejection) -> Self {
+        Error::invalid("query", ErrorCode::InvalidFormat, e.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{State, db};
+    use axum::{Router, body::Body, http::Request};
+    use tower::ServiceExt;
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, ErrorBody) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn post_json(uri: &str, body: &str) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_username_taken() {
+        use totp_rs::{Algorithm, Secret, TOTP};
+
+        let db = db::test_db().await;
+        db.connect()
+            .unwrap()
+            .execute(
+                "INSERT INTO users (username, password_hash, totp_secret) VALUES ('u', 'x', 'y')",
+                (),
+            )
+            .await
+            .unwrap();
+        let app = crate::router(State::test(db));
+
+        let secret = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+        let bytes = Secret::Encoded(secret.into()).to_bytes().unwrap();
+        let code = TOTP::new_unchecked(Algorithm::SHA1, 6, 0, 30, bytes, None, String::new())
+            .generate_current()
+            .unwrap();
+        let body = format!(
+            r#"{{"username":"u","password":"p","totp_secret":"{secret}","totp_code":"{code}"}}"#
+        );
+
+        let (status, body) = send(&app, post_json("/api/v1/auth/register", &body)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.code, ErrorCode::UsernameTaken);
+    }
+
+    #[tokio::test]
+    async fn test_validation_details() {
+        let app = crate::router(State::test(db::test_db().await));
+
+        let (status, body) = send(
+            &app,
+            post_json(
+                "/api/v1/auth/register",
+                r#"{"username":" ","password":"p","totp_secret":"s","totp_code":"1"}"#,
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body.code, ErrorCode::ValidationFailed);
+        assert_eq!(body.details[0].field, "username");
+        assert_eq!(body.details[0].code, ErrorCode::Required);
+
+        // Extractor rejections get the same body
+        let (status, body) = send(
+            &app,
+            post_json("/api/v1/auth/register", r#"{"username":1}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body.details[0].field, "body");
+        let (status, body) = send(&app, post_json("/api/v1/auth/register", "{")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, ErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn test_not_found_message() {
+        let error = Error::NotFound(ErrorCode::WeightEntryNotFound);
+        assert_eq!(error.to_string(), "not found: weight entry");
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            Error::Conflict(ErrorCode::PlanExistsForDay).to_string(),
+            "conflict: plan exists for day"
+        );
+    }
+}
@@ -1,76 +1,308 @@
 mod auth;
+mod backup;
+pub mod cli;
+mod config;
 mod db;
 mod error;
+mod extract;
+mod metrics;
+mod openapi;
+mod ratelimit;
+mod request_id;
 mod routes;
+mod secrets;
+mod shutdown;
+mod tls;
+mod version;
+mod web;
+mod webauthn;
 
 use axum::{
     Router,
-    http::{HeaderValue, Method, header},
+    http::{HeaderValue, Method, Request, Response, header},
+    middleware,
 };
+use backup::Backups;
+use config::{Config, LogFormat};
+use metrics::Metrics;
+use request_id::RequestId;
+use secrets::Keyring;
+use shutdown::Shutdown;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
-use tracing::info;
+use tracing::{Span, field, info, info_span, warn};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use webauthn::RelyingParty;
 
 pub use error::{Error, Result};
 
+const SESSION_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[derive(Clone)]
 pub struct State {
     pub db: turso::Database,
+    pub keys: Arc<Keyring>,
+    pub rp: Arc<RelyingParty>,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    /// Set when `backup.dir` is configured.
+    pub backups: Option<Arc<Backups>>,
 }
 
-pub async fn run() -> Result<()> {
-    init_tracing();
-
-    let port: u16 = std::env::var("EKMAN__SERVER__PORT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(3000);
-
-    let db_path = std::env::var("EKMAN__DATABASE__PATH").unwrap_or_else(|_| "ekman.db".into());
-
-    let origins: Vec<HeaderValue> = std::env::var("EKMAN__SERVER__CORS_ORIGINS")
-        .unwrap_or_else(|_| "http://localhost:5173".into())
-        .split(',')
-        .filter_map(|s| s.trim().parse().ok())
-        .collect();
-
-    let db = db::init(&db_path).await?;
-    let state = State { db };
-
-    let cors = CorsLayer::new()
-        .allow_methods([
-            Method::GET,
-            Method::POST,
-            Method::PUT,
-            Method::PATCH,
-            Method::DELETE,
-        ])
-        .allow_origin(origins)
-        .allow_credentials(true)
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::ACCEPT]);
-
-    let app = Router::new()
-        .merge(routes::api())
-        .with_state(state)
-        .layer(CompressionLayer::new())
-        .layer(cors)
-        .layer(TraceLayer::new_for_http());
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = TcpListener::bind(addr).await?;
-    info!("listening on {addr}, database: {db_path}");
-    axum::serve(listener, app).await?;
+#[cfg(test)]
+impl State {
+    pub(crate) fn test(db: turso::Database) -> Self {
+        let config = Config::default();
+        Self {
+            db,
+            keys: Arc::default(),
+            rp: Arc::new(RelyingParty::from_config(&config)),
+            config: Arc::new(config),
+            metrics: Arc::default(),
+            backups: None,
+        }
+    }
+}
+
+/// A new empty directory for one test. Unique per call, so tests running in
+/// parallel never share one; callers remove it when done.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("ekman-{name}-{}-{n}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub async fn run(config: Config) -> Result<()> {
+    init_tracing(config.log.format);
+
+    let config = Arc::new(config);
+    let db_path = &config.database.path;
+    let db = db::init(db_path).await?;
+    let keys = Keyring::from_config(&config.secrets)?;
+    if keys.is_enabled() {
+        let sealed = secrets::reseal_totp_secrets(&db.connect()?, &keys, false).await?;
+        if sealed > 0 {
+            info!("encrypted {sealed} plaintext totp secrets");
+        }
+    } else {
+        warn!("secrets.totp_key not set, totp secrets are stored unencrypted");
+    }
+
+    // Background tasks stop on shutdown and are awaited before the final
+    // checkpoint, so it never races a purge or snapshot
+    let shutdown = Shutdown::listen();
+    let mut tasks = vec![tokio::spawn(purge_sessions(db.clone(), shutdown.clone()))];
+    let backups = Backups::from_config(&config.backup).map(Arc::new);
+    if let Some(backups) = &backups {
+        let (backups, db, shutdown) = (backups.clone(), db.clone(), shutdown.clone());
+        tasks.push(tokio::spawn(async move { backups.run(db, shutdown).await }));
+    }
+
+    let request_metrics = Arc::new(Metrics::default());
+    let state = State {
+        db: db.clone(),
+        keys: Arc::new(keys),
+        rp: Arc::new(RelyingParty::from_config(&config)),
+        config: config.clone(),
+        metrics: request_metrics.clone(),
+        backups,
+    };
+
+    let mut app = router(state).layer(CompressionLayer::new());
+    if !config.server.cors_origins.is_empty() {
+        let origins: Vec<HeaderValue> = config
+            .server
+            .cors_origins
+            .iter()
+            .filter_map(|s| s.parse().ok())
+            .collect();
+        app = app.layer(
+            CorsLayer::new()
+                .allow_methods([
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::DELETE,
+                ])
+                .allow_origin(origins)
+                .allow_credentials(true)
+                .allow_headers([
+                    header::CONTENT_TYPE,
+                    header::AUTHORIZATION,
+                    header::ACCEPT,
+                    request_id::HEADER,
+                ])
+                .expose_headers([request_id::HEADER]),
+        );
+    }
+    let app = app
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_span)
+                .on_response(log_response),
+        )
+        .layer(middleware::from_fn(request_id::assign));
+
+    let addr = SocketAddr::new(config.server.bind, config.server.port);
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let timeout = config.server.shutdown_timeout();
+    match tls::TlsSettings::from_config(&config.server) {
+        Some(settings) => {
+            let tls_config = settings.load().await?;
+            tokio::spawn(tls::Reloader::new(settings.clone(), tls_config.clone()).run());
+
+            if let Some(redirect_port) = settings.redirect_port {
+                let redirect_addr = SocketAddr::new(config.server.bind, redirect_port);
+                let listener = TcpListener::bind(redirect_addr).await?;
+                info!("redirecting http on {redirect_addr} to https");
+                let https_port = config.server.port;
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    let result = axum::serve(listener, tls::redirect_router(https_port))
+                        .with_graceful_shutdown(shutdown.requested())
+                        .await;
+                    if let Err(e) = result {
+                        warn!("https redirect listener stopped: {e}");
+                    }
+                });
+            }
+
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let (handle, shutdown) = (handle.clone(), shutdown.clone());
+                async move {
+                    shutdown.requested().await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+
+            info!("listening on {addr} (https), database: {db_path}");
+            let server = axum_server::bind_rustls(addr, tls_config)
+                .handle(handle)
+                .serve(app);
+            shutdown::drain(server, shutdown, timeout, &request_metrics).await?;
+        }
+        None => {
+            let listener = TcpListener::bind(addr).await?;
+            info!("listening on {addr}, database: {db_path}");
+            let server = axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown.clone().requested())
+                .into_future();
+            shutdown::drain(server, shutdown, timeout, &request_metrics).await?;
+        }
+    }
+
+    for task in tasks {
+        if let Err(e) = task.await {
+            warn!("background task failed: {e}");
+        }
+    }
+    match db::checkpoint(&db).await {
+        Ok(()) => info!("database checkpointed, bye"),
+        Err(e) => warn!("final database checkpoint failed: {e}"),
+    }
     Ok(())
 }
 
-fn init_tracing() {
+/// Versioned API with its OpenAPI document, the deprecated unversioned
+/// aliases, probes and web frontend with auth throttling and request
+/// metrics, without transport layers.
+fn router(state: State) -> Router {
+    let limiter = Arc::new(ratelimit::AuthLimiter::new(state.config.server.trust_proxy));
+    let metrics = state.metrics.clone();
+    let (api, spec) = routes::api().split_for_parts();
+    Router::new()
+        .merge(api)
+        .merge(version::unversioned())
+        .merge(openapi::router(openapi::finish(spec)))
+        .merge(metrics::router())
+        .merge(web::router())
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(
+            limiter,
+            ratelimit::limit_auth,
+        ))
+        .layer(middleware::from_fn_with_state(metrics, metrics::track))
+}
+
+/// Span wrapping each request. `user_id` starts empty and is filled in by
+/// the `AuthUser` extractor, so every log line inside an authenticated
+/// request names the user.
+fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request.extensions().get::<RequestId>().map(|id| &id.0);
+    info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id,
+        user_id = field::Empty,
+    )
+}
+
+/// One line per finished request, inside its span.
+fn log_response<B>(response: &Response<B>, latency: Duration, _span: &Span) {
+    info!(
+        status = response.status().as_u16(),
+        latency_ms = latency.as_millis() as u64,
+        "finished"
+    );
+}
+
+/// Periodically drop expired sessions so idle rows don't pile up.
+async fn purge_sessions(db: turso::Database, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(SESSION_PURGE_INTERVAL);
+    let stop = shutdown.requested();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = &mut stop => return,
+            _ = interval.tick() => {}
+        }
+        let result = match db.connect() {
+            Ok(conn) => auth::purge_expired_sessions(&conn).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(0) => {}
+            Ok(n) => info!("purged {n} expired sessions"),
+            Err(e) => warn!("session purge failed: {e}"),
+        }
+    }
+}
+
+fn init_tracing(format: LogFormat) {
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("warn,ekman_server=info"));
 
+    let (text, json) = match format {
+        LogFormat::Text => (
+            Some(tracing_subscriber::fmt::layer().with_target(false)),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_target(false)
+                    .with_span_list(false),
+            ),
+        ),
+    };
+
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(text)
+        .with(json)
         .with(filter)
         .try_init()
         .ok();
@@ -1,8 +1,9 @@
-use ekman_server::run;
+use ekman_server::cli;
 
 #[tokio::main]
 async fn main() {
-    if let Err(e) = run().await {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = cli::run(&args).await {
         eprintln!("server error: {e}");
         std::process::exit(1);
     }
@@ -0,0 +1,486 @@
+//! Probes and Prometheus metrics.
+//!
+//! `/healthz` answers while the process is up, `/readyz` once the database
+//! is reachable with the current schema. `/metrics` renders request counters
+//! and latency histograms collected by `track`, statement timings from
+//! `db::Conn`, plus user, set and session totals queried at scrape time. It
+//! is only served when `metrics.token` is set, to scrapers sending that token
+//! as a bearer token.
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State as AxumState},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use turso::{Connection, IntoParams};
+
+use ekman_core::ErrorCode;
+
+use crate::{Error, Result, State, auth, db, ratelimit};
+
+/// Upper bounds in seconds, shared by request and query histograms.
+const BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Route label for requests no route matched (web assets, 404s).
+pub(crate) const UNMATCHED: &str = "unmatched";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        for (bucket, &bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (count, bound) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// (method, route, status) -> count
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// (method, route) -> latency
+    latency: BTreeMap<(String, String), Histogram>,
+    /// route -> rejected credentials
+    auth_failures: BTreeMap<String, u64>,
+    /// (method, route) -> duration of each statement run by its handler
+    db_queries: BTreeMap<(String, String), Histogram>,
+    /// probe or scrape query name -> duration
+    queries: BTreeMap<&'static str, Histogram>,
+    /// (method, route) -> requests currently being handled
+    in_flight: BTreeMap<(String, String), u64>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn record_request(&self, method: &str, route: &str, status: StatusCode, secs: f64) {
+        let mut inner = self.lock();
+        *inner
+            .requests
+            .entry((method.into(), route.into(), status.as_u16()))
+            .or_default() += 1;
+        inner
+            .latency
+            .entry((method.into(), route.into()))
+            .or_default()
+            .observe(secs);
+        if status == StatusCode::UNAUTHORIZED && ratelimit::is_limited(route) {
+            *inner.auth_failures.entry(route.into()).or_default() += 1;
+        }
+    }
+
+    fn start_request(&self, key: &(String, String)) {
+        *self.lock().in_flight.entry(key.clone()).or_default() += 1;
+    }
+
+    fn end_request(&self, key: &(String, String)) {
+        let mut inner = self.lock();
+        if let Some(count) = inner.in_flight.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                inner.in_flight.remove(key);
+            }
+        }
+    }
+
+    /// e.g. "2 in-flight requests (PUT /api/v1/days/{date}/... x2)", for the
+    /// shutdown log.
+    pub fn in_flight_summary(&self) -> String {
+        let inner = self.lock();
+        let total: u64 = inner.in_flight.values().sum();
+        if total == 0 {
+            return "0 in-flight requests".into();
+        }
+        let routes: Vec<String> = inner
+            .in_flight
+            .iter()
+            .map(|((method, route), count)| format!("{method} {route} x{count}"))
+            .collect();
+        format!("{total} in-flight requests ({})", routes.join(", "))
+    }
+
+    /// Run a single-value count query, recording how long it took.
+    async fn count(
+        &self,
+        conn: &Connection,
+        name: &'static str,
+        sql: &str,
+        params: impl IntoParams,
+    ) -> Result<i64> {
+        let started = Instant::now();
+        let mut stmt = conn.prepare(sql).await?;
+        let value = stmt.query_row(params).await?.get::<i64>(0)?;
+        self.observe_query(name, started);
+        Ok(value)
+    }
+
+    pub(crate) fn observe_db_query(&self, key: &(String, String), elapsed: Duration) {
+        self.lock()
+            .db_queries
+            .entry(key.clone())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn observe_query(&self, name: &'static str, started: Instant) {
+        self.lock()
+            .queries
+            .entry(name)
+            .or_default()
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    fn render(&self, out: &mut String) {
+        let inner = self.lock();
+
+        header(
+            out,
+            "ekman_http_requests_total",
+            "counter",
+            "HTTP requests by method, route and status.",
+        );
+        for ((method, route, status), count) in &inner.requests {
+            let _ = writeln!(
+                out,
+                "ekman_http_requests_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {count}",
+                escape(route)
+            );
+        }
+
+        header(
+            out,
+            "ekman_http_request_duration_seconds",
+            "histogram",
+            "HTTP request latency by method and route.",
+        );
+        for ((method, route), histogram) in &inner.latency {
+            let labels = format!("method=\"{method}\",route=\"{}\"", escape(route));
+            histogram.render(out, "ekman_http_request_duration_seconds", &labels);
+        }
+
+        header(
+            out,
+            "ekman_http_requests_in_flight",
+            "gauge",
+            "HTTP requests currently being handled.",
+        );
+        let in_flight: u64 = inner.in_flight.values().sum();
+        let _ = writeln!(out, "ekman_http_requests_in_flight {in_flight}");
+
+        header(
+            out,
+            "ekman_auth_failures_total",
+            "counter",
+            "Rejected passwords, TOTP codes and passkeys by route.",
+        );
+        for (route, count) in &inner.auth_failures {
+            let _ = writeln!(
+                out,
+                "ekman_auth_failures_total{{route=\"{}\"}} {count}",
+                escape(route)
+            );
+        }
+
+        header(
+            out,
+            "ekman_db_query_duration_seconds",
+            "histogram",
+            "Latency of the database statements run while handling requests, by method and route.",
+        );
+        for ((method, route), histogram) in &inner.db_queries {
+            let labels = format!("method=\"{method}\",route=\"{}\"", escape(route));
+            histogram.render(out, "ekman_db_query_duration_seconds", &labels);
+        }
+
+        header(
+            out,
+            "ekman_probe_query_duration_seconds",
+            "histogram",
+            "Latency of the queries /readyz and /metrics run, by query.",
+        );
+        for (name, histogram) in &inner.queries {
+            histogram.render(
+                out,
+                "ekman_probe_query_duration_seconds",
+                &format!("query=\"{name}\""),
+            );
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Middleware recording every request into `Metrics`. Applied with
+/// `Router::layer`, so it runs after routing and sees the matched route.
+pub async fn track(
+    AxumState(metrics): AxumState<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED, |p| p.as_str())
+        .to_owned();
+
+    let started = Instant::now();
+    let in_flight = InFlight::new(&metrics, (method, route));
+    let response = next.run(request).await;
+    let (method, route) = &in_flight.key;
+    metrics.record_request(
+        method,
+        route,
+        response.status(),
+        started.elapsed().as_secs_f64(),
+    );
+    response
+}
+
+/// Counts a request as in flight until dropped, including when the request
+/// future is cancelled.
+struct InFlight<'a> {
+    metrics: &'a Metrics,
+    key: (String, String),
+}
+
+impl<'a> InFlight<'a> {
+    fn new(metrics: &'a Metrics, key: (String, String)) -> Self {
+        metrics.start_request(&key);
+        Self { metrics, key }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.metrics.end_request(&self.key);
+    }
+}
+
+pub fn router() -> Router<State> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(AxumState(state): AxumState<State>) -> Response {
+    let started = Instant::now();
+    let ready = match state.db.connect() {
+        Ok(conn) => db::schema_ready(&conn).await,
+        Err(e) => Err(e.into()),
+    };
+    state.metrics.observe_query("schema_ready", started);
+    match ready {
+        Ok(true) => "ready".into_response(),
+        Ok(false) => (StatusCode::SERVICE_UNAVAILABLE, "schema out of date").into_response(),
+        Err(e) => {
+            tracing::warn!("readiness check failed: {e}");
+            (StatusCode::SERVICE_UNAVAILABLE, "database unavailable").into_response()
+        }
+    }
+}
+
+async fn metrics(AxumState(state): AxumState<State>, headers: HeaderMap) -> Result<Response> {
+    let Some(token) = &state.config.metrics.token else {
+        return Err(Error::NotFound(ErrorCode::NotFound));
+    };
+    // Compare digests so the time taken says nothing about the token
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if given.map(auth::hash_token) != Some(auth::hash_token(token)) {
+        return Err(Error::Unauthorized);
+    }
+
+    let conn = state.db.connect()?;
+    let metrics = &state.metrics;
+    let now = db::timestamp(db::now());
+    let users = metrics
+        .count(&conn, "count_users", "SELECT COUNT(*) FROM users", ())
+        .await?;
+    let sets = metrics
+        .count(&conn, "count_sets", "SELECT COUNT(*) FROM workout_sets", ())
+        .await?;
+    let sessions = metrics
+        .count(
+            &conn,
+            "count_active_sessions",
+            "SELECT COUNT(*) FROM sessions WHERE expires_at > ?",
+            [now],
+        )
+        .await?;
+
+    let mut out = String::new();
+    for (name, help, value) in [
+        ("ekman_users", "Registered users.", users),
+        ("ekman_sets", "Logged sets.", sets),
+        ("ekman_active_sessions", "Unexpired sessions.", sessions),
+    ] {
+        header(&mut out, name, "gauge", help);
+        let _ = writeln!(out, "{name} {value}");
+    }
+    metrics.render(&mut out);
+
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], out).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let app = crate::router(State::test(db::test_db().await));
+        assert_eq!(get(&app, "/healthz").await.0, StatusCode::OK);
+        assert_eq!(get(&app, "/readyz").await.0, StatusCode::OK);
+        // No token configured, no metrics
+        assert_eq!(get(&app, "/metrics").await.0, StatusCode::NOT_FOUND);
+
+        // A database that was never migrated is not ready
+        let db = turso::Builder::new_local(":memory:").build().await.unwrap();
+        let app = crate::router(State::test(db));
+        assert_eq!(
+            get(&app, "/readyz").await.0,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let mut state = State::test(db::test_db().await);
+        let mut config = crate::config::Config::default();
+        config.metrics.token = Some("scrape".into());
+        state.config = Arc::new(config);
+        let app = crate::router(state);
+        assert_eq!(
+            get(&app, "/api/v1/exercises/7").await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        let login = Request::post("/api/v1/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"username":"u","password":"p","totp":"000000"}"#,
+            ))
+            .unwrap();
+        app.clone().oneshot(login).await.unwrap();
+
+        // Only scrapers with the token get the metrics
+        assert_eq!(get(&app, "/metrics").await.0, StatusCode::UNAUTHORIZED);
+        let scrape = Request::get("/metrics")
+            .header(header::AUTHORIZATION, "Bearer scrape")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(scrape).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(
+            "ekman_http_requests_total{method=\"GET\",route=\"/api/v1/exercises/{id}\",status=\"401\"} 1"
+        ));
+        assert!(body.contains(
+            "ekman_http_request_duration_seconds_count{method=\"GET\",route=\"/api/v1/exercises/{id}\"} 1"
+        ));
+        assert!(body.contains("ekman_auth_failures_total{route=\"/api/v1/auth/login\"} 1"));
+        assert!(body.contains("ekman_users 0"));
+        // Only the scrape itself
+        assert!(body.contains("ekman_http_requests_in_flight 1"));
+        assert!(body.contains("ekman_probe_query_duration_seconds_count{query=\"count_sets\"} 1"));
+        // The login's user lookup, timed through the request's connection
+        assert!(body.contains(
+            "ekman_db_query_duration_seconds_count{method=\"POST\",route=\"/api/v1/auth/login\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_in_flight_summary() {
+        let metrics = Metrics::default();
+        let key = ("PUT".to_string(), "/api/v1/weight/{date}".to_string());
+        let first = InFlight::new(&metrics, key.clone());
+        let second = InFlight::new(&metrics, key);
+        assert_eq!(
+            metrics.in_flight_summary(),
+            "2 in-flight requests (PUT /api/v1/weight/{date} x2)"
+        );
+
+        drop(first);
+        drop(second);
+        assert_eq!(metrics.in_flight_summary(), "0 in-flight requests");
+    }
+}
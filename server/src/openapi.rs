@@ -0,0 +1,229 @@
+//! OpenAPI 3.1 document for the API.
+//!
+//! Operations come from the `#[utoipa::path]` attribute on each handler in
+//! `routes`, which also registers the route, and schemas from the
+//! `ekman_core` types. Served at `/api/v1/openapi.json`; with the `swagger-ui`
+//! feature, a bundled Swagger UI is served at `/api/docs`.
+
+use axum::{Router, body::Bytes, http::header, routing::get};
+use ekman_core::ErrorBody;
+use utoipa::{
+    Modify, OpenApi, PartialSchema, ToSchema,
+    openapi::{
+        self, RefOr, ResponseBuilder,
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    },
+};
+
+use crate::{State, version};
+
+/// Relative to the version prefix.
+const SPEC_PATH: &str = "/openapi.json";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ekman", description = "Workout, body weight and measurement tracking."),
+    tags(
+        (name = "auth", description = "Sign-in, second factors, sessions, API tokens and passkeys"),
+        (name = "account"),
+        (name = "admin", description = "Usernames listed in `admin.users` only"),
+        (name = "plans"),
+        (name = "activity"),
+        (name = "exercises"),
+        (name = "sets"),
+        (name = "weight"),
+        (name = "measurements"),
+        (name = "profile", description = "Profile and strength reports"),
+    ),
+    security(("session" = []), ("token" = [])),
+    modifiers(&SecuritySchemes),
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, api: &mut openapi::OpenApi) {
+        let components = api.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("ekman_session"))),
+        );
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Add the shared error body as every operation's default response. Runs on
+/// the finished document, since modifiers on `ApiDoc` run before the routes
+/// are added.
+pub fn finish(mut api: openapi::OpenApi) -> openapi::OpenApi {
+    api.components
+        .get_or_insert_with(Default::default)
+        .schemas
+        .extend([
+            (ErrorBody::name().into(), ErrorBody::schema()),
+            (
+                ekman_core::ErrorCode::name().into(),
+                ekman_core::ErrorCode::schema(),
+            ),
+            (
+                ekman_core::FieldError::name().into(),
+                ekman_core::FieldError::schema(),
+            ),
+        ]);
+
+    let error = ResponseBuilder::new()
+        .description("Error, see `code`")
+        .content(
+            "application/json",
+            openapi::ContentBuilder::new()
+                .schema(Some(RefOr::Ref(openapi::Ref::from_schema_name(
+                    ErrorBody::name(),
+                ))))
+                .build(),
+        )
+        .build();
+    for item in api.paths.paths.values_mut() {
+        for operation in [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            operation
+                .responses
+                .responses
+                .insert("default".into(), error.clone().into());
+        }
+    }
+    api
+}
+
+pub fn router(api: openapi::OpenApi) -> Router<State> {
+    #[cfg(feature = "swagger-ui")]
+    let docs = utoipa_swagger_ui::SwaggerUi::new("/api/docs")
+        .url(format!("{}{SPEC_PATH}", version::V1), api.clone());
+
+    // Also served at the pre-versioning `/api/openapi.json`
+    let json = Bytes::from(serde_json::to_vec(&api).expect("openapi document serializes"));
+    let spec = Router::new().route(
+        SPEC_PATH,
+        get(move || async move { ([(header::CONTENT_TYPE, "application/json")], json.clone()) }),
+    );
+    let router = Router::new()
+        .nest(version::V1, spec.clone())
+        .merge(version::deprecated(spec));
+
+    #[cfg(feature = "swagger-ui")]
+    let router = router.merge(docs);
+    router
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db, routes};
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    /// Paths registered on an axum router. Axum has no API to list them, so
+    /// they are read from its `Debug` output.
+    fn registered_paths(router: &Router<State>) -> Vec<String> {
+        let debug = format!("{router:?}");
+        let mut paths: Vec<_> = debug
+            .split('"')
+            .filter(|s| s.starts_with("/api/v1/"))
+            .map(str::to_owned)
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    #[tokio::test]
+    async fn test_every_route_is_documented() {
+        let (router, api) = routes::api().split_for_parts();
+        let paths = registered_paths(&router);
+        assert!(paths.len() > 40, "could not read routes: {paths:?}");
+
+        let app = router.with_state(State::test(db::test_db().await));
+        for path in &paths {
+            let item = api
+                .paths
+                .paths
+                .get(path)
+                .unwrap_or_else(|| panic!("{path} is not documented"));
+
+            // Fill in path parameters; handlers reject the request before
+            // doing anything, but only a missing method gets 405
+            let uri = path
+                .split('/')
+                .map(|s| if s.starts_with('{') { "1" } else { s })
+                .collect::<Vec<_>>()
+                .join("/");
+            for (method, operation) in [
+                (Method::GET, &item.get),
+                (Method::PUT, &item.put),
+                (Method::POST, &item.post),
+                (Method::DELETE, &item.delete),
+                (Method::PATCH, &item.patch),
+            ] {
+                let request = Request::builder()
+                    .method(&method)
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let response = app.clone().oneshot(request).await.unwrap();
+                let routed = response.status() != StatusCode::METHOD_NOT_ALLOWED;
+                assert_eq!(
+                    routed,
+                    operation.is_some(),
+                    "{method} {path} is {}",
+                    if routed {
+                        "not documented"
+                    } else {
+                        "documented but not routed"
+                    }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_spec() {
+        let api = finish(routes::api().split_for_parts().1);
+        let json: serde_json::Value = serde_json::to_value(&api).unwrap();
+        assert_eq!(json["openapi"], "3.1.0");
+        assert_eq!(json["info"]["title"], "ekman");
+
+        let register = &json["paths"]["/api/v1/auth/register"]["post"];
+        assert_eq!(register["security"], serde_json::json!([{}]));
+        assert_eq!(
+            register["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/RegisterInput"
+        );
+        assert_eq!(
+            register["responses"]["default"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorBody"
+        );
+        let sets = &json["paths"]["/api/v1/days/{date}/exercises/{exercise_id}/sets/{set_number}"];
+        assert_eq!(sets["put"]["parameters"].as_array().unwrap().len(), 3);
+        assert!(json["components"]["schemas"]["WorkoutSet"].is_object());
+        assert!(
+            json["components"]["schemas"]["ErrorCode"]["enum"]
+                .as_array()
+                .unwrap()
+                .contains(&"not_found.exercise".into())
+        );
+    }
+}
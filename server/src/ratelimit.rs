@@ -0,0 +1,397 @@
+//! Throttling for endpoints that check credentials.
+//!
+//! Failures are counted per client IP and per username. Past `FREE_FAILURES`
+//! each further failure locks the key out for twice as long as the last, up
+//! to `MAX_LOCKOUT`; a success clears both. Each IP also gets a fixed budget
+//! of attempts per minute. Passkey login is keyed by IP only, so a locked
+//! username can still sign in that way.
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result, tls, version};
+
+/// Routes where a 401 means a wrong password, TOTP or passkey, relative to
+/// the API version prefix. Passkey login start never fails that way but
+/// stores a challenge per call, so it spends the IP budget too.
+const LIMITED_PATHS: &[&str] = &[
+    "/auth/login",
+    "/auth/register",
+    "/auth/password",
+    "/auth/recovery-codes",
+    "/auth/passkeys/register/start",
+    "/auth/passkeys/login/start",
+    "/auth/passkeys/login/finish",
+    "/account",
+];
+const FREE_FAILURES: u32 = 5;
+const BASE_LOCKOUT: Duration = Duration::from_secs(1);
+const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+const WINDOW: Duration = Duration::from_secs(60);
+const ATTEMPTS_PER_WINDOW: u32 = 30;
+/// Entries idle this long are dropped once the table reaches `MAX_TRACKED`,
+/// which is a hard cap.
+const FORGET_AFTER: Duration = Duration::from_secs(60 * 60);
+const MAX_TRACKED: usize = 10_000;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Whether `path` checks credentials, so a 401 there is a failed attempt.
+pub fn is_limited(path: &str) -> bool {
+    version::api_path(path).is_some_and(|path| LIMITED_PATHS.contains(&path))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Ip(IpAddr),
+    User(String),
+}
+
+#[derive(Debug)]
+struct Entry {
+    failures: u32,
+    locked_until: Option<Instant>,
+    window_start: Instant,
+    window_attempts: u32,
+    last_seen: Instant,
+}
+
+impl Entry {
+    fn new(now: Instant) -> Self {
+        Self {
+            failures: 0,
+            locked_until: None,
+            window_start: now,
+            window_attempts: 0,
+            last_seen: now,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AuthLimiter {
+    entries: Mutex<HashMap<Key, Entry>>,
+    /// Key on the `X-Forwarded-For` client rather than the proxy.
+    trust_proxy: bool,
+}
+
+impl AuthLimiter {
+    pub fn new(trust_proxy: bool) -> Self {
+        Self {
+            trust_proxy,
+            ..Self::default()
+        }
+    }
+
+    /// Count an attempt, returning how long to wait if any key is blocked.
+    /// IPs are checked first, so a throttled client doesn't touch the
+    /// username entries; those only exist after a failure.
+    fn check(&self, keys: &[Key], now: Instant) -> Option<Duration> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut wait = Duration::ZERO;
+        for key in keys.iter().filter(|k| matches!(k, Key::Ip(_))) {
+            let entry = entry(&mut entries, key, now);
+            if now.duration_since(entry.window_start) >= WINDOW {
+                entry.window_start = now;
+                entry.window_attempts = 0;
+            }
+            entry.window_attempts += 1;
+            if entry.window_attempts > ATTEMPTS_PER_WINDOW {
+                wait = wait.max(WINDOW.saturating_sub(now.duration_since(entry.window_start)));
+            }
+            if let Some(until) = entry.locked_until {
+                wait = wait.max(until.saturating_duration_since(now));
+            }
+        }
+        if !wait.is_zero() {
+            return Some(wait);
+        }
+
+        for key in keys.iter().filter(|k| matches!(k, Key::User(_))) {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.last_seen = now;
+                if let Some(until) = entry.locked_until {
+                    wait = wait.max(until.saturating_duration_since(now));
+                }
+            }
+        }
+
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    /// Record a failed attempt. Returns the failure count and any new lockout.
+    fn record_failure(&self, keys: &[Key], now: Instant) -> (u32, Option<Duration>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut failures, mut lockout) = (0, None);
+
+        for key in keys {
+            let entry = entry(&mut entries, key, now);
+            entry.failures += 1;
+            failures = failures.max(entry.failures);
+
+            if let Some(extra) = entry.failures.checked_sub(FREE_FAILURES + 1) {
+                let duration = BASE_LOCKOUT
+                    .checked_mul(2u32.saturating_pow(extra))
+                    .unwrap_or(MAX_LOCKOUT)
+                    .min(MAX_LOCKOUT);
+                entry.locked_until = Some(now + duration);
+                lockout = lockout.max(Some(duration));
+            }
+        }
+
+        (failures, lockout)
+    }
+
+    fn record_success(&self, keys: &[Key]) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        for key in keys {
+            if let Some(entry) = entries.get_mut(key) {
+                entry.failures = 0;
+                entry.locked_until = None;
+            }
+        }
+    }
+}
+
+/// The entry for `key`, created if needed. At `MAX_TRACKED` entries, idle
+/// ones are dropped first, then the least recently seen quarter, so the
+/// table stays bounded however many keys clients make up.
+fn entry<'a>(entries: &'a mut HashMap<Key, Entry>, key: &Key, now: Instant) -> &'a mut Entry {
+    if entries.len() >= MAX_TRACKED && !entries.contains_key(key) {
+        entries.retain(|_, e| now.duration_since(e.last_seen) < FORGET_AFTER);
+        if entries.len() >= MAX_TRACKED {
+            let mut seen: Vec<Instant> = entries.values().map(|e| e.last_seen).collect();
+            let cutoff = *seen.select_nth_unstable(MAX_TRACKED / 4).1;
+            entries.retain(|_, e| e.last_seen > cutoff);
+        }
+    }
+    let entry = entries
+        .entry(key.clone())
+        .or_insert_with(|| Entry::new(now));
+    entry.last_seen = now;
+    entry
+}
+
+#[derive(Deserialize)]
+struct UsernameField {
+    username: Option<String>,
+}
+
+/// Middleware applying `AuthLimiter` to `LIMITED_PATHS`.
+pub async fn limit_auth(
+    State(limiter): State<Arc<AuthLimiter>>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let path = request.uri().path().to_owned();
+    if !is_limited(&path) {
+        return Ok(next.run(request).await);
+    }
+
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|c| c.0.ip());
+    let ip = tls::client_ip(limiter.trust_proxy, request.headers(), peer);
+
+    // Buffer the body to key on the username it names
+    let (parts, body) = request.into_parts();
+    let bytes = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| Error::BadRequest("request body too large".into()))?;
+    let username = serde_json::from_slice::<UsernameField>(&bytes)
+        .ok()
+        .and_then(|b| b.username)
+        .map(|u| u.trim().to_owned())
+        .filter(|u| !u.is_empty());
+
+    let keys: Vec<Key> = ip
+        .map(Key::Ip)
+        .into_iter()
+        .chain(username.clone().map(Key::User))
+        .collect();
+
+    if let Some(wait) = limiter.check(&keys, Instant::now()) {
+        tracing::warn!(?ip, ?username, %path, "auth attempt throttled");
+        return Err(Error::TooManyRequests(wait.as_secs().max(1)));
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    match response.status() {
+        StatusCode::UNAUTHORIZED => {
+            let (failures, lockout) = limiter.record_failure(&keys, Instant::now());
+            tracing::warn!(?ip, ?username, %path, failures, "auth failure");
+            if let Some(lockout) = lockout {
+                tracing::warn!(?ip, ?username, "locked out for {}s", lockout.as_secs());
+            }
+        }
+        status if status.is_success() => limiter.record_success(&keys),
+        _ => {}
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{State as AppState, auth, db};
+    use axum::http::header;
+    use tower::ServiceExt;
+
+    fn user(name: &str) -> Vec<Key> {
+        vec![Key::User(name.into())]
+    }
+
+    #[test]
+    fn test_lockout_doubles_and_resets() {
+        let limiter = AuthLimiter::default();
+        let keys = user("alice");
+        let now = Instant::now();
+
+        for _ in 0..FREE_FAILURES {
+            assert_eq!(limiter.record_failure(&keys, now).1, None);
+        }
+        assert_eq!(limiter.check(&keys, now), None);
+
+        let lockouts: Vec<_> = (0..3)
+            .map(|_| limiter.record_failure(&keys, now).1.unwrap())
+            .collect();
+        assert_eq!(lockouts, [1, 2, 4].map(Duration::from_secs));
+        assert_eq!(limiter.check(&keys, now), Some(Duration::from_secs(4)));
+        assert_eq!(limiter.check(&keys, now + Duration::from_secs(4)), None);
+
+        // Other usernames are unaffected
+        assert_eq!(limiter.check(&user("bob"), now), None);
+
+        limiter.record_success(&keys);
+        assert_eq!(limiter.check(&keys, now), None);
+        assert_eq!(limiter.record_failure(&keys, now).1, None);
+    }
+
+    #[test]
+    fn test_lockout_is_capped() {
+        let limiter = AuthLimiter::default();
+        let keys = user("alice");
+        let now = Instant::now();
+
+        let last = (0..100)
+            .filter_map(|_| limiter.record_failure(&keys, now).1)
+            .last();
+        assert_eq!(last, Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn test_ip_attempt_window() {
+        let limiter = AuthLimiter::default();
+        let keys = vec![Key::Ip([127, 0, 0, 1].into())];
+        let now = Instant::now();
+
+        for _ in 0..ATTEMPTS_PER_WINDOW {
+            assert_eq!(limiter.check(&keys, now), None);
+        }
+        assert_eq!(limiter.check(&keys, now), Some(WINDOW));
+        assert_eq!(limiter.check(&keys, now + WINDOW), None);
+    }
+
+    #[test]
+    fn test_table_is_bounded() {
+        let limiter = AuthLimiter::default();
+        let ip = Key::Ip([127, 0, 0, 1].into());
+        let now = Instant::now();
+
+        // Attempts alone, throttled or not, never add usernames
+        for i in 0..2 * ATTEMPTS_PER_WINDOW {
+            limiter.check(&[ip.clone(), Key::User(format!("u{i}"))], now);
+        }
+        assert_eq!(limiter.entries.lock().unwrap().len(), 1);
+
+        // Failures do, up to the cap, evicting the least recently seen
+        for i in 0..MAX_TRACKED + 100 {
+            let at = now + Duration::from_millis(i as u64);
+            limiter.record_failure(&user(&format!("u{i}")), at);
+        }
+        let entries = limiter.entries.lock().unwrap();
+        assert!(entries.len() <= MAX_TRACKED);
+        assert!(entries.contains_key(&Key::User(format!("u{}", MAX_TRACKED + 99))));
+        assert!(!entries.contains_key(&ip));
+    }
+
+    #[tokio::test]
+    async fn test_passkey_login_start_is_budgeted() {
+        let app = crate::router(AppState::test(db::test_db().await));
+        let start = || {
+            let mut request = Request::post("/api/v1/auth/passkeys/login/start")
+                .body(Body::empty())
+                .unwrap();
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))));
+            app.clone().oneshot(request)
+        };
+
+        for _ in 0..ATTEMPTS_PER_WINDOW {
+            assert_eq!(start().await.unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(
+            start().await.unwrap().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_locks_out_after_failures() {
+        use totp_rs::{Algorithm, Secret, TOTP};
+
+        let secret = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+        let db = db::test_db().await;
+        db.connect()
+            .unwrap()
+            .execute(
+                "INSERT INTO users (username, password_hash, totp_secret, totp_enabled)
+                 VALUES ('u', ?, ?, 1)",
+                [auth::hash_password("p").unwrap(), secret.into()],
+            )
+            .await
+            .unwrap();
+        let app = crate::router(AppState::test(db));
+
+        let bytes = Secret::Encoded(secret.into()).to_bytes().unwrap();
+        let code = TOTP::new_unchecked(Algorithm::SHA1, 6, 0, 30, bytes, None, String::new())
+            .generate_current()
+            .unwrap();
+        let login = |password: &str| {
+            let body = serde_json::json!({ "username": "u", "password": password, "totp": code });
+            let request = Request::post("/api/v1/auth/login")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        for _ in 0..=FREE_FAILURES {
+            assert_eq!(
+                login("wrong").await.unwrap().status(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        // Locked out, even with the right password and code
+        let response = login("p").await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+}
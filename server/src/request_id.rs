@@ -0,0 +1,117 @@
+//! Request IDs.
+//!
+//! Every request gets an `X-Request-Id`: the client's own if it sent a
+//! sensible one (so a proxy's ID carries through), otherwise a fresh random
+//! one. The ID is recorded on the request span, echoed in the response
+//! headers and included in error bodies, so a user can quote it and we can
+//! find their request in the logs.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::auth::hex;
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Longer client IDs are replaced rather than logged.
+const MAX_LEN: usize = 64;
+
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// ID of the request being handled, for code without access to the request.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Middleware assigning the request ID. Must wrap the trace layer so the
+/// span can record the ID.
+pub async fn assign(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid(v))
+        .map_or_else(generate, str::to_owned);
+
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let mut response = CURRENT
+        .scope(RequestId(id.clone()), next.run(request))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+fn generate() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{State, db};
+    use axum::{body::Body, http::StatusCode, middleware};
+    use tower::ServiceExt;
+
+    async fn get(request_id: Option<&str>) -> (StatusCode, String, serde_json::Value) {
+        let app =
+            crate::router(State::test(db::test_db().await)).layer(middleware::from_fn(assign));
+        let mut request = Request::get("/api/v1/exercises/7");
+        if let Some(id) = request_id {
+            request = request.header(HEADER, id);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let header = response.headers()[HEADER].to_str().unwrap().to_owned();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, header, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_propagated_id() {
+        let (status, header, body) = get(Some("lb-1234.abcd")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(header, "lb-1234.abcd");
+        assert_eq!(body["request_id"], "lb-1234.abcd");
+    }
+
+    #[tokio::test]
+    async fn test_generated_id() {
+        let (_, header, body) = get(None).await;
+        assert_eq!(header.len(), 32);
+        assert_eq!(body["request_id"], header);
+
+        // Unusable client IDs are replaced
+        for bad in ["has space", "quote\"", &"x".repeat(MAX_LEN + 1)] {
+            let (_, header, _) = get(Some(bad)).await;
+            assert_ne!(header, bad);
+            assert!(is_valid(&header));
+        }
+    }
+}
@@ -1,117 +1,197 @@
 use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
 
 use axum::{
-    Json, Router,
-    extract::{Path, Query, State as AxumState},
+    Extension,
+    extract::{ConnectInfo, State as AxumState},
     http::{HeaderMap, StatusCode, header},
     response::IntoResponse,
-    routing::{delete, get, post, put},
 };
-use chrono::{DateTime, Duration, NaiveDate, Utc};
-use turso::{Connection, Value};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use turso::Value;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
 
 use ekman_core::{
-    self as core, Activity, ActivityDay, ActivityQuery, CompactSet, CreateExercise, DaySets,
-    Exercise, Graph, GraphQuery, LastSession, LoginInput, Metric, Owner, RegisterInput, Session,
-    SetData, SetInput, Template, TemplateExercise, TotpSetup, TotpVerify, UpdateExercise, User,
-    WeightEntry, WeightHistory, WeightInput, WorkoutSet,
+    self as core, SetData,
+    v1::{
+        Activity, ActivityDay, ActivityQuery, ApiToken, Backup, ChangePasswordInput, CompactSet,
+        CreateApiToken, CreateExercise, CreatedApiToken, DaySets, DeleteAccountInput, ErrorCode,
+        Exercise, ExerciseHistory, Graph, GraphPoint, GraphQuery, GraphSeries, HistoryQuery,
+        LastSession, Lift, LiftBest, LoginInput, MEASUREMENT_KINDS, MeasurementEntry,
+        MeasurementGraph, MeasurementHistory, MeasurementInput, MeasurementKind, MeasurementQuery,
+        Metric, MultiGraph, MultiGraphQuery, Overlay, Owner, Passkey, PasskeyAssertion,
+        PasskeyCreationOptions, PasskeyRegistration, PasskeyRegistrationStart,
+        PasskeyRequestOptions, Profile, RecoveryCodes, RegenerateRecoveryCodes, RegisterInput,
+        SeriesKind, Session, SessionInfo, SetInput, Sex, StrengthQuery, StrengthReport, Template,
+        TemplateExercise, TotpSetup, TotpVerify, UpdateExercise, User, WeightEntry, WeightHistory,
+        WeightInput, WeightTrend, WeightTrendQuery, WorkoutSet,
+    },
 };
 
-use crate::{Error, Result, State, auth, db};
+use crate::{
+    Error, Result, State,
+    auth::{self, AdminUser, AuthUser, SecureTransport, SessionUser},
+    db::{self, Conn, Db},
+    extract::{Json, Path, Query},
+    openapi, version,
+    webauthn::{self, Ceremony},
+};
 
-const MAX_GRAPH_POINTS: usize = 50;
+const MAX_GRAPH_EXERCISES: usize = 10;
+const MOVING_AVERAGE_WINDOW: usize = 5;
+const MAX_MOVING_AVERAGE_WINDOW: usize = 100;
 const ACTIVITY_DAYS: i64 = 21;
+const HISTORY_PAGE_SIZE: u32 = 20;
+const MAX_HISTORY_PAGE_SIZE: u32 = 100;
+
+/// Every API version under its prefix, with the OpenAPI document.
+pub fn api() -> OpenApiRouter<State> {
+    OpenApiRouter::with_openapi(openapi::ApiDoc::openapi()).nest(version::V1, v1())
+}
 
-pub fn api() -> Router<State> {
-    Router::new()
+/// Version 1 routes, relative to the version prefix.
+pub fn v1() -> OpenApiRouter<State> {
+    OpenApiRouter::new()
         // Auth
-        .route("/api/auth/register", post(register))
-        .route("/api/auth/login", post(login))
-        .route("/api/auth/logout", post(logout))
-        .route("/api/auth/me", get(me))
-        .route("/api/auth/totp/setup", get(totp_setup))
-        .route("/api/auth/totp/enable", post(totp_enable))
+        .routes(routes!(register))
+        .routes(routes!(login))
+        .routes(routes!(logout))
+        .routes(routes!(me))
+        .routes(routes!(totp_setup))
+        .routes(routes!(totp_enable))
+        .routes(routes!(change_password))
+        .routes(routes!(regenerate_recovery_codes))
+        .routes(routes!(list_api_tokens, create_api_token))
+        .routes(routes!(delete_api_token))
+        .routes(routes!(list_passkeys))
+        .routes(routes!(delete_passkey))
+        .routes(routes!(passkey_register_start))
+        .routes(routes!(passkey_register_finish))
+        .routes(routes!(passkey_login_start))
+        .routes(routes!(passkey_login_finish))
+        .routes(routes!(list_sessions, delete_all_sessions))
+        .routes(routes!(delete_session))
+        // Account
+        .routes(routes!(delete_account))
+        // Admin
+        .routes(routes!(create_backup))
         // Plans
-        .route("/api/plans", post(create_plan))
-        .route("/api/plans/daily", get(daily_plans))
-        .route(
-            "/api/plans/{template_id}/exercises",
-            post(add_exercise_to_plan),
-        )
-        .route(
-            "/api/plans/{template_id}/exercises/{exercise_id}",
-            delete(remove_exercise_from_plan),
-        )
+        .routes(routes!(create_plan))
+        .routes(routes!(daily_plans))
+        .routes(routes!(add_exercise_to_plan))
+        .routes(routes!(remove_exercise_from_plan))
         // Activity
-        .route("/api/activity/days", get(activity))
+        .routes(routes!(activity))
         // Exercises
-        .route("/api/exercises", get(list_exercises).post(create_exercise))
-        .route(
-            "/api/exercises/{id}",
-            get(get_exercise).patch(update_exercise),
-        )
-        .route("/api/exercises/{id}/archive", post(archive_exercise))
-        .route("/api/exercises/{id}/graph", get(exercise_graph))
+        .routes(routes!(list_exercises, create_exercise))
+        .routes(routes!(get_exercise, update_exercise))
+        .routes(routes!(archive_exercise))
+        .routes(routes!(exercise_graph))
+        .routes(routes!(exercise_history))
+        .routes(routes!(multi_graph))
         // Sets
-        .route(
-            "/api/days/{date}/exercises/{exercise_id}/sets",
-            get(day_sets),
-        )
-        .route(
-            "/api/days/{date}/exercises/{exercise_id}/sets/{set_number}",
-            put(upsert_set).delete(delete_set),
-        )
+        .routes(routes!(day_sets))
+        .routes(routes!(upsert_set, delete_set))
         // Weight
-        .route("/api/weight", get(weight_history))
-        .route(
-            "/api/weight/{date}",
-            get(get_weight).put(upsert_weight).delete(delete_weight),
-        )
+        .routes(routes!(weight_history))
+        .routes(routes!(weight_trend))
+        .routes(routes!(get_weight, upsert_weight, delete_weight))
+        // Measurements
+        .routes(routes!(measurement_history))
+        .routes(routes!(measurement_kinds))
+        .routes(routes!(measurement_graph))
+        .routes(routes!(
+            get_measurement,
+            upsert_measurement,
+            delete_measurement
+        ))
+        // Profile & reports
+        .routes(routes!(get_profile, update_profile))
+        .routes(routes!(strength_report))
 }
 
 // ============================================================================
 // Auth handlers
 // ============================================================================
 
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    security(()),
+    responses((status = OK, description = "Signed up and signed in; sets the session cookie", body = Session))
+)]
 async fn register(
+    SecureTransport(secure): SecureTransport,
     AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
     Json(input): Json<RegisterInput>,
 ) -> Result<impl IntoResponse> {
     let username = input.username.trim();
     if username.is_empty() {
-        return Err(Error::BadRequest("username required".into()));
+        return Err(Error::invalid(
+            "username",
+            ErrorCode::Required,
+            "username required",
+        ));
     }
     if input.password.is_empty() {
-        return Err(Error::BadRequest("password required".into()));
+        return Err(Error::invalid(
+            "password",
+            ErrorCode::Required,
+            "password required",
+        ));
     }
     if input.totp_secret.is_empty() || input.totp_code.is_empty() {
-        return Err(Error::BadRequest("totp required".into()));
+        return Err(Error::invalid(
+            "totp_code",
+            ErrorCode::Required,
+            "totp secret and code required",
+        ));
     }
 
-    auth::verify_totp(&input.totp_secret, &input.totp_code)?;
+    let step = auth::verify_totp(
+        &state.keys,
+        &input.totp_secret,
+        &input.totp_code,
+        state.config.auth.totp_skew,
+        None,
+        db::now(),
+    )?;
 
     let hash = auth::hash_password(&input.password)?;
-    let mut conn = state.db.connect()?;
-
-    let result = conn
+    let secret = state.keys.seal(&input.totp_secret)?;
+    let inserted = conn
         .execute(
-            "INSERT INTO users (username, password_hash, totp_secret) VALUES (?, ?, ?)",
-            (username, hash.as_str(), input.totp_secret.as_str()),
+            "INSERT INTO users (username, password_hash, totp_secret, totp_last_step)
+             VALUES (?, ?, ?, ?)
+             ON CONFLICT(username) DO NOTHING",
+            (username, hash.as_str(), secret.as_str(), step),
         )
-        .await;
-
-    if let Err(e) = result {
-        if e.to_string().contains("UNIQUE constraint") {
-            return Err(Error::BadRequest("username taken".into()));
-        }
-        return Err(e.into());
+        .await?;
+    if inserted == 0 {
+        return Err(Error::Conflict(ErrorCode::UsernameTaken));
     }
 
     let user_id = conn.last_insert_rowid();
-    let (token, expires_at) = auth::create_session(&mut conn, user_id).await?;
+    let recovery_codes = auth::replace_recovery_codes(&mut conn, user_id).await?;
+    let client = auth::ClientInfo::new(&headers, connect_info.map(|c| c.0.0));
+    let (token, expires_at) = auth::create_session(
+        &mut conn,
+        user_id,
+        &client,
+        state.config.auth.session_lifetime(),
+    )
+    .await?;
 
     let mut headers = HeaderMap::new();
-    headers.insert(header::SET_COOKIE, auth::session_cookie(&token, expires_at));
+    headers.insert(
+        header::SET_COOKIE,
+        auth::session_cookie(&token, expires_at, secure),
+    );
 
     Ok((
         headers,
@@ -119,29 +199,66 @@ async fn register(
             user_id,
             username: username.into(),
             expires_at,
+            recovery_codes,
         }),
     ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    security(()),
+    responses((status = OK, description = "Signed in; sets the session cookie", body = Session))
+)]
 async fn login(
+    SecureTransport(secure): SecureTransport,
     AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
     Json(input): Json<LoginInput>,
 ) -> Result<impl IntoResponse> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_by_username(&mut conn, &input.username).await?;
+    let user = match auth::user_by_username(&mut conn, &input.username).await {
+        Ok(user) => user,
+        Err(Error::Unauthorized) => {
+            auth::verify_password_dummy(&input.password);
+            return Err(Error::Unauthorized);
+        }
+        Err(e) => return Err(e),
+    };
 
     auth::verify_password(&input.password, &user.password_hash)?;
 
-    if !user.totp_enabled {
-        return Err(Error::Unauthorized);
-    }
     let code = input.totp.as_deref().ok_or(Error::Unauthorized)?;
-    auth::verify_totp(&user.totp_secret, code)?;
-
-    let (token, expires_at) = auth::create_session(&mut conn, user.id).await?;
+    let checked = if auth::is_totp_code(code) {
+        if !user.totp_enabled {
+            return Err(Error::Unauthorized);
+        }
+        auth::accept_totp(&mut conn, &state, &user, code).await
+    } else {
+        auth::use_recovery_code(&mut conn, user.id, code).await
+    };
+    // A wrong code is reported like a wrong password so it doesn't confirm the password
+    checked.map_err(|e| match e {
+        Error::TotpInvalid => Error::Unauthorized,
+        e => e,
+    })?;
+
+    let client = auth::ClientInfo::new(&headers, connect_info.map(|c| c.0.0));
+    let (token, expires_at) = auth::create_session(
+        &mut conn,
+        user.id,
+        &client,
+        state.config.auth.session_lifetime(),
+    )
+    .await?;
 
     let mut headers = HeaderMap::new();
-    headers.insert(header::SET_COOKIE, auth::session_cookie(&token, expires_at));
+    headers.insert(
+        header::SET_COOKIE,
+        auth::session_cookie(&token, expires_at, secure),
+    );
 
     Ok((
         headers,
@@ -149,12 +266,21 @@ async fn login(
             user_id: user.id,
             username: user.username,
             expires_at,
+            recovery_codes: Vec::new(),
         }),
     ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    security(()),
+    responses((status = NO_CONTENT, description = "Signed out; clears the session cookie"))
+)]
 async fn logout(
-    AxumState(state): AxumState<State>,
+    SecureTransport(secure): SecureTransport,
+    Db(mut conn): Db,
     headers: HeaderMap,
 ) -> Result<impl IntoResponse> {
     if let Some(token) = headers
@@ -165,38 +291,61 @@ async fn logout(
                 .find_map(|p| p.trim().strip_prefix("ekman_session="))
         })
     {
-        let mut conn = state.db.connect()?;
         auth::delete_session(&mut conn, token).await?;
     }
 
     let mut resp_headers = HeaderMap::new();
-    resp_headers.insert(header::SET_COOKIE, auth::clear_cookie());
+    resp_headers.insert(header::SET_COOKIE, auth::clear_cookie(secure));
     Ok((resp_headers, StatusCode::NO_CONTENT))
 }
 
-async fn me(AxumState(state): AxumState<State>, headers: HeaderMap) -> Result<Json<User>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
+#[utoipa::path(
+    get,
+    path = "/auth/me",
+    tag = "auth",
+    responses((status = OK, body = User))
+)]
+async fn me(
+    AxumState(state): AxumState<State>,
+    user: AuthUser,
+    SecureTransport(secure): SecureTransport,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    // Keep the browser cookie in step with the sliding server-side expiry
+    let mut resp_headers = HeaderMap::new();
+    if let Some(cookie) =
+        auth::refresh_cookie(&headers, state.config.auth.session_lifetime(), secure)
+    {
+        resp_headers.insert(header::SET_COOKIE, cookie);
+    }
 
-    Ok(Json(User {
-        user_id: user.id,
-        username: user.username,
-        totp_enabled: user.totp_enabled,
-    }))
+    Ok((
+        resp_headers,
+        Json(User {
+            user_id: user.id,
+            username: user.username,
+            totp_enabled: user.totp_enabled,
+        }),
+    ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/auth/totp/setup",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = TotpSetup))
+)]
 async fn totp_setup(
     AxumState(state): AxumState<State>,
-    headers: HeaderMap,
+    Db(conn): Db,
+    SessionUser(user): SessionUser,
 ) -> Result<Json<TotpSetup>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let (secret, url) = auth::generate_totp_secret(&user.username)?;
 
     conn.execute(
-        "UPDATE users SET totp_secret = ?, totp_enabled = 0 WHERE id = ?",
-        (secret.as_str(), user.id),
+        "UPDATE users SET totp_secret = ?, totp_enabled = 0, totp_last_step = NULL WHERE id = ?",
+        (state.keys.seal(&secret)?, user.id),
     )
     .await?;
 
@@ -206,33 +355,626 @@ async fn totp_setup(
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/totp/enable",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = RecoveryCodes))
+)]
 async fn totp_enable(
     AxumState(state): AxumState<State>,
-    headers: HeaderMap,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
     Json(input): Json<TotpVerify>,
-) -> Result<impl IntoResponse> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
-    auth::verify_totp(&user.totp_secret, &input.code)?;
+) -> Result<Json<RecoveryCodes>> {
+    auth::accept_totp(&mut conn, &state, &user, &input.code).await?;
 
     conn.execute("UPDATE users SET totp_enabled = 1 WHERE id = ?", [user.id])
         .await?;
 
+    let codes = auth::replace_recovery_codes(&mut conn, user.id).await?;
+    Ok(Json(RecoveryCodes { codes }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/recovery-codes",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = RecoveryCodes))
+)]
+async fn regenerate_recovery_codes(
+    AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
+    Json(input): Json<RegenerateRecoveryCodes>,
+) -> Result<Json<RecoveryCodes>> {
+    auth::verify_password(&input.password, &user.password_hash)?;
+    auth::accept_totp(&mut conn, &state, &user, &input.totp).await?;
+
+    let codes = auth::replace_recovery_codes(&mut conn, user.id).await?;
+    Ok(Json(RecoveryCodes { codes }))
+}
+
+// ============================================================================
+// API tokens
+// ============================================================================
+
+const MAX_API_TOKEN_NAME_LEN: usize = 64;
+const MAX_API_TOKEN_DAYS: u32 = 3650;
+
+#[utoipa::path(
+    get,
+    path = "/auth/tokens",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = Vec<ApiToken>))
+)]
+async fn list_api_tokens(
+    Db(conn): Db,
+    SessionUser(user): SessionUser,
+) -> Result<Json<Vec<ApiToken>>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, name, scope, created_at, last_used_at, expires_at FROM api_tokens
+             WHERE user_id = ? ORDER BY id",
+            [user.id],
+        )
+        .await?;
+
+    let mut tokens = Vec::new();
+    while let Some(row) = rows.next().await? {
+        tokens.push(ApiToken {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            scope: auth::parse_token_scope(&row.get::<String>(2)?)?,
+            created_at: db::parse_timestamp(&row.get::<String>(3)?)?,
+            last_used_at: row
+                .get::<Option<String>>(4)?
+                .map(|s| db::parse_timestamp(&s))
+                .transpose()?,
+            expires_at: row
+                .get::<Option<String>>(5)?
+                .map(|s| db::parse_timestamp(&s))
+                .transpose()?,
+        });
+    }
+
+    Ok(Json(tokens))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/tokens",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, description = "The secret is only returned here", body = CreatedApiToken))
+)]
+async fn create_api_token(
+    AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
+    Json(input): Json<CreateApiToken>,
+) -> Result<Json<CreatedApiToken>> {
+    let name = input.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_API_TOKEN_NAME_LEN {
+        return Err(Error::invalid(
+            "name",
+            ErrorCode::OutOfRange,
+            format!("name must be 1-{MAX_API_TOKEN_NAME_LEN} characters"),
+        ));
+    }
+    if input
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_API_TOKEN_DAYS).contains(&days))
+    {
+        return Err(Error::invalid(
+            "expires_in_days",
+            ErrorCode::OutOfRange,
+            format!("expires_in_days must be 1-{MAX_API_TOKEN_DAYS}"),
+        ));
+    }
+
+    auth::verify_password(&input.password, &user.password_hash)?;
+    auth::accept_totp(&mut conn, &state, &user, &input.totp).await?;
+
+    let token = auth::generate_api_token();
+    let created_at = db::now();
+    let expires_at = input
+        .expires_in_days
+        .map(|days| created_at + Duration::days(days.into()));
+
+    conn.execute(
+        "INSERT INTO api_tokens (user_id, name, token_hash, scope, created_at, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?)",
+        (
+            user.id,
+            name,
+            auth::hash_token(&token),
+            auth::token_scope_as_str(input.scope),
+            db::timestamp(created_at),
+            expires_at.map(db::timestamp),
+        ),
+    )
+    .await?;
+
+    tracing::info!(user_id = user.id, scope = ?input.scope, "api token created");
+
+    Ok(Json(CreatedApiToken {
+        info: ApiToken {
+            id: conn.last_insert_rowid(),
+            name: name.into(),
+            scope: input.scope,
+            created_at,
+            last_used_at: None,
+            expires_at,
+        },
+        token,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/tokens/{id}",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = NO_CONTENT))
+)]
+async fn delete_api_token(
+    Db(conn): Db,
+    Path(id): Path<i64>,
+    SessionUser(user): SessionUser,
+) -> Result<StatusCode> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM api_tokens WHERE id = ? AND user_id = ?",
+            (id, user.id),
+        )
+        .await?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound(ErrorCode::ApiTokenNotFound));
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
 // ============================================================================
-// Plans
+// Passkeys
 // ============================================================================
 
-async fn daily_plans(
+const MAX_PASSKEY_NAME_LEN: usize = 64;
+
+#[utoipa::path(
+    get,
+    path = "/auth/passkeys",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = Vec<Passkey>))
+)]
+async fn list_passkeys(Db(conn): Db, SessionUser(user): SessionUser) -> Result<Json<Vec<Passkey>>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, name, created_at, last_used_at FROM passkeys
+             WHERE user_id = ? ORDER BY id",
+            [user.id],
+        )
+        .await?;
+
+    let mut passkeys = Vec::new();
+    while let Some(row) = rows.next().await? {
+        passkeys.push(Passkey {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            created_at: db::parse_timestamp(&row.get::<String>(2)?)?,
+            last_used_at: row
+                .get::<Option<String>>(3)?
+                .map(|s| db::parse_timestamp(&s))
+                .transpose()?,
+        });
+    }
+
+    Ok(Json(passkeys))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/passkeys/{id}",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = NO_CONTENT))
+)]
+async fn delete_passkey(
+    Db(conn): Db,
+    Path(id): Path<i64>,
+    SessionUser(user): SessionUser,
+) -> Result<StatusCode> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM passkeys WHERE id = ? AND user_id = ?",
+            (id, user.id),
+        )
+        .await?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound(ErrorCode::PasskeyNotFound));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/passkeys/register/start",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = PasskeyCreationOptions))
+)]
+async fn passkey_register_start(
+    AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
+    Json(input): Json<PasskeyRegistrationStart>,
+) -> Result<Json<PasskeyCreationOptions>> {
+    auth::verify_password(&input.password, &user.password_hash)?;
+    auth::accept_totp(&mut conn, &state, &user, &input.totp).await?;
+
+    let mut rows = conn
+        .query(
+            "SELECT credential_id FROM passkeys WHERE user_id = ?",
+            [user.id],
+        )
+        .await?;
+    let mut exclude_credentials = Vec::new();
+    while let Some(row) = rows.next().await? {
+        exclude_credentials.push(row.get(0)?);
+    }
+    drop(rows);
+
+    let challenge = webauthn::create_challenge(&conn, Some(user.id), Ceremony::Register).await?;
+
+    Ok(Json(PasskeyCreationOptions {
+        challenge,
+        rp_id: state.rp.id.clone(),
+        rp_name: state.rp.name.clone(),
+        user_id: webauthn::user_handle(user.id),
+        user_name: user.username,
+        exclude_credentials,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/passkeys/register/finish",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = Passkey))
+)]
+async fn passkey_register_finish(
+    AxumState(state): AxumState<State>,
+    Db(conn): Db,
+    SessionUser(user): SessionUser,
+    Json(input): Json<PasskeyRegistration>,
+) -> Result<Json<Passkey>> {
+    let name = input.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_PASSKEY_NAME_LEN {
+        return Err(Error::invalid(
+            "name",
+            ErrorCode::OutOfRange,
+            format!("name must be 1-{MAX_PASSKEY_NAME_LEN} characters"),
+        ));
+    }
+
+    let passkey = webauthn::finish_registration(&conn, &state.rp, user.id, &input).await?;
+    let created_at = db::now();
+
+    let inserted = conn
+        .execute(
+            "INSERT INTO passkeys (user_id, credential_id, public_key, sign_count, name, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(credential_id) DO NOTHING",
+            (
+                user.id,
+                passkey.credential_id.as_str(),
+                passkey.public_key.as_str(),
+                passkey.sign_count,
+                name,
+                db::timestamp(created_at),
+            ),
+        )
+        .await?;
+    if inserted == 0 {
+        return Err(Error::Conflict(ErrorCode::PasskeyExists));
+    }
+
+    tracing::info!(user_id = user.id, "passkey registered");
+
+    Ok(Json(Passkey {
+        id: conn.last_insert_rowid(),
+        name: name.into(),
+        created_at,
+        last_used_at: None,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/passkeys/login/start",
+    tag = "auth",
+    security(()),
+    responses((status = OK, body = PasskeyRequestOptions))
+)]
+async fn passkey_login_start(
+    AxumState(state): AxumState<State>,
+    Db(conn): Db,
+) -> Result<Json<PasskeyRequestOptions>> {
+    let challenge = webauthn::create_challenge(&conn, None, Ceremony::Login).await?;
+
+    Ok(Json(PasskeyRequestOptions {
+        challenge,
+        rp_id: state.rp.id.clone(),
+    }))
+}
+
+/// Passkey alternative to `login`; replaces both password and TOTP.
+#[utoipa::path(
+    post,
+    path = "/auth/passkeys/login/finish",
+    tag = "auth",
+    security(()),
+    responses((status = OK, description = "Signed in; sets the session cookie", body = Session))
+)]
+async fn passkey_login_finish(
+    SecureTransport(secure): SecureTransport,
+    AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    Json(input): Json<PasskeyAssertion>,
+) -> Result<impl IntoResponse> {
+    let user_id = webauthn::finish_login(&conn, &state.rp, &input).await?;
+    let user = auth::user_by_id(&mut conn, user_id).await?;
+
+    let client = auth::ClientInfo::new(&headers, connect_info.map(|c| c.0.0));
+    let (token, expires_at) = auth::create_session(
+        &mut conn,
+        user.id,
+        &client,
+        state.config.auth.session_lifetime(),
+    )
+    .await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::SET_COOKIE,
+        auth::session_cookie(&token, expires_at, secure),
+    );
+
+    Ok((
+        headers,
+        Json(Session {
+            user_id: user.id,
+            username: user.username,
+            expires_at,
+            recovery_codes: Vec::new(),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = NO_CONTENT, description = "Password changed; other sessions are signed out"))
+)]
+async fn change_password(
     AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
     headers: HeaderMap,
-) -> Result<Json<Vec<Template>>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
+    Json(input): Json<ChangePasswordInput>,
+) -> Result<impl IntoResponse> {
+    if input.new_password.is_empty() {
+        return Err(Error::invalid(
+            "new_password",
+            ErrorCode::Required,
+            "password required",
+        ));
+    }
+
+    auth::verify_password(&input.old_password, &user.password_hash)?;
+    auth::accept_totp(&mut conn, &state, &user, &input.totp).await?;
+
+    let hash = auth::hash_password(&input.new_password)?;
+    conn.execute(
+        "UPDATE users SET password_hash = ? WHERE id = ?",
+        (hash.as_str(), user.id),
+    )
+    .await?;
+
+    // Keep the caller signed in, log out everywhere else
+    let token = auth::extract_token(&headers).ok_or(Error::Unauthorized)?;
+    auth::delete_other_sessions(&mut conn, user.id, &token).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = OK, body = Vec<SessionInfo>))
+)]
+async fn list_sessions(
+    Db(conn): Db,
+    SessionUser(user): SessionUser,
+) -> Result<Json<Vec<SessionInfo>>> {
+    let mut rows = conn
+        .query(
+            "SELECT id, created_at, last_used_at, expires_at, user_agent, ip
+             FROM sessions
+             WHERE user_id = ? AND expires_at >= ?
+             ORDER BY COALESCE(last_used_at, created_at) DESC",
+            (user.id, db::timestamp(db::now())),
+        )
+        .await?;
+
+    let mut sessions = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let id: i64 = row.get(0)?;
+        sessions.push(SessionInfo {
+            id,
+            created_at: db::parse_timestamp(&row.get::<String>(1)?)?,
+            last_used_at: row
+                .get::<Option<String>>(2)?
+                .map(|s| db::parse_timestamp(&s))
+                .transpose()?,
+            expires_at: db::parse_timestamp(&row.get::<String>(3)?)?,
+            user_agent: row.get(4)?,
+            ip: row.get(5)?,
+            current: user.session_id == Some(id),
+        });
+    }
+
+    Ok(Json(sessions))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{id}",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = NO_CONTENT))
+)]
+async fn delete_session(
+    SecureTransport(secure): SecureTransport,
+    Db(conn): Db,
+    Path(id): Path<i64>,
+    SessionUser(user): SessionUser,
+) -> Result<impl IntoResponse> {
+    let deleted = conn
+        .execute(
+            "DELETE FROM sessions WHERE id = ? AND user_id = ?",
+            (id, user.id),
+        )
+        .await?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound(ErrorCode::SessionNotFound));
+    }
+
+    let mut resp_headers = HeaderMap::new();
+    if user.session_id == Some(id) {
+        resp_headers.insert(header::SET_COOKIE, auth::clear_cookie(secure));
+    }
+    Ok((resp_headers, StatusCode::NO_CONTENT))
+}
+
+/// Log out everywhere, including this session.
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions",
+    tag = "auth",
+    security(("session" = [])),
+    responses((status = NO_CONTENT, description = "Every session, including this one, is signed out"))
+)]
+async fn delete_all_sessions(
+    SecureTransport(secure): SecureTransport,
+    Db(conn): Db,
+    SessionUser(user): SessionUser,
+) -> Result<impl IntoResponse> {
+    conn.execute("DELETE FROM sessions WHERE user_id = ?", [user.id])
+        .await?;
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(header::SET_COOKIE, auth::clear_cookie(secure));
+    Ok((resp_headers, StatusCode::NO_CONTENT))
+}
 
+// ============================================================================
+// Account
+// ============================================================================
+
+/// Delete the user and everything they own. Child rows are removed explicitly
+/// since `workout_sets` and `template_exercises` don't cascade from
+/// `exercises`.
+#[utoipa::path(
+    delete,
+    path = "/account",
+    tag = "account",
+    security(("session" = [])),
+    responses((status = NO_CONTENT, description = "The user and all of their data are gone"))
+)]
+async fn delete_account(
+    SecureTransport(secure): SecureTransport,
+    AxumState(state): AxumState<State>,
+    Db(mut conn): Db,
+    SessionUser(user): SessionUser,
+    Json(input): Json<DeleteAccountInput>,
+) -> Result<impl IntoResponse> {
+    auth::verify_password(&input.password, &user.password_hash)?;
+    auth::accept_totp(&mut conn, &state, &user, &input.totp).await?;
+
+    let tx = conn.transaction().await?;
+    // turso can't run a subquery in a DELETE's WHERE, so read the owned ids
+    // and clear each child table in one statement
+    for (owned, delete) in [
+        (
+            "SELECT id FROM exercises WHERE user_id = ?",
+            "DELETE FROM workout_sets WHERE exercise_id IN",
+        ),
+        (
+            "SELECT id FROM workout_templates WHERE user_id = ?",
+            "DELETE FROM template_exercises WHERE template_id IN",
+        ),
+    ] {
+        let mut rows = tx.query(owned, [user.id]).await?;
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await? {
+            ids.push(row.get_value(0)?);
+        }
+        if !ids.is_empty() {
+            let placeholders = vec!["?"; ids.len()].join(", ");
+            tx.execute(&format!("{delete} ({placeholders})"), ids)
+                .await?;
+        }
+    }
+    for sql in [
+        "DELETE FROM workout_templates WHERE user_id = ?",
+        "DELETE FROM user_profiles WHERE user_id = ?",
+        "DELETE FROM recovery_codes WHERE user_id = ?",
+        "DELETE FROM passkeys WHERE user_id = ?",
+        "DELETE FROM api_tokens WHERE user_id = ?",
+        "DELETE FROM webauthn_challenges WHERE user_id = ?",
+        "DELETE FROM exercises WHERE user_id = ?",
+        "DELETE FROM weight_entries WHERE user_id = ?",
+        "DELETE FROM measurement_entries WHERE user_id = ?",
+        "DELETE FROM sessions WHERE user_id = ?",
+        "DELETE FROM users WHERE id = ?",
+    ] {
+        tx.execute(sql, [user.id]).await?;
+    }
+    tx.commit().await?;
+
+    tracing::info!(user_id = user.id, "account deleted");
+
+    let mut resp_headers = HeaderMap::new();
+    resp_headers.insert(header::SET_COOKIE, auth::clear_cookie(secure));
+    Ok((resp_headers, StatusCode::NO_CONTENT))
+}
+
+// ============================================================================
+// Plans
+// ============================================================================
+
+#[utoipa::path(
+    get,
+    path = "/plans/daily",
+    tag = "plans",
+    responses((status = OK, body = Vec<Template>))
+)]
+async fn daily_plans(Db(conn): Db, user: AuthUser) -> Result<Json<Vec<Template>>> {
     let mut rows = conn
         .query(
             "SELECT wt.id, wt.name, wt.day_of_week, te.exercise_id, te.target_sets, e.name
@@ -287,36 +1029,37 @@ async fn daily_plans(
     Ok(Json(result))
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
 struct CreatePlanInput {
     name: String,
     day_of_week: Option<i32>,
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, ToSchema)]
 struct CreatedPlan {
     id: i64,
     name: String,
     day_of_week: Option<i32>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/plans",
+    tag = "plans",
+    responses((status = CREATED, body = CreatedPlan))
+)]
 async fn create_plan(
-    AxumState(state): AxumState<State>,
-    headers: HeaderMap,
+    Db(conn): Db,
+    user: AuthUser,
     Json(input): Json<CreatePlanInput>,
 ) -> Result<impl IntoResponse> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Check if a plan for this weekday already exists
     if let Some(day) = input.day_of_week {
         let mut stmt = conn
             .prepare("SELECT id FROM workout_templates WHERE user_id = ? AND day_of_week = ?")
             .await?;
         if stmt.query_row((user.id, day)).await.is_ok() {
-            return Err(Error::BadRequest(
-                "A plan for this day already exists".into(),
-            ));
+            return Err(Error::Conflict(ErrorCode::PlanExistsForDay));
         }
     }
 
@@ -339,7 +1082,7 @@ async fn create_plan(
 }
 
 async fn load_last_sessions(
-    conn: &Connection,
+    conn: &Conn,
     user_id: i64,
     exercise_ids: &[i64],
 ) -> Result<HashMap<i64, LastSession>> {
@@ -374,7 +1117,7 @@ async fn load_last_sessions(
 }
 
 async fn load_day_sets(
-    conn: &Connection,
+    conn: &Conn,
     exercise_id: i64,
     user_id: i64,
     day: &str,
@@ -400,14 +1143,45 @@ async fn load_day_sets(
     Ok(sets)
 }
 
+// ============================================================================
+// Admin
+// ============================================================================
+
+/// Take a database snapshot now, outside the backup schedule.
+#[utoipa::path(
+    post,
+    path = "/admin/backup",
+    tag = "admin",
+    responses((status = OK, body = Backup))
+)]
+async fn create_backup(
+    AxumState(state): AxumState<State>,
+    AdminUser(admin): AdminUser,
+) -> Result<Json<Backup>> {
+    let backups = state
+        .backups
+        .as_ref()
+        .ok_or_else(|| Error::BadRequest("backups are not configured (backup.dir)".into()))?;
+    let backup = backups.create(&state.db).await?;
+    tracing::info!(file = %backup.file, by = %admin.username, "backup written");
+    Ok(Json(backup))
+}
+
 // ============================================================================
 // Activity
 // ============================================================================
 
+#[utoipa::path(
+    get,
+    path = "/activity/days",
+    tag = "activity",
+    params(ActivityQuery),
+    responses((status = OK, body = Activity))
+)]
 async fn activity(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Query(query): Query<ActivityQuery>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<Activity>> {
     let end = query.end.unwrap_or_else(db::now);
     let start = query
@@ -418,12 +1192,13 @@ async fn activity(
     let end_date = end.date_naive();
 
     if start_date > end_date {
-        return Err(Error::BadRequest("start must be before end".into()));
+        return Err(Error::invalid(
+            "start",
+            ErrorCode::OutOfRange,
+            "start must be before end",
+        ));
     }
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Query counts
     let start_ts = start_date.and_hms_opt(0, 0, 0).unwrap();
     let end_ts = end_date.and_hms_opt(23, 59, 59).unwrap();
@@ -470,13 +1245,13 @@ async fn activity(
 // Exercises
 // ============================================================================
 
-async fn list_exercises(
-    AxumState(state): AxumState<State>,
-    headers: HeaderMap,
-) -> Result<Json<Vec<Exercise>>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
+#[utoipa::path(
+    get,
+    path = "/exercises",
+    tag = "exercises",
+    responses((status = OK, body = Vec<Exercise>))
+)]
+async fn list_exercises(Db(conn): Db, user: AuthUser) -> Result<Json<Vec<Exercise>>> {
     let mut rows = conn
         .query(
             "SELECT id, name, description, archived, user_id
@@ -505,29 +1280,32 @@ async fn list_exercises(
     Ok(Json(exercises))
 }
 
-async fn get_exercise(
-    AxumState(state): AxumState<State>,
-    Path(id): Path<i64>,
-    headers: HeaderMap,
-) -> Result<Json<Exercise>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
+#[utoipa::path(
+    get,
+    path = "/exercises/{id}",
+    tag = "exercises",
+    responses((status = OK, body = Exercise))
+)]
+async fn get_exercise(Db(conn): Db, Path(id): Path<i64>, user: AuthUser) -> Result<Json<Exercise>> {
     Ok(Json(fetch_exercise(&conn, id, user.id).await?))
 }
 
+#[utoipa::path(
+    post,
+    path = "/exercises",
+    tag = "exercises",
+    responses((status = OK, body = Exercise))
+)]
 async fn create_exercise(
-    AxumState(state): AxumState<State>,
-    headers: HeaderMap,
+    Db(conn): Db,
+    user: AuthUser,
     Json(input): Json<CreateExercise>,
 ) -> Result<Json<Exercise>> {
     let name = input.name.trim();
     if name.is_empty() {
-        return Err(Error::BadRequest("name required".into()));
+        return Err(Error::invalid("name", ErrorCode::Required, "name required"));
     }
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     conn.execute(
         "INSERT INTO exercises (user_id, name, description) VALUES (?, ?, ?)",
         (user.id, name, input.description.as_deref()),
@@ -543,19 +1321,22 @@ async fn create_exercise(
     }))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/exercises/{id}",
+    tag = "exercises",
+    responses((status = OK, body = Exercise))
+)]
 async fn update_exercise(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(id): Path<i64>,
-    headers: HeaderMap,
+    user: AuthUser,
     Json(input): Json<UpdateExercise>,
 ) -> Result<Json<Exercise>> {
     if input.name.is_none() && input.description.is_none() && input.archived.is_none() {
         return Err(Error::BadRequest("no fields to update".into()));
     }
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let mut sql = String::from("UPDATE exercises SET ");
     let mut params: Vec<Value> = Vec::new();
     let mut parts = Vec::new();
@@ -580,20 +1361,23 @@ async fn update_exercise(
 
     let updated = conn.execute(&sql, params).await?;
     if updated == 0 {
-        return Err(Error::NotFound("exercise".into()));
+        return Err(Error::NotFound(ErrorCode::ExerciseNotFound));
     }
 
     Ok(Json(fetch_exercise(&conn, id, user.id).await?))
 }
 
+#[utoipa::path(
+    post,
+    path = "/exercises/{id}/archive",
+    tag = "exercises",
+    responses((status = OK, body = Exercise))
+)]
 async fn archive_exercise(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(id): Path<i64>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<Exercise>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let updated = conn
         .execute(
             "UPDATE exercises SET archived = 1 WHERE id = ? AND user_id = ?",
@@ -602,31 +1386,141 @@ async fn archive_exercise(
         .await?;
 
     if updated == 0 {
-        return Err(Error::NotFound("exercise".into()));
+        return Err(Error::NotFound(ErrorCode::ExerciseNotFound));
     }
 
     Ok(Json(fetch_exercise(&conn, id, user.id).await?))
 }
 
+#[utoipa::path(
+    get,
+    path = "/exercises/{id}/graph",
+    tag = "exercises",
+    params(GraphQuery),
+    responses((status = OK, body = Graph))
+)]
 async fn exercise_graph(
     AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(id): Path<i64>,
     Query(query): Query<GraphQuery>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<Graph>> {
     let metric = query.metric.unwrap_or(Metric::MaxWeight);
 
     if let (Some(start), Some(end)) = (query.start, query.end)
         && start > end
     {
-        return Err(Error::BadRequest("start must be before end".into()));
+        return Err(Error::invalid(
+            "start",
+            ErrorCode::OutOfRange,
+            "start must be before end",
+        ));
     }
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
     let exercise = fetch_exercise(&conn, id, user.id).await?;
+    let sets = load_set_data(&conn, id, user.id, query.start, query.end).await?;
+
+    let points = core::build_graph(sets, metric, state.config.graphs.max_points);
+
+    Ok(Json(Graph {
+        exercise_id: id,
+        exercise_name: exercise.name,
+        points,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/graphs",
+    tag = "exercises",
+    params(MultiGraphQuery),
+    responses((status = OK, body = MultiGraph))
+)]
+async fn multi_graph(
+    AxumState(state): AxumState<State>,
+    Db(conn): Db,
+    Query(query): Query<MultiGraphQuery>,
+    user: AuthUser,
+) -> Result<Json<MultiGraph>> {
+    let ids = query
+        .parse_exercise_ids()
+        .map_err(|e| Error::invalid("exercise_ids", ErrorCode::InvalidFormat, e))?;
+    let metrics = query
+        .parse_metrics()
+        .map_err(|e| Error::invalid("metrics", ErrorCode::InvalidFormat, e))?;
+
+    if ids.is_empty() || ids.len() > MAX_GRAPH_EXERCISES {
+        return Err(Error::invalid(
+            "exercise_ids",
+            ErrorCode::OutOfRange,
+            format!("between 1 and {MAX_GRAPH_EXERCISES} exercise ids required"),
+        ));
+    }
+    if let (Some(start), Some(end)) = (query.start, query.end)
+        && start > end
+    {
+        return Err(Error::invalid(
+            "start",
+            ErrorCode::OutOfRange,
+            "start must be before end",
+        ));
+    }
 
-    // Build query
+    let window = query.window.unwrap_or(MOVING_AVERAGE_WINDOW);
+    if !(1..=MAX_MOVING_AVERAGE_WINDOW).contains(&window) {
+        return Err(Error::invalid(
+            "window",
+            ErrorCode::OutOfRange,
+            format!("window must be 1-{MAX_MOVING_AVERAGE_WINDOW} sessions"),
+        ));
+    }
+
+    let mut series = Vec::new();
+    for id in ids {
+        let exercise = fetch_exercise(&conn, id, user.id).await?;
+        let sets = load_set_data(&conn, id, user.id, query.start, query.end).await?;
+
+        for &metric in &metrics {
+            let points = core::build_graph(sets.clone(), metric, state.config.graphs.max_points);
+            let overlay = query.overlay.map(|overlay| {
+                let kind = match overlay {
+                    Overlay::MovingAverage => SeriesKind::MovingAverage,
+                    Overlay::Trend => SeriesKind::Trend,
+                };
+                (kind, core::overlay_series(overlay, &points, window))
+            });
+
+            series.push(GraphSeries {
+                exercise_id: id,
+                exercise_name: exercise.name.clone(),
+                metric,
+                kind: SeriesKind::Raw,
+                points,
+            });
+
+            if let Some((kind, points)) = overlay {
+                series.push(GraphSeries {
+                    exercise_id: id,
+                    exercise_name: exercise.name.clone(),
+                    metric,
+                    kind,
+                    points,
+                });
+            }
+        }
+    }
+
+    Ok(Json(MultiGraph { series }))
+}
+
+async fn load_set_data(
+    conn: &Conn,
+    exercise_id: i64,
+    user_id: i64,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> Result<Vec<SetData>> {
     let mut sql = String::from(
         "SELECT ws.day, ws.weight_kg, ws.reps
          FROM workout_sets ws
@@ -634,44 +1528,118 @@ async fn exercise_graph(
          WHERE ws.exercise_id = ? AND (e.user_id = ? OR e.user_id IS NULL)",
     );
 
-    let mut params: Vec<Value> = vec![id.into(), user.id.into()];
+    let mut params: Vec<Value> = vec![exercise_id.into(), user_id.into()];
 
-    if let Some(start) = query.start {
+    if let Some(start) = start {
         sql.push_str(" AND ws.completed_at >= ?");
         params.push(db::timestamp(start).into());
     }
-    if let Some(end) = query.end {
+    if let Some(end) = end {
         sql.push_str(" AND ws.completed_at <= ?");
         params.push(db::timestamp(end).into());
     }
 
-    sql.push_str(" ORDER BY ws.completed_at");
+    sql.push_str(" ORDER BY ws.completed_at");
+
+    let mut rows = conn.query(&sql, params).await?;
+    let mut sets = Vec::new();
+
+    while let Some(row) = rows.next().await? {
+        let day: String = row.get(0)?;
+        let date = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+            .map_err(|e| Error::Internal(format!("bad date: {e}")))?;
+
+        sets.push(SetData {
+            date,
+            weight: row.get(1)?,
+            reps: row.get::<i64>(2)? as i32,
+        });
+    }
+
+    Ok(sets)
+}
+
+#[utoipa::path(
+    get,
+    path = "/exercises/{id}/history",
+    tag = "exercises",
+    params(HistoryQuery),
+    responses((status = OK, body = ExerciseHistory))
+)]
+async fn exercise_history(
+    Db(conn): Db,
+    Path(id): Path<i64>,
+    Query(query): Query<HistoryQuery>,
+    user: AuthUser,
+) -> Result<Json<ExerciseHistory>> {
+    let limit = query
+        .limit
+        .unwrap_or(HISTORY_PAGE_SIZE)
+        .clamp(1, MAX_HISTORY_PAGE_SIZE);
+
+    let exercise = fetch_exercise(&conn, id, user.id).await?;
+
+    // Page over distinct days, fetching one extra to know if there is more
+    let mut sql = String::from("SELECT DISTINCT day FROM workout_sets WHERE exercise_id = ?");
+    let mut params: Vec<Value> = vec![id.into()];
+
+    if let Some(before) = query.before {
+        sql.push_str(" AND day < ?");
+        params.push(before.to_string().into());
+    }
+
+    sql.push_str(" ORDER BY day DESC LIMIT ?");
+    params.push((limit as i64 + 1).into());
 
     let mut rows = conn.query(&sql, params).await?;
-    let mut sets = Vec::new();
-
+    let mut days = Vec::new();
     while let Some(row) = rows.next().await? {
-        let day: String = row.get(0)?;
-        let date = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
-            .map_err(|e| Error::Internal(format!("bad date: {e}")))?;
-
-        sets.push(SetData {
-            date,
-            weight: row.get(1)?,
-            reps: row.get::<i64>(2)? as i32,
-        });
+        days.push(parse_day(&row.get::<String>(0)?)?);
     }
 
-    let points = core::build_graph(sets, metric, MAX_GRAPH_POINTS);
+    let has_more = days.len() > limit as usize;
+    days.truncate(limit as usize);
+
+    let mut sessions = Vec::with_capacity(days.len());
+    if let (Some(newest), Some(oldest)) = (days.first(), days.last()) {
+        let mut rows = conn
+            .query(
+                "SELECT day, weight_kg, reps
+                 FROM workout_sets
+                 WHERE exercise_id = ? AND day >= ? AND day <= ?
+                 ORDER BY day DESC, set_number",
+                (id, oldest.to_string(), newest.to_string()),
+            )
+            .await?;
+
+        let mut by_day: BTreeMap<NaiveDate, Vec<CompactSet>> = BTreeMap::new();
+        while let Some(row) = rows.next().await? {
+            by_day
+                .entry(parse_day(&row.get::<String>(0)?)?)
+                .or_default()
+                .push(CompactSet {
+                    weight: row.get(1)?,
+                    reps: row.get::<i64>(2)? as i32,
+                });
+        }
 
-    Ok(Json(Graph {
+        sessions.extend(
+            by_day
+                .into_iter()
+                .rev()
+                .map(|(date, sets)| core::history_session(date, sets)),
+        );
+    }
+
+    Ok(Json(ExerciseHistory {
         exercise_id: id,
         exercise_name: exercise.name,
-        points,
+        next_before: if has_more { days.last().copied() } else { None },
+        sessions,
     }))
 }
 
-async fn fetch_exercise(conn: &Connection, id: i64, user_id: i64) -> Result<Exercise> {
+async fn fetch_exercise(conn: &Conn, id: i64, user_id: i64) -> Result<Exercise> {
     let mut stmt = conn
         .prepare(
             "SELECT id, name, description, archived, user_id
@@ -683,7 +1651,7 @@ async fn fetch_exercise(conn: &Connection, id: i64, user_id: i64) -> Result<Exer
     let row = stmt
         .query_row((id, user_id))
         .await
-        .map_err(|_| Error::NotFound("exercise".into()))?;
+        .map_err(|_| Error::NotFound(ErrorCode::ExerciseNotFound))?;
 
     Ok(Exercise {
         id: row.get(0)?,
@@ -702,29 +1670,35 @@ async fn fetch_exercise(conn: &Connection, id: i64, user_id: i64) -> Result<Exer
 // Sets
 // ============================================================================
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 pub struct SetPath {
     date: String,
     exercise_id: i64,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
 pub struct SetPathFull {
     date: String,
     exercise_id: i64,
     set_number: i32,
 }
 
+#[utoipa::path(
+    get,
+    path = "/days/{date}/exercises/{exercise_id}/sets",
+    tag = "sets",
+    params(SetPath),
+    responses((status = OK, body = DaySets))
+)]
 async fn day_sets(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(path): Path<SetPath>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<DaySets>> {
     let day = parse_day(&path.date)?;
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Verify exercise ownership
     let _ = fetch_exercise(&conn, path.exercise_id, user.id).await?;
 
@@ -754,20 +1728,39 @@ async fn day_sets(
     Ok(Json(DaySets { sets }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/days/{date}/exercises/{exercise_id}/sets/{set_number}",
+    tag = "sets",
+    params(SetPathFull),
+    responses((status = OK, body = WorkoutSet))
+)]
 async fn upsert_set(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(path): Path<SetPathFull>,
-    headers: HeaderMap,
+    user: AuthUser,
     Json(input): Json<SetInput>,
 ) -> Result<Json<WorkoutSet>> {
     if path.set_number < 1 {
-        return Err(Error::BadRequest("set_number must be >= 1".into()));
+        return Err(Error::invalid(
+            "set_number",
+            ErrorCode::OutOfRange,
+            "set_number must be >= 1",
+        ));
     }
     if input.reps < 1 {
-        return Err(Error::BadRequest("reps must be >= 1".into()));
+        return Err(Error::invalid(
+            "reps",
+            ErrorCode::OutOfRange,
+            "reps must be >= 1",
+        ));
     }
     if input.weight < 0.0 {
-        return Err(Error::BadRequest("weight must be >= 0".into()));
+        return Err(Error::invalid(
+            "weight",
+            ErrorCode::OutOfRange,
+            "weight must be >= 0",
+        ));
     }
 
     let day = parse_day(&path.date)?;
@@ -781,9 +1774,6 @@ async fn upsert_set(
     let clamped = day.and_time(completed_at.time());
     let completed_at = DateTime::from_naive_utc_and_offset(clamped, Utc);
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Verify ownership
     let _ = fetch_exercise(&conn, path.exercise_id, user.id).await?;
 
@@ -825,20 +1815,28 @@ async fn upsert_set(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/days/{date}/exercises/{exercise_id}/sets/{set_number}",
+    tag = "sets",
+    params(SetPathFull),
+    responses((status = NO_CONTENT))
+)]
 async fn delete_set(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(path): Path<SetPathFull>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<impl IntoResponse> {
     if path.set_number < 1 {
-        return Err(Error::BadRequest("set_number must be >= 1".into()));
+        return Err(Error::invalid(
+            "set_number",
+            ErrorCode::OutOfRange,
+            "set_number must be >= 1",
+        ));
     }
 
     let day = parse_day(&path.date)?;
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Verify ownership
     let _ = fetch_exercise(&conn, path.exercise_id, user.id).await?;
 
@@ -850,7 +1848,7 @@ async fn delete_set(
         .await?;
 
     if deleted == 0 {
-        return Err(Error::NotFound("set".into()));
+        return Err(Error::NotFound(ErrorCode::SetNotFound));
     }
 
     Ok(StatusCode::NO_CONTENT)
@@ -858,34 +1856,42 @@ async fn delete_set(
 
 fn parse_day(s: &str) -> Result<NaiveDate> {
     NaiveDate::parse_from_str(s, "%Y-%m-%d")
-        .map_err(|_| Error::BadRequest("invalid date, expected YYYY-MM-DD".into()))
+        .map_err(|_| Error::invalid("date", ErrorCode::InvalidDate, "expected YYYY-MM-DD"))
+}
+
+fn validate_kind(kind: &str) -> Result<()> {
+    core::validate_measurement_kind(kind)
+        .map_err(|e| Error::invalid("kind", ErrorCode::InvalidFormat, e))
 }
 
 // ============================================================================
 // Plan management
 // ============================================================================
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
 struct AddExerciseInput {
     exercise_id: i64,
 }
 
+#[utoipa::path(
+    post,
+    path = "/plans/{template_id}/exercises",
+    tag = "plans",
+    responses((status = CREATED))
+)]
 async fn add_exercise_to_plan(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(template_id): Path<i64>,
-    headers: HeaderMap,
+    user: AuthUser,
     Json(input): Json<AddExerciseInput>,
 ) -> Result<impl IntoResponse> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Verify template belongs to user
     let mut stmt = conn
         .prepare("SELECT id FROM workout_templates WHERE id = ? AND user_id = ?")
         .await?;
     stmt.query_row((template_id, user.id))
         .await
-        .map_err(|_| Error::NotFound("template".into()))?;
+        .map_err(|_| Error::NotFound(ErrorCode::PlanNotFound))?;
 
     // Verify exercise exists and belongs to user
     let _ = fetch_exercise(&conn, input.exercise_id, user.id).await?;
@@ -909,21 +1915,24 @@ async fn add_exercise_to_plan(
     Ok(StatusCode::CREATED)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/plans/{template_id}/exercises/{exercise_id}",
+    tag = "plans",
+    responses((status = NO_CONTENT))
+)]
 async fn remove_exercise_from_plan(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path((template_id, exercise_id)): Path<(i64, i64)>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<impl IntoResponse> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     // Verify template belongs to user
     let mut stmt = conn
         .prepare("SELECT id FROM workout_templates WHERE id = ? AND user_id = ?")
         .await?;
     stmt.query_row((template_id, user.id))
         .await
-        .map_err(|_| Error::NotFound("template".into()))?;
+        .map_err(|_| Error::NotFound(ErrorCode::PlanNotFound))?;
 
     let deleted = conn
         .execute(
@@ -933,7 +1942,7 @@ async fn remove_exercise_from_plan(
         .await?;
 
     if deleted == 0 {
-        return Err(Error::NotFound("exercise not in plan".into()));
+        return Err(Error::NotFound(ErrorCode::PlanExerciseNotFound));
     }
 
     Ok(StatusCode::NO_CONTENT)
@@ -943,20 +1952,25 @@ async fn remove_exercise_from_plan(
 // Weight tracking
 // ============================================================================
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct WeightQuery {
     start: Option<DateTime<Utc>>,
     end: Option<DateTime<Utc>>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/weight",
+    tag = "weight",
+    params(WeightQuery),
+    responses((status = OK, body = WeightHistory))
+)]
 async fn weight_history(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Query(query): Query<WeightQuery>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<WeightHistory>> {
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let mut sql = String::from(
         "SELECT id, day, weight_kg, recorded_at FROM weight_entries WHERE user_id = ?",
     );
@@ -988,16 +2002,65 @@ async fn weight_history(
     Ok(Json(WeightHistory { entries }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/weight/trend",
+    tag = "weight",
+    params(WeightTrendQuery),
+    responses((status = OK, body = WeightTrend))
+)]
+async fn weight_trend(
+    Db(conn): Db,
+    Query(query): Query<WeightTrendQuery>,
+    user: AuthUser,
+) -> Result<Json<WeightTrend>> {
+    if query.goal_kg.is_some_and(|g| g <= 0.0) {
+        return Err(Error::invalid(
+            "goal_kg",
+            ErrorCode::OutOfRange,
+            "goal must be > 0",
+        ));
+    }
+
+    // Smooth over all earlier entries so the trend at `start` is warmed up
+    let mut sql = String::from("SELECT day, weight_kg FROM weight_entries WHERE user_id = ?");
+    let mut params: Vec<Value> = vec![user.id.into()];
+
+    if let Some(end) = query.end {
+        sql.push_str(" AND day <= ?");
+        params.push(end.date_naive().to_string().into());
+    }
+
+    sql.push_str(" ORDER BY day");
+
+    let mut rows = conn.query(&sql, params).await?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        entries.push((parse_day(&row.get::<String>(0)?)?, row.get::<f64>(1)?));
+    }
+
+    let mut trend = core::weight_trend(&entries, query.goal_kg);
+    if let Some(start) = query.start {
+        let start = start.date_naive();
+        trend.points.retain(|p| p.day >= start);
+    }
+
+    Ok(Json(trend))
+}
+
+#[utoipa::path(
+    get,
+    path = "/weight/{date}",
+    tag = "weight",
+    responses((status = OK, description = "`null` when nothing is logged that day", body = Option<WeightEntry>))
+)]
 async fn get_weight(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(date): Path<String>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<Json<Option<WeightEntry>>> {
     let day = parse_day(&date)?;
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let mut stmt = conn
         .prepare("SELECT id, day, weight_kg, recorded_at FROM weight_entries WHERE user_id = ? AND day = ?")
         .await?;
@@ -1015,22 +2078,29 @@ async fn get_weight(
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/weight/{date}",
+    tag = "weight",
+    responses((status = OK, body = WeightEntry))
+)]
 async fn upsert_weight(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(date): Path<String>,
-    headers: HeaderMap,
+    user: AuthUser,
     Json(input): Json<WeightInput>,
 ) -> Result<Json<WeightEntry>> {
     if input.weight_kg <= 0.0 {
-        return Err(Error::BadRequest("weight must be > 0".into()));
+        return Err(Error::invalid(
+            "weight_kg",
+            ErrorCode::OutOfRange,
+            "weight must be > 0",
+        ));
     }
 
     let day = parse_day(&date)?;
     let recorded_at = input.recorded_at.unwrap_or_else(db::now);
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     conn.execute(
         "INSERT INTO weight_entries (user_id, day, weight_kg, recorded_at)
          VALUES (?, ?, ?, ?)
@@ -1060,16 +2130,19 @@ async fn upsert_weight(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/weight/{date}",
+    tag = "weight",
+    responses((status = NO_CONTENT))
+)]
 async fn delete_weight(
-    AxumState(state): AxumState<State>,
+    Db(conn): Db,
     Path(date): Path<String>,
-    headers: HeaderMap,
+    user: AuthUser,
 ) -> Result<impl IntoResponse> {
     let day = parse_day(&date)?;
 
-    let mut conn = state.db.connect()?;
-    let user = auth::user_from_headers(&mut conn, &headers).await?;
-
     let deleted = conn
         .execute(
             "DELETE FROM weight_entries WHERE user_id = ? AND day = ?",
@@ -1078,8 +2151,560 @@ async fn delete_weight(
         .await?;
 
     if deleted == 0 {
-        return Err(Error::NotFound("weight entry".into()));
+        return Err(Error::NotFound(ErrorCode::WeightEntryNotFound));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ============================================================================
+// Body measurements
+// ============================================================================
+
+#[utoipa::path(
+    get,
+    path = "/measurements",
+    tag = "measurements",
+    params(MeasurementQuery),
+    responses((status = OK, body = MeasurementHistory))
+)]
+async fn measurement_history(
+    Db(conn): Db,
+    Query(query): Query<MeasurementQuery>,
+    user: AuthUser,
+) -> Result<Json<MeasurementHistory>> {
+    let mut sql = String::from(
+        "SELECT id, kind, day, value, recorded_at FROM measurement_entries WHERE user_id = ?",
+    );
+    let mut params: Vec<Value> = vec![user.id.into()];
+
+    if let Some(kind) = query.kind {
+        sql.push_str(" AND kind = ?");
+        params.push(kind.into());
+    }
+    if let Some(start) = query.start {
+        sql.push_str(" AND day >= ?");
+        params.push(start.date_naive().to_string().into());
+    }
+    if let Some(end) = query.end {
+        sql.push_str(" AND day <= ?");
+        params.push(end.date_naive().to_string().into());
+    }
+
+    sql.push_str(" ORDER BY day DESC, kind");
+
+    let mut rows = conn.query(&sql, params).await?;
+    let mut entries = Vec::new();
+
+    while let Some(row) = rows.next().await? {
+        entries.push(MeasurementEntry {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            day: row.get(2)?,
+            value: row.get(3)?,
+            recorded_at: db::parse_timestamp(&row.get::<String>(4)?)?,
+        });
+    }
+
+    Ok(Json(MeasurementHistory { entries }))
+}
+
+/// Built-in kinds followed by any custom kinds the user has logged.
+#[utoipa::path(
+    get,
+    path = "/measurements/kinds",
+    tag = "measurements",
+    responses((status = OK, body = Vec<MeasurementKind>))
+)]
+async fn measurement_kinds(Db(conn): Db, user: AuthUser) -> Result<Json<Vec<MeasurementKind>>> {
+    let mut kinds: Vec<String> = MEASUREMENT_KINDS.iter().map(|k| k.to_string()).collect();
+
+    let mut rows = conn
+        .query(
+            "SELECT DISTINCT kind FROM measurement_entries WHERE user_id = ? ORDER BY kind",
+            [user.id],
+        )
+        .await?;
+    while let Some(row) = rows.next().await? {
+        let kind: String = row.get(0)?;
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+
+    Ok(Json(
+        kinds
+            .into_iter()
+            .map(|kind| MeasurementKind {
+                unit: core::measurement_unit(&kind).into(),
+                kind,
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/measurements/{kind}/graph",
+    tag = "measurements",
+    params(WeightQuery),
+    responses((status = OK, body = MeasurementGraph))
+)]
+async fn measurement_graph(
+    Db(conn): Db,
+    Path(kind): Path<String>,
+    Query(query): Query<WeightQuery>,
+    user: AuthUser,
+) -> Result<Json<MeasurementGraph>> {
+    validate_kind(&kind)?;
+
+    let mut sql =
+        String::from("SELECT day, value FROM measurement_entries WHERE user_id = ? AND kind = ?");
+    let mut params: Vec<Value> = vec![user.id.into(), kind.clone().into()];
+
+    if let Some(start) = query.start {
+        sql.push_str(" AND day >= ?");
+        params.push(start.date_naive().to_string().into());
+    }
+    if let Some(end) = query.end {
+        sql.push_str(" AND day <= ?");
+        params.push(end.date_naive().to_string().into());
+    }
+
+    sql.push_str(" ORDER BY day");
+
+    let mut rows = conn.query(&sql, params).await?;
+    let mut points = Vec::new();
+    while let Some(row) = rows.next().await? {
+        points.push(GraphPoint {
+            date: row.get(0)?,
+            value: row.get(1)?,
+        });
+    }
+
+    Ok(Json(MeasurementGraph {
+        unit: core::measurement_unit(&kind).into(),
+        kind,
+        points,
+    }))
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct MeasurementPath {
+    kind: String,
+    date: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/measurements/{kind}/{date}",
+    tag = "measurements",
+    params(MeasurementPath),
+    responses((status = OK, description = "`null` when nothing is logged that day", body = Option<MeasurementEntry>))
+)]
+async fn get_measurement(
+    Db(conn): Db,
+    Path(path): Path<MeasurementPath>,
+    user: AuthUser,
+) -> Result<Json<Option<MeasurementEntry>>> {
+    validate_kind(&path.kind)?;
+    let day = parse_day(&path.date)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, kind, day, value, recorded_at FROM measurement_entries
+             WHERE user_id = ? AND kind = ? AND day = ?",
+        )
+        .await?;
+
+    let result = stmt
+        .query_row((user.id, path.kind.as_str(), day.to_string()))
+        .await;
+
+    match result {
+        Ok(row) => Ok(Json(Some(MeasurementEntry {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            day: row.get(2)?,
+            value: row.get(3)?,
+            recorded_at: db::parse_timestamp(&row.get::<String>(4)?)?,
+        }))),
+        Err(_) => Ok(Json(None)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/measurements/{kind}/{date}",
+    tag = "measurements",
+    params(MeasurementPath),
+    responses((status = OK, body = MeasurementEntry))
+)]
+async fn upsert_measurement(
+    Db(conn): Db,
+    Path(path): Path<MeasurementPath>,
+    user: AuthUser,
+    Json(input): Json<MeasurementInput>,
+) -> Result<Json<MeasurementEntry>> {
+    validate_kind(&path.kind)?;
+    if input.value <= 0.0 {
+        return Err(Error::invalid(
+            "value",
+            ErrorCode::OutOfRange,
+            "value must be > 0",
+        ));
+    }
+    if core::measurement_unit(&path.kind) == "%" && input.value > 100.0 {
+        return Err(Error::invalid(
+            "value",
+            ErrorCode::OutOfRange,
+            "percentage must be <= 100",
+        ));
+    }
+
+    let day = parse_day(&path.date)?;
+    let recorded_at = input.recorded_at.unwrap_or_else(db::now);
+
+    conn.execute(
+        "INSERT INTO measurement_entries (user_id, kind, day, value, recorded_at)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(user_id, kind, day) DO UPDATE SET
+            value = excluded.value,
+            recorded_at = excluded.recorded_at",
+        (
+            user.id,
+            path.kind.as_str(),
+            day.to_string(),
+            input.value,
+            db::timestamp(recorded_at),
+        ),
+    )
+    .await?;
+
+    // Get the id
+    let mut stmt = conn
+        .prepare("SELECT id FROM measurement_entries WHERE user_id = ? AND kind = ? AND day = ?")
+        .await?;
+    let row = stmt
+        .query_row((user.id, path.kind.as_str(), day.to_string()))
+        .await?;
+
+    Ok(Json(MeasurementEntry {
+        id: row.get(0)?,
+        kind: path.kind,
+        day: day.to_string(),
+        value: input.value,
+        recorded_at,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/measurements/{kind}/{date}",
+    tag = "measurements",
+    params(MeasurementPath),
+    responses((status = NO_CONTENT))
+)]
+async fn delete_measurement(
+    Db(conn): Db,
+    Path(path): Path<MeasurementPath>,
+    user: AuthUser,
+) -> Result<impl IntoResponse> {
+    let day = parse_day(&path.date)?;
+
+    let deleted = conn
+        .execute(
+            "DELETE FROM measurement_entries WHERE user_id = ? AND kind = ? AND day = ?",
+            (user.id, path.kind.as_str(), day.to_string()),
+        )
+        .await?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound(ErrorCode::MeasurementNotFound));
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+// ============================================================================
+// Profile & strength reports
+// ============================================================================
+
+fn sex_as_str(sex: Sex) -> &'static str {
+    match sex {
+        Sex::Male => "male",
+        Sex::Female => "female",
+    }
+}
+
+fn parse_sex(s: &str) -> Result<Sex> {
+    match s {
+        "male" => Ok(Sex::Male),
+        "female" => Ok(Sex::Female),
+        _ => Err(Error::Internal(format!("bad sex: {s}"))),
+    }
+}
+
+async fn load_profile(conn: &Conn, user_id: i64) -> Result<Profile> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT sex, birth_year, squat_exercise_id, bench_exercise_id, deadlift_exercise_id
+             FROM user_profiles WHERE user_id = ?",
+        )
+        .await?;
+
+    let Ok(row) = stmt.query_row([user_id]).await else {
+        return Ok(Profile::default());
+    };
+
+    Ok(Profile {
+        sex: row
+            .get::<Option<String>>(0)?
+            .map(|s| parse_sex(&s))
+            .transpose()?,
+        birth_year: row.get::<Option<i64>>(1)?.map(|y| y as i32),
+        squat_exercise_id: row.get(2)?,
+        bench_exercise_id: row.get(3)?,
+        deadlift_exercise_id: row.get(4)?,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/profile",
+    tag = "profile",
+    responses((status = OK, body = Profile))
+)]
+async fn get_profile(Db(conn): Db, user: AuthUser) -> Result<Json<Profile>> {
+    Ok(Json(load_profile(&conn, user.id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/profile",
+    tag = "profile",
+    responses((status = OK, body = Profile))
+)]
+async fn update_profile(
+    Db(conn): Db,
+    user: AuthUser,
+    Json(input): Json<Profile>,
+) -> Result<Json<Profile>> {
+    if let Some(year) = input.birth_year
+        && !(1900..=db::now().year()).contains(&year)
+    {
+        return Err(Error::invalid(
+            "birth_year",
+            ErrorCode::OutOfRange,
+            "birth year out of range",
+        ));
+    }
+
+    for lift in Lift::ALL {
+        if let Some(id) = input.lift_exercise(lift) {
+            fetch_exercise(&conn, id, user.id).await?;
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO user_profiles
+            (user_id, sex, birth_year, squat_exercise_id, bench_exercise_id, deadlift_exercise_id)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT(user_id) DO UPDATE SET
+            sex = excluded.sex,
+            birth_year = excluded.birth_year,
+            squat_exercise_id = excluded.squat_exercise_id,
+            bench_exercise_id = excluded.bench_exercise_id,
+            deadlift_exercise_id = excluded.deadlift_exercise_id",
+        vec![
+            Value::from(user.id),
+            input.sex.map(sex_as_str).into(),
+            input.birth_year.map(i64::from).into(),
+            input.squat_exercise_id.into(),
+            input.bench_exercise_id.into(),
+            input.deadlift_exercise_id.into(),
+        ],
+    )
+    .await?;
+
+    Ok(Json(input))
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/strength",
+    tag = "profile",
+    params(StrengthQuery),
+    responses((status = OK, body = StrengthReport))
+)]
+async fn strength_report(
+    Db(conn): Db,
+    Query(query): Query<StrengthQuery>,
+    user: AuthUser,
+) -> Result<Json<StrengthReport>> {
+    let date = query.date.unwrap_or_else(|| db::now().date_naive());
+
+    let profile = load_profile(&conn, user.id).await?;
+
+    // Body weight closest to the report day, either side
+    let mut stmt = conn
+        .prepare(
+            "SELECT day, weight_kg FROM weight_entries
+             WHERE user_id = ?
+             ORDER BY ABS(julianday(day) - julianday(?)), day DESC
+             LIMIT 1",
+        )
+        .await?;
+    let body_weight = match stmt.query_row((user.id, date.to_string())).await {
+        Ok(row) => Some((parse_day(&row.get::<String>(0)?)?, row.get::<f64>(1)?)),
+        Err(_) => None,
+    };
+    let body_weight_kg = body_weight.map(|(_, kg)| kg);
+
+    let mut lifts = Vec::new();
+    for lift in Lift::ALL {
+        let Some(id) = profile.lift_exercise(lift) else {
+            continue;
+        };
+        let exercise = fetch_exercise(&conn, id, user.id).await?;
+        let best = load_set_data(&conn, id, user.id, None, None)
+            .await?
+            .into_iter()
+            .filter(|s| s.date <= date)
+            .map(|s| (s.date, core::estimate_1rm(s.weight, s.reps)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((day, est_1rm)) = best {
+            lifts.push(LiftBest {
+                lift,
+                exercise_id: id,
+                exercise_name: exercise.name,
+                est_1rm,
+                day,
+                bodyweight_multiple: body_weight_kg
+                    .map(|bw| core::bodyweight_multiple(est_1rm, bw)),
+            });
+        }
+    }
+
+    let total_kg =
+        (lifts.len() == Lift::ALL.len()).then(|| lifts.iter().map(|l| l.est_1rm).sum::<f64>());
+    let scores = total_kg.zip(body_weight_kg).zip(profile.sex);
+    let score = |f: fn(f64, f64, Sex) -> f64| scores.map(|((total, bw), sex)| f(total, bw, sex));
+
+    Ok(Json(StrengthReport {
+        sex: profile.sex,
+        age: profile.birth_year.map(|year| date.year() - year),
+        body_weight_kg,
+        body_weight_day: body_weight.map(|(day, _)| day),
+        lifts,
+        total_kg,
+        wilks: score(core::wilks),
+        dots: score(core::dots),
+        ipf_gl: score(core::ipf_gl),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use axum::{Router, body::Body, http::Request};
+    use totp_rs::{Algorithm, Secret, TOTP};
+    use tower::ServiceExt;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    /// Router with user 1 (`u`, password `p`), a connection to its database,
+    /// and a session cookie for the user.
+    async fn fixture() -> (Router, Conn, String) {
+        let db = db::test_db().await;
+        let mut conn = Conn::from(db.connect().unwrap());
+        conn.execute(
+            "INSERT INTO users (username, password_hash, totp_secret, totp_enabled)
+             VALUES ('u', ?, ?, 1)",
+            [auth::hash_password("p").unwrap(), SECRET.into()],
+        )
+        .await
+        .unwrap();
+        let (token, _) = auth::create_session(
+            &mut conn,
+            1,
+            &auth::ClientInfo::default(),
+            chrono::Duration::days(1),
+        )
+        .await
+        .unwrap();
+        let cookie = format!("ekman_session={token}");
+        (crate::router(State::test(db)), conn, cookie)
+    }
+
+    async fn ids(conn: &Conn, sql: &str) -> Vec<i64> {
+        let mut rows = conn.query(sql, ()).await.unwrap();
+        let mut ids = Vec::new();
+        while let Some(row) = rows.next().await.unwrap() {
+            ids.push(row.get(0).unwrap());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_multi_graph_window_range() {
+        let (app, _conn, cookie) = fixture().await;
+        let request =
+            Request::get("/api/v1/graphs?exercise_ids=1&metrics=max_weight&window=1000000")
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: core::ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.details[0].field, "window");
+        assert_eq!(body.details[0].code, ErrorCode::OutOfRange);
+    }
+
+    #[tokio::test]
+    async fn test_delete_account() {
+        let (app, conn, cookie) = fixture().await;
+        for sql in [
+            "INSERT INTO users (username, password_hash, totp_secret) VALUES ('other', 'x', 'y')",
+            "INSERT INTO exercises (user_id, name) VALUES (1, 'a'), (1, 'b'), (2, 'c')",
+            "INSERT INTO workout_sets (exercise_id, day, set_number, weight_kg, reps, completed_at)
+             VALUES (1, 'd', 1, 50, 5, 't'), (2, 'd', 1, 50, 5, 't'), (3, 'd', 1, 50, 5, 't')",
+            "INSERT INTO workout_templates (user_id, name) VALUES (1, 'mine'), (2, 'theirs')",
+            "INSERT INTO template_exercises (template_id, exercise_id, display_order)
+             VALUES (1, 1, 0), (1, 2, 1), (2, 3, 0)",
+        ] {
+            conn.execute(sql, ()).await.unwrap();
+        }
+
+        let bytes = Secret::Encoded(SECRET.into()).to_bytes().unwrap();
+        let code = TOTP::new_unchecked(Algorithm::SHA1, 6, 0, 30, bytes, None, String::new())
+            .generate_current()
+            .unwrap();
+        let body = serde_json::json!({ "password": "p", "totp": code });
+        let request = Request::delete("/api/v1/account")
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // Only the other user's rows are left
+        assert_eq!(ids(&conn, "SELECT id FROM users").await, [2]);
+        assert_eq!(
+            ids(&conn, "SELECT exercise_id FROM workout_sets").await,
+            [3]
+        );
+        assert_eq!(
+            ids(&conn, "SELECT template_id FROM template_exercises").await,
+            [2]
+        );
+    }
+}
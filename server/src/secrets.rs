@@ -0,0 +1,260 @@
+//! Envelope encryption for secrets stored in the database.
+//!
+//! Each value gets its own random data key. The data key is wrapped with the
+//! server key from `EKMAN__SECRETS__TOTP_KEY`, so rotating the server key only
+//! re-wraps data keys. Stored form:
+//!
+//! `enc1:<key id>:<hex nonce + wrapped data key>:<hex nonce + ciphertext>`
+//!
+//! Values without the prefix are plaintext from before encryption was enabled.
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng},
+};
+use sha2::{Digest, Sha256};
+use turso::Connection;
+
+use crate::{Error, Result, auth::hex, config::SecretsConfig};
+
+const PREFIX: &str = "enc1";
+const NONCE_LEN: usize = 12;
+
+struct ServerKey {
+    id: String,
+    cipher: ChaCha20Poly1305,
+}
+
+impl ServerKey {
+    fn parse(hex_key: &str) -> Result<Self> {
+        let bytes = hex::decode(hex_key.trim())
+            .filter(|b| b.len() == 32)
+            .ok_or_else(|| Error::Internal("secret key must be 64 hex characters".into()))?;
+        // Short fingerprint so stored values name the key that wrapped them
+        let id = hex::encode(&Sha256::digest(&bytes)[..4]);
+        Ok(Self {
+            id,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&bytes)),
+        })
+    }
+}
+
+/// Current key for sealing plus older keys still accepted for opening.
+#[derive(Default)]
+pub struct Keyring {
+    current: Option<ServerKey>,
+    previous: Vec<ServerKey>,
+}
+
+impl Keyring {
+    pub fn from_config(config: &SecretsConfig) -> Result<Self> {
+        let current = config
+            .totp_key
+            .as_deref()
+            .map(ServerKey::parse)
+            .transpose()?;
+        let previous = config
+            .totp_key_previous
+            .iter()
+            .map(|key| ServerKey::parse(key))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { current, previous })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Encrypt under the current key, or pass through when no key is set.
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let Some(key) = &self.current else {
+            return Ok(plaintext.into());
+        };
+
+        let data_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+        let wrapped = encrypt(&key.cipher, &data_key)?;
+        let sealed = encrypt(&ChaCha20Poly1305::new(&data_key), plaintext.as_bytes())?;
+
+        Ok(format!(
+            "{PREFIX}:{}:{}:{}",
+            key.id,
+            hex::encode(wrapped),
+            hex::encode(sealed)
+        ))
+    }
+
+    /// Decrypt a stored value; plaintext values come back unchanged.
+    pub fn open(&self, stored: &str) -> Result<String> {
+        let Some(envelope) = Envelope::parse(stored)? else {
+            return Ok(stored.into());
+        };
+
+        let data_key = self.unwrap_data_key(&envelope)?;
+        let plaintext = decrypt(&ChaCha20Poly1305::new(&data_key), &envelope.sealed)?;
+        String::from_utf8(plaintext).map_err(|_| Error::Internal("secret is not utf-8".into()))
+    }
+
+    /// Re-wrap under the current key. Returns `None` when nothing changes.
+    pub fn reseal(&self, stored: &str) -> Result<Option<String>> {
+        let Some(key) = &self.current else {
+            return Ok(None);
+        };
+
+        let Some(envelope) = Envelope::parse(stored)? else {
+            return self.seal(stored).map(Some);
+        };
+        if envelope.key_id == key.id {
+            return Ok(None);
+        }
+
+        // Only the data key is re-encrypted; the secret itself is untouched
+        let data_key = self.unwrap_data_key(&envelope)?;
+        let wrapped = encrypt(&key.cipher, &data_key)?;
+        Ok(Some(format!(
+            "{PREFIX}:{}:{}:{}",
+            key.id,
+            hex::encode(wrapped),
+            hex::encode(&envelope.sealed)
+        )))
+    }
+
+    fn unwrap_data_key(&self, envelope: &Envelope) -> Result<Key> {
+        let key = self
+            .current
+            .iter()
+            .chain(&self.previous)
+            .find(|k| k.id == envelope.key_id)
+            .ok_or_else(|| Error::Internal(format!("no secret key with id {}", envelope.key_id)))?;
+
+        let data_key = decrypt(&key.cipher, &envelope.wrapped)?;
+        if data_key.len() != 32 {
+            return Err(Error::Internal("bad data key".into()));
+        }
+        Ok(*Key::from_slice(&data_key))
+    }
+}
+
+struct Envelope {
+    key_id: String,
+    wrapped: Vec<u8>,
+    sealed: Vec<u8>,
+}
+
+impl Envelope {
+    fn parse(stored: &str) -> Result<Option<Self>> {
+        let Some(rest) = stored
+            .strip_prefix(PREFIX)
+            .and_then(|r| r.strip_prefix(':'))
+        else {
+            return Ok(None);
+        };
+
+        let bad = || Error::Internal("malformed encrypted secret".into());
+        let mut parts = rest.split(':');
+        let (Some(key_id), Some(wrapped), Some(sealed), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(bad());
+        };
+
+        Ok(Some(Self {
+            key_id: key_id.into(),
+            wrapped: hex::decode(wrapped).ok_or_else(bad)?,
+            sealed: hex::decode(sealed).ok_or_else(bad)?,
+        }))
+    }
+}
+
+fn encrypt(cipher: &ChaCha20Poly1305, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut out = nonce.to_vec();
+    out.extend(
+        cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Error::Internal("encryption failed".into()))?,
+    );
+    Ok(out)
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(Error::Internal("ciphertext too short".into()));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::Internal("decryption failed (wrong key?)".into()))
+}
+
+/// Random server key in the format `EKMAN__SECRETS__TOTP_KEY` expects.
+pub fn generate_key() -> String {
+    hex::encode(ChaCha20Poly1305::generate_key(&mut OsRng))
+}
+
+/// Bring stored TOTP secrets under the current key. Without `rotate` only
+/// plaintext rows are touched. Returns how many rows were rewritten.
+pub async fn reseal_totp_secrets(conn: &Connection, keys: &Keyring, rotate: bool) -> Result<u64> {
+    let mut rows = conn.query("SELECT id, totp_secret FROM users", ()).await?;
+    let mut users = Vec::new();
+    while let Some(row) = rows.next().await? {
+        users.push((row.get::<i64>(0)?, row.get::<String>(1)?));
+    }
+
+    let mut updated = 0;
+    for (id, stored) in users {
+        if !rotate && Envelope::parse(&stored)?.is_some() {
+            continue;
+        }
+        if let Some(sealed) = keys.reseal(&stored)? {
+            conn.execute(
+                "UPDATE users SET totp_secret = ? WHERE id = ?",
+                (sealed, id),
+            )
+            .await?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(current: &str, previous: &[&str]) -> Keyring {
+        Keyring {
+            current: Some(ServerKey::parse(current).unwrap()),
+            previous: previous
+                .iter()
+                .map(|k| ServerKey::parse(k).unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let keys = keyring(&generate_key(), &[]);
+        let sealed = keys.seal("JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(sealed.starts_with("enc1:"));
+        assert!(!sealed.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(keys.open(&sealed).unwrap(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(keys.open("JBSWY3DPEHPK3PXP").unwrap(), "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn test_rotation_rewraps_data_key() {
+        let (old, new) = (generate_key(), generate_key());
+        let sealed = keyring(&old, &[]).seal("SECRET").unwrap();
+
+        let rotated = keyring(&new, &[&old]);
+        assert_eq!(rotated.open(&sealed).unwrap(), "SECRET");
+
+        let resealed = rotated.reseal(&sealed).unwrap().unwrap();
+        assert_eq!(rotated.reseal(&resealed).unwrap(), None);
+        // New key alone is enough once rotation has run
+        assert_eq!(keyring(&new, &[]).open(&resealed).unwrap(), "SECRET");
+        assert!(keyring(&new, &[]).open(&sealed).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Graceful shutdown on SIGTERM or SIGINT.
+//!
+//! The first signal stops accepting connections and gives in-flight requests
+//! `server.shutdown_timeout_secs` to finish; whatever is still running after
+//! that is dropped. The caller then waits for background tasks, which stop
+//! on the same signal, and checkpoints the database.
+
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{Result, metrics::Metrics};
+
+/// Fires once a shutdown signal arrives.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Start listening for SIGTERM and SIGINT.
+    pub fn listen() -> Self {
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            signal().await;
+            let _ = tx.send(true);
+        });
+        Self(rx)
+    }
+
+    pub async fn requested(mut self) {
+        // Err means the sender is gone, which only happens after it fired
+        let _ = self.0.wait_for(|&fired| fired).await;
+    }
+}
+
+async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("failed to listen for ctrl-c: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("received SIGINT"),
+        _ = terminate => info!("received SIGTERM"),
+    }
+}
+
+/// Run `server` until it exits. Once `shutdown` fires, the server is
+/// expected to stop accepting and finish its connections; it gets `timeout`
+/// to do so before being dropped.
+pub async fn drain<F>(
+    server: F,
+    shutdown: Shutdown,
+    timeout: Duration,
+    metrics: &Metrics,
+) -> Result<()>
+where
+    F: Future<Output = std::io::Result<()>>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return Ok(result?),
+        _ = shutdown.requested() => {}
+    }
+
+    info!(
+        "shutting down, waiting up to {}s for {}",
+        timeout.as_secs(),
+        metrics.in_flight_summary()
+    );
+    match tokio::time::timeout(timeout, server).await {
+        Ok(result) => {
+            info!("all requests finished");
+            Ok(result?)
+        }
+        Err(_) => {
+            warn!("shutdown timeout, dropping {}", metrics.in_flight_summary());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fired() -> Shutdown {
+        let (tx, rx) = watch::channel(false);
+        tx.send(true).unwrap();
+        // Keep the value readable after the sender drops
+        Shutdown(rx)
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_server() {
+        let metrics = Metrics::default();
+        let server = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(())
+        };
+        let started = std::time::Instant::now();
+        drain(server, fired(), Duration::from_secs(5), &metrics)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_drain_times_out() {
+        let metrics = Metrics::default();
+        let server = std::future::pending::<std::io::Result<()>>();
+        let started = std::time::Instant::now();
+        drain(server, fired(), Duration::from_millis(50), &metrics)
+            .await
+            .unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+}
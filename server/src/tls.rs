@@ -0,0 +1,242 @@
+//! Optional TLS termination.
+//!
+//! Enabled when `server.tls_cert` and `server.tls_key` point at PEM files.
+//! The files are polled and reloaded in place when they change, so renewed
+//! certificates are picked up without a restart. With
+//! `server.tls_redirect_port` set, a plain HTTP listener on that port
+//! redirects everything to HTTPS.
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, Uri, header, uri::Authority},
+    response::{IntoResponse, Redirect},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::{Error, Result, config::ServerConfig};
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Port for the HTTP listener that redirects to HTTPS.
+    pub redirect_port: Option<u16>,
+}
+
+impl TlsSettings {
+    /// `None` when TLS is not configured.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        Some(Self {
+            cert: config.tls_cert.clone()?,
+            key: config.tls_key.clone()?,
+            redirect_port: config.tls_redirect_port,
+        })
+    }
+
+    pub async fn load(&self) -> Result<RustlsConfig> {
+        install_crypto_provider();
+        RustlsConfig::from_pem_file(&self.cert, &self.key)
+            .await
+            .map_err(|e| Error::Internal(format!("failed to load tls certificate: {e}")))
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        Some((modified(&self.cert)?, modified(&self.key)?))
+    }
+}
+
+/// rustls needs a process-wide crypto provider before any config is built.
+fn install_crypto_provider() {
+    // Fails only when one is already installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+}
+
+/// Reloads `config` when the certificate or key file changes.
+pub struct Reloader {
+    settings: TlsSettings,
+    config: RustlsConfig,
+    last_modified: Option<(SystemTime, SystemTime)>,
+}
+
+impl Reloader {
+    pub fn new(settings: TlsSettings, config: RustlsConfig) -> Self {
+        Self {
+            last_modified: settings.modified(),
+            settings,
+            config,
+        }
+    }
+
+    /// Reload if either file changed since the last successful load. A
+    /// failed reload keeps serving the old certificate and is retried on the
+    /// next check, which covers the cert being written before the key.
+    async fn check(&mut self) -> bool {
+        let modified = self.settings.modified();
+        if modified.is_none() || modified == self.last_modified {
+            return false;
+        }
+
+        match self
+            .config
+            .reload_from_pem_file(&self.settings.cert, &self.settings.key)
+            .await
+        {
+            Ok(()) => {
+                info!("reloaded tls certificate");
+                self.last_modified = modified;
+                true
+            }
+            Err(e) => {
+                warn!("tls certificate reload failed, keeping the current one: {e}");
+                false
+            }
+        }
+    }
+
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check().await;
+        }
+    }
+}
+
+/// Plain HTTP app that sends every request to the same path over HTTPS.
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        match https_url(&headers, &uri, https_port) {
+            Some(url) => Redirect::permanent(&url).into_response(),
+            None => StatusCode::BAD_REQUEST.into_response(),
+        }
+    })
+}
+
+fn https_url(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Option<String> {
+    let authority: Authority = headers.get(header::HOST)?.to_str().ok()?.parse().ok()?;
+    let host = authority.host();
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    Some(match https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    })
+}
+
+/// Whether a response to `headers` goes out over HTTPS: either we terminate
+/// TLS, or a trusted reverse proxy says it did.
+pub fn is_secure(tls: bool, trust_proxy: bool, headers: &HeaderMap) -> bool {
+    tls || (trust_proxy
+        && headers
+            .get("x-forwarded-proto")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https")))
+}
+
+/// The client's address: the peer, or behind a trusted reverse proxy the
+/// last `X-Forwarded-For` entry, which is the one our proxy appended.
+/// Earlier entries come from the client and can be forged.
+pub fn client_ip(trust_proxy: bool, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+    let forwarded = trust_proxy
+        .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+        .flatten()
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+    forwarded.or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn write_cert(settings: &TlsSettings) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        std::fs::write(&settings.cert, cert.cert.pem()).unwrap();
+        std::fs::write(&settings.key, cert.signing_key.serialize_pem()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reloads_changed_certificate() {
+        let dir = crate::test_dir("tls");
+        let settings = TlsSettings {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            redirect_port: None,
+        };
+        write_cert(&settings);
+
+        let config = settings.load().await.unwrap();
+        let mut reloader = Reloader::new(settings.clone(), config.clone());
+        assert!(!reloader.check().await);
+
+        // A broken key keeps the old config and is retried
+        std::thread::sleep(Duration::from_millis(20));
+        std::fs::write(&settings.key, "not a key").unwrap();
+        let before = config.get_inner();
+        assert!(!reloader.check().await);
+        assert!(std::sync::Arc::ptr_eq(&before, &config.get_inner()));
+
+        write_cert(&settings);
+        assert!(reloader.check().await);
+        assert!(!std::sync::Arc::ptr_eq(&before, &config.get_inner()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_https_redirect_url() {
+        let mut headers = HeaderMap::new();
+        let uri: Uri = "/api/auth/me?x=1".parse().unwrap();
+        assert_eq!(https_url(&headers, &uri, 443), None);
+
+        headers.insert(header::HOST, HeaderValue::from_static("ekman.test:80"));
+        assert_eq!(
+            https_url(&headers, &uri, 443).unwrap(),
+            "https://ekman.test/api/auth/me?x=1"
+        );
+        assert_eq!(
+            https_url(&headers, &"/".parse().unwrap(), 8443).unwrap(),
+            "https://ekman.test:8443/"
+        );
+    }
+
+    #[test]
+    fn test_is_secure() {
+        let mut headers = HeaderMap::new();
+        assert!(is_secure(true, false, &headers));
+        assert!(!is_secure(false, true, &headers));
+
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https, http"));
+        assert!(is_secure(false, true, &headers));
+        // Spoofable unless we are told a proxy sets it
+        assert!(!is_secure(false, false, &headers));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let peer = Some(IpAddr::from([10, 0, 0, 1]));
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_ip(true, &headers, peer), peer);
+
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.1.1.1, 203.0.113.7"),
+        );
+        assert_eq!(
+            client_ip(true, &headers, peer),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        assert_eq!(client_ip(false, &headers, peer), peer);
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("garbage"));
+        assert_eq!(client_ip(true, &headers, peer), peer);
+    }
+}
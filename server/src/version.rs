@@ -0,0 +1,118 @@
+//! API versions.
+//!
+//! `/api/v1` is the current contract and the only one in the OpenAPI
+//! document. The unversioned `/api/...` paths from before versioning serve
+//! the same handlers so older clients keep working, but every response there
+//! carries `Deprecation` and `Sunset` headers (RFC 9745, RFC 8594) and a
+//! `Link` to the versioned path.
+
+use axum::{
+    Router,
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::{self, Next},
+    response::Response,
+};
+
+use crate::{State, routes};
+
+pub const V1: &str = "/api/v1";
+const UNVERSIONED: &str = "/api";
+
+/// When the unversioned paths were deprecated, as `@` Unix seconds.
+const DEPRECATED_AT: &str = "@1792281600";
+/// When the unversioned paths will be removed.
+const SUNSET: &str = "Sun, 18 Apr 2027 00:00:00 GMT";
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET_HEADER: HeaderName = HeaderName::from_static("sunset");
+
+/// The v1 routes at their old unversioned paths.
+pub fn unversioned() -> Router<State> {
+    deprecated(routes::v1().into())
+}
+
+/// `routes`, relative to the version prefix, at their unversioned paths
+/// with the deprecation headers.
+pub fn deprecated(routes: Router<State>) -> Router<State> {
+    Router::new().nest(UNVERSIONED, routes.layer(middleware::from_fn(deprecate)))
+}
+
+/// Path of an API request relative to its version prefix, e.g.
+/// `/auth/login` for both `/api/v1/auth/login` and `/api/auth/login`.
+pub fn api_path(path: &str) -> Option<&str> {
+    [V1, UNVERSIONED].into_iter().find_map(|prefix| {
+        path.strip_prefix(prefix)
+            .filter(|rest| rest.starts_with('/'))
+    })
+}
+
+/// Runs inside the nest, so the request path has the prefix stripped.
+async fn deprecate(request: Request, next: Next) -> Response {
+    let successor = format!("<{V1}{}>; rel=\"successor-version\"", request.uri().path());
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION, HeaderValue::from_static(DEPRECATED_AT));
+    headers.insert(SUNSET_HEADER, HeaderValue::from_static(SUNSET));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use axum::{body::Body, http::StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_unversioned_alias() {
+        let app = crate::router(State::test(db::test_db().await));
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        };
+
+        let current = get("/api/v1/exercises/7").await.unwrap();
+        assert_eq!(current.status(), StatusCode::UNAUTHORIZED);
+        assert!(current.headers().get(DEPRECATION).is_none());
+
+        let old = get("/api/exercises/7").await.unwrap();
+        assert_eq!(old.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(old.headers()[DEPRECATION], DEPRECATED_AT);
+        assert_eq!(old.headers()[SUNSET_HEADER], SUNSET);
+        assert_eq!(
+            old.headers()[header::LINK],
+            "</api/v1/exercises/7>; rel=\"successor-version\""
+        );
+
+        // So is the OpenAPI document
+        let spec = get("/api/openapi.json").await.unwrap();
+        assert_eq!(spec.status(), StatusCode::OK);
+        assert_eq!(spec.headers()[DEPRECATION], DEPRECATED_AT);
+        assert_eq!(
+            spec.headers()[header::LINK],
+            "</api/v1/openapi.json>; rel=\"successor-version\""
+        );
+        let spec = get("/api/v1/openapi.json").await.unwrap();
+        assert_eq!(spec.status(), StatusCode::OK);
+        assert!(spec.headers().get(DEPRECATION).is_none());
+
+        // Neither prefix swallows other paths
+        let unknown = get("/api/v2/exercises").await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+        assert!(unknown.headers().get(DEPRECATION).is_none());
+    }
+
+    #[test]
+    fn test_api_path() {
+        assert_eq!(api_path("/api/v1/auth/login"), Some("/auth/login"));
+        assert_eq!(api_path("/api/auth/login"), Some("/auth/login"));
+        assert_eq!(api_path("/api/v10/x"), Some("/v10/x"));
+        assert_eq!(api_path("/apiary"), None);
+        assert_eq!(api_path("/healthz"), None);
+    }
+}
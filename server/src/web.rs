@@ -0,0 +1,227 @@
+//! The web frontend, compiled into the binary and served at `/`.
+//!
+//! `index.html` is always revalidated. It references the other assets with a
+//! `?v=<etag>` suffix, and those versioned URLs are cached as immutable, so
+//! a new build is picked up on the next page load.
+
+use axum::{
+    Router,
+    body::Body,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use ekman_core::ErrorCode;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+use crate::{Error, Result, State, auth::hex};
+
+const INDEX: &str = "index.html";
+const REVALIDATE: &str = "no-cache";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Embedded files: (path, content type, body).
+const FILES: &[(&str, &str, &[u8])] = &[
+    (
+        INDEX,
+        "text/html; charset=utf-8",
+        include_bytes!("../../web/index.html"),
+    ),
+    (
+        "app.js",
+        "text/javascript; charset=utf-8",
+        include_bytes!("../../web/app.js"),
+    ),
+    (
+        "style.css",
+        "text/css; charset=utf-8",
+        include_bytes!("../../web/style.css"),
+    ),
+];
+
+struct Asset {
+    path: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+    etag: String,
+}
+
+impl Asset {
+    /// The `?v=` value in versioned URLs.
+    fn version(&self) -> &str {
+        self.etag.trim_matches('"')
+    }
+}
+
+fn etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(&Sha256::digest(body)[..8]))
+}
+
+fn assets() -> &'static [Asset] {
+    static ASSETS: OnceLock<Vec<Asset>> = OnceLock::new();
+    ASSETS.get_or_init(|| {
+        let mut assets: Vec<Asset> = FILES
+            .iter()
+            .map(|&(path, content_type, body)| Asset {
+                path,
+                content_type,
+                body: body.to_vec(),
+                etag: etag(body),
+            })
+            .collect();
+
+        // Point index.html at absolute, versioned asset URLs, so the index
+        // served for a nested client-side route still finds them
+        let versions: Vec<(&str, String)> = assets
+            .iter()
+            .filter(|a| a.path != INDEX)
+            .map(|a| (a.path, a.version().to_owned()))
+            .collect();
+        if let Some(index) = assets.iter_mut().find(|a| a.path == INDEX) {
+            let mut html = String::from_utf8_lossy(&index.body).into_owned();
+            for (path, version) in versions {
+                html = html.replace(&format!("\"{path}\""), &format!("\"/{path}?v={version}\""));
+            }
+            index.etag = etag(html.as_bytes());
+            index.body = html.into_bytes();
+        }
+
+        assets
+    })
+}
+
+pub fn router() -> Router<State> {
+    Router::new().fallback(serve)
+}
+
+/// Serve an embedded file. Unknown paths without an extension get
+/// `index.html` so client-side routes survive a reload.
+async fn serve(method: Method, uri: Uri, headers: HeaderMap) -> Result<Response> {
+    let path = uri.path().trim_start_matches('/');
+    if path.starts_with("api/") || !matches!(method, Method::GET | Method::HEAD) {
+        return Err(Error::NotFound(ErrorCode::NotFound));
+    }
+
+    let asset = match assets().iter().find(|a| a.path == path) {
+        Some(asset) => asset,
+        None if path.is_empty() || !path.contains('.') => asset(INDEX),
+        None => return Err(Error::NotFound(ErrorCode::NotFound)),
+    };
+
+    // Only the current version may be cached for good; a stale `v` gets
+    // today's body, which must not stick
+    let versioned = asset.path != INDEX
+        && uri.query().and_then(|q| q.strip_prefix("v=")) == Some(asset.version());
+    let cache_control = if versioned { IMMUTABLE } else { REVALIDATE };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(cache_control),
+    );
+    if let Ok(etag) = HeaderValue::from_str(&asset.etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+
+    let fresh = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| {
+            tags.split(',')
+                .any(|t| t.trim() == asset.etag || t.trim() == "*")
+        });
+    if fresh {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(asset.content_type),
+    );
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(asset.body.as_slice())
+    };
+    Ok((response_headers, body).into_response())
+}
+
+fn asset(path: &str) -> &'static Asset {
+    assets()
+        .iter()
+        .find(|a| a.path == path)
+        .expect("embedded asset")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get(uri: &str, if_none_match: Option<&str>) -> Response {
+        let mut request = Request::get(uri);
+        if let Some(tag) = if_none_match {
+            request = request.header(header::IF_NONE_MATCH, tag);
+        }
+        let app = router().with_state(State::test(crate::db::test_db().await));
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_serves_index_with_versioned_assets() {
+        let response = get("/", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        let version = asset("app.js").version();
+        assert!(html.contains(&format!("\"/app.js?v={version}\"")));
+
+        assert_eq!(
+            get("/", Some(&etag)).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+        // Client-side routes fall back to the app, whose asset URLs still
+        // resolve from a nested path
+        assert_eq!(get("/workout/today", None).await.status(), StatusCode::OK);
+        for asset in ["app.js", "style.css"] {
+            assert!(html.contains(&format!("\"/{asset}?v=")), "{asset}");
+            assert!(!html.contains(&format!("\"{asset}")), "{asset}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_asset_caching() {
+        let current = format!("/app.js?v={}", asset("app.js").version());
+        let response = get(&current, None).await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/javascript")
+        );
+
+        let response = get("/style.css", None).await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+        // A stale or made-up version isn't cached for good
+        let response = get("/app.js?v=abc", None).await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+
+        assert_eq!(
+            get("/missing.png", None).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(get("/api/nope", None).await.status(), StatusCode::NOT_FOUND);
+    }
+}
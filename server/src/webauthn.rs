@@ -0,0 +1,663 @@
+//! WebAuthn (passkey) registration and login ceremonies.
+//!
+//! Covers what platform passkeys use: ES256 credentials with user
+//! verification. Attestation statements aren't checked since we don't restrict
+//! authenticator models; the credential key comes from the signed-over
+//! authenticator data either way.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Duration;
+use ciborium::Value as Cbor;
+use ekman_core::{PasskeyAssertion, PasskeyRegistration};
+use p256::ecdsa::{Signature, VerifyingKey, signature::Verifier};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Error, Result,
+    config::Config,
+    db::{self, Conn},
+};
+
+const RP_NAME: &str = "ekman";
+const CHALLENGE_MINUTES: i64 = 5;
+
+// Authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED: u8 = 0x40;
+
+// COSE key labels and the values ES256 on P-256 uses
+const COSE_KTY: i64 = 1;
+const COSE_ALG: i64 = 3;
+const COSE_CRV: i64 = -1;
+const COSE_X: i64 = -2;
+const COSE_Y: i64 = -3;
+const COSE_KTY_EC2: i64 = 2;
+const COSE_ALG_ES256: i64 = -7;
+const COSE_CRV_P256: i64 = 1;
+
+/// The site passkeys are bound to. The RP ID is the domain, the origin is
+/// what browsers report in client data.
+#[derive(Debug, Clone)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    /// Without a configured origin, the web app served by this server.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            id: config.webauthn.rp_id.clone(),
+            name: RP_NAME.into(),
+            origin: config
+                .webauthn
+                .origin
+                .clone()
+                .unwrap_or_else(|| config.own_origin()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Ceremony {
+    Register,
+    Login,
+}
+
+impl Ceremony {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Register => "register",
+            Self::Login => "login",
+        }
+    }
+
+    fn client_data_type(self) -> &'static str {
+        match self {
+            Self::Register => "webauthn.create",
+            Self::Login => "webauthn.get",
+        }
+    }
+}
+
+/// A verified new credential, ready to store.
+#[derive(Debug)]
+pub struct NewPasskey {
+    pub credential_id: String,
+    /// Uncompressed SEC1 point, base64url.
+    pub public_key: String,
+    pub sign_count: u32,
+}
+
+pub fn encode(bytes: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn decode(s: &str, field: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(s.trim_end_matches('='))
+        .map_err(|_| Error::BadRequest(format!("{field} must be base64url")))
+}
+
+/// Opaque WebAuthn user handle for an account.
+pub fn user_handle(user_id: i64) -> String {
+    encode(user_id.to_be_bytes())
+}
+
+/// Issue a single-use challenge. Login challenges have no user since the
+/// passkey itself says who is signing in.
+pub async fn create_challenge(
+    conn: &Conn,
+    user_id: Option<i64>,
+    ceremony: Ceremony,
+) -> Result<String> {
+    use argon2::password_hash::rand_core::{OsRng, RngCore};
+
+    let now = db::now();
+    conn.execute(
+        "DELETE FROM webauthn_challenges WHERE expires_at < ?",
+        [db::timestamp(now)],
+    )
+    .await?;
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let challenge = encode(bytes);
+
+    conn.execute(
+        "INSERT INTO webauthn_challenges (challenge, user_id, kind, expires_at)
+         VALUES (?, ?, ?, ?)",
+        (
+            challenge.as_str(),
+            user_id,
+            ceremony.as_str(),
+            db::timestamp(now + Duration::minutes(CHALLENGE_MINUTES)),
+        ),
+    )
+    .await?;
+
+    Ok(challenge)
+}
+
+/// Consume a challenge, returning the user it was issued to.
+async fn take_challenge(conn: &Conn, challenge: &str, ceremony: Ceremony) -> Result<Option<i64>> {
+    let mut rows = conn
+        .query(
+            "SELECT user_id, expires_at FROM webauthn_challenges
+             WHERE challenge = ? AND kind = ?",
+            (challenge, ceremony.as_str()),
+        )
+        .await?;
+    let row = rows.next().await?.ok_or(Error::Unauthorized)?;
+    let user_id: Option<i64> = row.get(0)?;
+    let expires_at = db::parse_timestamp(&row.get::<String>(1)?)?;
+    drop(rows);
+
+    // Conditional delete so two concurrent responses can't share a challenge
+    let deleted = conn
+        .execute(
+            "DELETE FROM webauthn_challenges WHERE challenge = ?",
+            [challenge],
+        )
+        .await?;
+    if deleted == 0 || expires_at < db::now() {
+        return Err(Error::Unauthorized);
+    }
+
+    Ok(user_id)
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// Check the browser's client data and consume the challenge it signed.
+async fn check_client_data(
+    conn: &Conn,
+    rp: &RelyingParty,
+    client_data_json: &[u8],
+    ceremony: Ceremony,
+) -> Result<Option<i64>> {
+    let client_data: ClientData = serde_json::from_slice(client_data_json)
+        .map_err(|_| Error::BadRequest("invalid client data".into()))?;
+
+    if client_data.kind != ceremony.client_data_type()
+        || client_data.origin != rp.origin
+        || client_data.cross_origin
+    {
+        return Err(Error::Unauthorized);
+    }
+
+    take_challenge(conn, &client_data.challenge, ceremony).await
+}
+
+struct AuthenticatorData<'a> {
+    sign_count: u32,
+    /// Credential id and COSE public key, present on registration.
+    attested: Option<(&'a [u8], &'a [u8])>,
+}
+
+fn parse_authenticator_data<'a>(
+    rp: &RelyingParty,
+    data: &'a [u8],
+) -> Result<AuthenticatorData<'a>> {
+    let invalid = || Error::BadRequest("invalid authenticator data".into());
+    if data.len() < 37 {
+        return Err(invalid());
+    }
+
+    if data[..32] != Sha256::digest(rp.id.as_bytes())[..] {
+        return Err(Error::Unauthorized);
+    }
+
+    // A passkey stands in for password and TOTP, so require PIN or biometrics
+    let flags = data[32];
+    if flags & FLAG_USER_PRESENT == 0 || flags & FLAG_USER_VERIFIED == 0 {
+        return Err(Error::Unauthorized);
+    }
+
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested = if flags & FLAG_ATTESTED != 0 {
+        // 16-byte AAGUID, then a length-prefixed credential id
+        let rest = data.get(37 + 16..).ok_or_else(invalid)?;
+        let len = u16::from_be_bytes([
+            *rest.first().ok_or_else(invalid)?,
+            *rest.get(1).ok_or_else(invalid)?,
+        ]);
+        let credential_id = rest.get(2..2 + len as usize).ok_or_else(invalid)?;
+        Some((credential_id, &rest[2 + len as usize..]))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        sign_count,
+        attested,
+    })
+}
+
+fn parse_cose_key(bytes: &[u8]) -> Result<VerifyingKey> {
+    let unsupported = || Error::BadRequest("unsupported passkey, ES256 required".into());
+    let key: Cbor = ciborium::from_reader(bytes)
+        .map_err(|_| Error::BadRequest("invalid credential public key".into()))?;
+    let entries = key.as_map().ok_or_else(unsupported)?;
+
+    let get = |label: i64| {
+        entries
+            .iter()
+            .find(|(k, _)| k.as_integer() == Some(label.into()))
+            .map(|(_, v)| v)
+    };
+    let int = |label: i64| {
+        get(label)
+            .and_then(Cbor::as_integer)
+            .and_then(|i| i64::try_from(i).ok())
+    };
+
+    if int(COSE_KTY) != Some(COSE_KTY_EC2)
+        || int(COSE_ALG) != Some(COSE_ALG_ES256)
+        || int(COSE_CRV) != Some(COSE_CRV_P256)
+    {
+        return Err(unsupported());
+    }
+
+    let coordinate = |label: i64| {
+        get(label)
+            .and_then(Cbor::as_bytes)
+            .filter(|b| b.len() == 32)
+            .ok_or_else(unsupported)
+    };
+    let mut point = vec![0x04];
+    point.extend_from_slice(coordinate(COSE_X)?);
+    point.extend_from_slice(coordinate(COSE_Y)?);
+
+    VerifyingKey::from_sec1_bytes(&point).map_err(|_| unsupported())
+}
+
+/// Verify a `navigator.credentials.create()` response for `user_id`.
+pub async fn finish_registration(
+    conn: &Conn,
+    rp: &RelyingParty,
+    user_id: i64,
+    input: &PasskeyRegistration,
+) -> Result<NewPasskey> {
+    let client_data_json = decode(&input.client_data_json, "client_data_json")?;
+    let attestation_object = decode(&input.attestation_object, "attestation_object")?;
+    let credential_id = decode(&input.credential_id, "credential_id")?;
+
+    if check_client_data(conn, rp, &client_data_json, Ceremony::Register).await? != Some(user_id) {
+        return Err(Error::Unauthorized);
+    }
+
+    let invalid = || Error::BadRequest("invalid attestation object".into());
+    let attestation: Cbor =
+        ciborium::from_reader(attestation_object.as_slice()).map_err(|_| invalid())?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|m| m.iter().find(|(k, _)| k.as_text() == Some("authData")))
+        .and_then(|(_, v)| v.as_bytes())
+        .ok_or_else(invalid)?;
+
+    let data = parse_authenticator_data(rp, auth_data)?;
+    let (attested_id, cose_key) = data.attested.ok_or_else(invalid)?;
+    if attested_id != credential_id.as_slice() {
+        return Err(invalid());
+    }
+    let key = parse_cose_key(cose_key)?;
+
+    Ok(NewPasskey {
+        credential_id: encode(&credential_id),
+        public_key: encode(key.to_encoded_point(false)),
+        sign_count: data.sign_count,
+    })
+}
+
+/// Verify a `navigator.credentials.get()` response and return who signed in.
+pub async fn finish_login(conn: &Conn, rp: &RelyingParty, input: &PasskeyAssertion) -> Result<i64> {
+    let credential_id = encode(decode(&input.credential_id, "credential_id")?);
+    let client_data_json = decode(&input.client_data_json, "client_data_json")?;
+    let auth_data = decode(&input.authenticator_data, "authenticator_data")?;
+    let signature = decode(&input.signature, "signature")?;
+
+    let mut rows = conn
+        .query(
+            "SELECT id, user_id, public_key, sign_count FROM passkeys WHERE credential_id = ?",
+            [credential_id.as_str()],
+        )
+        .await?;
+    let row = rows.next().await?.ok_or(Error::Unauthorized)?;
+    let id: i64 = row.get(0)?;
+    let user_id: i64 = row.get(1)?;
+    let public_key: String = row.get(2)?;
+    let stored_count: i64 = row.get(3)?;
+    drop(rows);
+
+    if let Some(handle) = &input.user_handle
+        && decode(handle, "user_handle")? != user_id.to_be_bytes()
+    {
+        return Err(Error::Unauthorized);
+    }
+
+    check_client_data(conn, rp, &client_data_json, Ceremony::Login).await?;
+    let data = parse_authenticator_data(rp, &auth_data)?;
+
+    let key = VerifyingKey::from_sec1_bytes(&decode(&public_key, "public_key")?)
+        .map_err(|e| Error::Internal(format!("stored passkey unreadable: {e}")))?;
+    let signature = Signature::from_der(&signature).map_err(|_| Error::Unauthorized)?;
+    let mut signed = auth_data.clone();
+    signed.extend_from_slice(&Sha256::digest(&client_data_json));
+    key.verify(&signed, &signature)
+        .map_err(|_| Error::Unauthorized)?;
+
+    // Counters only move forward; synced passkeys report zero throughout
+    let sign_count = i64::from(data.sign_count);
+    if (sign_count != 0 || stored_count != 0) && sign_count <= stored_count {
+        tracing::warn!(
+            user_id,
+            passkey_id = id,
+            "passkey sign count went backwards, possible clone"
+        );
+        return Err(Error::Unauthorized);
+    }
+
+    conn.execute(
+        "UPDATE passkeys SET sign_count = ?, last_used_at = ? WHERE id = ?",
+        (sign_count, db::timestamp(db::now()), id),
+    )
+    .await?;
+
+    Ok(user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{State, auth, routes};
+    use axum::{
+        Router,
+        body::{Body, to_bytes},
+        http::{Request, StatusCode, header},
+    };
+    use ekman_core::{PasskeyCreationOptions, PasskeyRequestOptions};
+    use p256::ecdsa::{SigningKey, signature::Signer};
+    use serde_json::{Value as Json, json};
+    use std::sync::Arc;
+    use totp_rs::{Algorithm, Secret, TOTP};
+    use tower::ServiceExt;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+    const ORIGIN: &str = "https://ekman.test";
+
+    /// Software authenticator standing in for a phone's platform passkey.
+    struct SoftAuthenticator {
+        key: SigningKey,
+        credential_id: Vec<u8>,
+        sign_count: u32,
+    }
+
+    impl SoftAuthenticator {
+        fn new() -> Self {
+            Self {
+                key: SigningKey::from_bytes(&[7u8; 32].into()).unwrap(),
+                credential_id: b"soft-credential".to_vec(),
+                sign_count: 0,
+            }
+        }
+
+        fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+            serde_json::to_vec(&json!({ "type": kind, "challenge": challenge, "origin": origin }))
+                .unwrap()
+        }
+
+        fn auth_data(&mut self, rp_id: &str, attested: bool) -> Vec<u8> {
+            self.sign_count += 1;
+            let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            let flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+            data.push(if attested {
+                flags | FLAG_ATTESTED
+            } else {
+                flags
+            });
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+
+            if attested {
+                let point = self.key.verifying_key().to_encoded_point(false);
+                let cose = Cbor::Map(vec![
+                    (COSE_KTY.into(), COSE_KTY_EC2.into()),
+                    (COSE_ALG.into(), COSE_ALG_ES256.into()),
+                    (COSE_CRV.into(), COSE_CRV_P256.into()),
+                    (COSE_X.into(), Cbor::Bytes(point.x().unwrap().to_vec())),
+                    (COSE_Y.into(), Cbor::Bytes(point.y().unwrap().to_vec())),
+                ]);
+                data.extend_from_slice(&[0u8; 16]);
+                data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+                data.extend_from_slice(&self.credential_id);
+                ciborium::into_writer(&cose, &mut data).unwrap();
+            }
+            data
+        }
+
+        fn create(&mut self, options: &PasskeyCreationOptions, origin: &str) -> Json {
+            let auth_data = self.auth_data(&options.rp_id, true);
+            let attestation = Cbor::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), Cbor::Map(vec![])),
+                ("authData".into(), Cbor::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = Vec::new();
+            ciborium::into_writer(&attestation, &mut attestation_object).unwrap();
+
+            json!({
+                "name": "phone",
+                "credential_id": encode(&self.credential_id),
+                "client_data_json": encode(Self::client_data("webauthn.create", &options.challenge, origin)),
+                "attestation_object": encode(attestation_object),
+            })
+        }
+
+        fn get(&mut self, options: &PasskeyRequestOptions, origin: &str) -> Json {
+            let client_data = Self::client_data("webauthn.get", &options.challenge, origin);
+            let auth_data = self.auth_data(&options.rp_id, false);
+            let mut signed = auth_data.clone();
+            signed.extend_from_slice(&Sha256::digest(&client_data));
+            let signature: Signature = self.key.sign(&signed);
+
+            json!({
+                "credential_id": encode(&self.credential_id),
+                "client_data_json": encode(client_data),
+                "authenticator_data": encode(auth_data),
+                "signature": encode(signature.to_der()),
+            })
+        }
+    }
+
+    async fn app() -> (Router, Conn) {
+        let db = db::test_db().await;
+        let conn = Conn::from(db.connect().unwrap());
+        conn.execute(
+            "INSERT INTO users (username, password_hash, totp_secret, totp_enabled)
+             VALUES ('u', ?, ?, 1)",
+            (auth::hash_password("p").unwrap(), SECRET),
+        )
+        .await
+        .unwrap();
+
+        let state = State {
+            rp: Arc::new(RelyingParty {
+                id: "ekman.test".into(),
+                name: RP_NAME.into(),
+                origin: ORIGIN.into(),
+            }),
+            ..State::test(db)
+        };
+        (Router::from(routes::api()).with_state(state), conn)
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        cookie: Option<&str>,
+        body: Json,
+    ) -> (StatusCode, Option<String>, Json) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::from(body.to_string())).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let cookie = response
+            .headers()
+            .get(header::SET_COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.split(';').next())
+            .map(String::from);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (
+            status,
+            cookie,
+            serde_json::from_slice(&bytes).unwrap_or(Json::Null),
+        )
+    }
+
+    fn totp_now() -> String {
+        let bytes = Secret::Encoded(SECRET.into()).to_bytes().unwrap();
+        TOTP::new_unchecked(Algorithm::SHA1, 6, 0, 30, bytes, None, String::new())
+            .generate_current()
+            .unwrap()
+    }
+
+    /// Sign in and register the soft authenticator, returning the session cookie.
+    async fn register_passkey(
+        app: &Router,
+        conn: &mut Conn,
+        authenticator: &mut SoftAuthenticator,
+    ) -> String {
+        let (token, _) = auth::create_session(
+            conn,
+            1,
+            &auth::ClientInfo::default(),
+            chrono::Duration::days(30),
+        )
+        .await
+        .unwrap();
+        let cookie = format!("ekman_session={token}");
+
+        let (status, _, options) = call(
+            app,
+            "POST",
+            "/api/v1/auth/passkeys/register/start",
+            Some(&cookie),
+            json!({ "password": "p", "totp": totp_now() }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let options: PasskeyCreationOptions = serde_json::from_value(options).unwrap();
+        assert_eq!(options.user_id, user_handle(1));
+
+        let (status, _, passkey) = call(
+            app,
+            "POST",
+            "/api/v1/auth/passkeys/register/finish",
+            Some(&cookie),
+            authenticator.create(&options, ORIGIN),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{passkey}");
+        cookie
+    }
+
+    async fn login_options(app: &Router) -> PasskeyRequestOptions {
+        let (status, _, options) = call(
+            app,
+            "POST",
+            "/api/v1/auth/passkeys/login/start",
+            None,
+            Json::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        serde_json::from_value(options).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_passkey_register_and_login() {
+        let (app, mut conn) = app().await;
+        let mut authenticator = SoftAuthenticator::new();
+        register_passkey(&app, &mut conn, &mut authenticator).await;
+
+        let options = login_options(&app).await;
+        let (status, cookie, session) = call(
+            &app,
+            "POST",
+            "/api/v1/auth/passkeys/login/finish",
+            None,
+            authenticator.get(&options, ORIGIN),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(session["username"], "u");
+
+        let (status, _, me) = call(
+            &app,
+            "GET",
+            "/api/v1/auth/me",
+            cookie.as_deref(),
+            Json::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(me["username"], "u");
+    }
+
+    #[tokio::test]
+    async fn test_passkey_login_rejects_replay_and_wrong_origin() {
+        let (app, mut conn) = app().await;
+        let mut authenticator = SoftAuthenticator::new();
+        register_passkey(&app, &mut conn, &mut authenticator).await;
+
+        let options = login_options(&app).await;
+        let assertion = authenticator.get(&options, ORIGIN);
+        let finish = |body: Json| {
+            call(
+                &app,
+                "POST",
+                "/api/v1/auth/passkeys/login/finish",
+                None,
+                body,
+            )
+        };
+
+        assert_eq!(finish(assertion.clone()).await.0, StatusCode::OK);
+        // Challenge already consumed
+        assert_eq!(finish(assertion).await.0, StatusCode::UNAUTHORIZED);
+
+        let options = login_options(&app).await;
+        let phished = authenticator.get(&options, "https://ekman.evil");
+        assert_eq!(finish(phished).await.0, StatusCode::UNAUTHORIZED);
+
+        // A cloned authenticator replays an old counter
+        let options = login_options(&app).await;
+        authenticator.sign_count = 1;
+        let cloned = authenticator.get(&options, ORIGIN);
+        assert_eq!(finish(cloned).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
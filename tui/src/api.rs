@@ -1,9 +1,13 @@
 //! HTTP API client with background task.
 
 use chrono::NaiveDate;
-use ekman_core::{
-    Activity, ActivityQuery, DaySets, Exercise, Graph, GraphQuery, LoginInput, RegisterInput,
-    Session, SetInput, Template, User, WeightEntry, WeightInput, WorkoutSet,
+use ekman_core::v1::{
+    Activity, ActivityQuery, ChangePasswordInput, DaySets, DeleteAccountInput, Exercise,
+    ExerciseHistory, HistoryQuery, LoginInput, MeasurementEntry, MeasurementGraph,
+    MeasurementHistory, MeasurementInput, MeasurementKind, MeasurementQuery, Metric, MultiGraph,
+    MultiGraphQuery, Overlay, Profile, RecoveryCodes, RegenerateRecoveryCodes, RegisterInput,
+    Session, SetInput, StrengthReport, Template, User, WeightEntry, WeightInput, WeightTrend,
+    WeightTrendQuery, WorkoutSet,
 };
 use reqwest::{
     Client, Url,
@@ -31,7 +35,14 @@ pub enum Request {
     CheckSession,
     LoadPlans,
     LoadExercises,
-    LoadGraph(i64),
+    LoadGraphs {
+        exercise_ids: Vec<i64>,
+        metric: Metric,
+    },
+    LoadHistory {
+        exercise_id: i64,
+        before: Option<NaiveDate>,
+    },
     LoadActivity(ActivityQuery),
     LoadSets {
         day: NaiveDate,
@@ -78,6 +89,30 @@ pub enum Request {
     DeleteWeight {
         day: NaiveDate,
     },
+    LoadWeightTrend(WeightTrendQuery),
+    LoadMeasurementKinds,
+    LoadMeasurements {
+        day: NaiveDate,
+    },
+    SaveMeasurement {
+        kind: String,
+        day: NaiveDate,
+        input: MeasurementInput,
+    },
+    DeleteMeasurement {
+        kind: String,
+        day: NaiveDate,
+    },
+    LoadMeasurementGraph {
+        kind: String,
+    },
+    LoadProfile,
+    SaveProfile(Profile),
+    LoadStrength,
+    ChangePassword(ChangePasswordInput),
+    DeleteAccount(DeleteAccountInput),
+    LogoutEverywhere,
+    RegenerateRecoveryCodes(RegenerateRecoveryCodes),
 }
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -95,7 +130,16 @@ pub enum Response {
     SessionChecked(Result<User, String>),
     Plans(Result<Vec<Template>, String>),
     Exercises(Result<Vec<Exercise>, String>),
-    Graph(i64, Result<Graph, String>),
+    Graphs {
+        exercise_ids: Vec<i64>,
+        metric: Metric,
+        result: Result<MultiGraph, String>,
+    },
+    History {
+        exercise_id: i64,
+        before: Option<NaiveDate>,
+        result: Result<ExerciseHistory, String>,
+    },
     Activity(Result<Activity, String>),
     SetsLoaded {
         exercise_id: i64,
@@ -130,6 +174,29 @@ pub enum Response {
         day: NaiveDate,
         result: Result<(), String>,
     },
+    WeightTrend(Result<WeightTrend, String>),
+    MeasurementKinds(Result<Vec<MeasurementKind>, String>),
+    MeasurementsLoaded {
+        day: NaiveDate,
+        result: Result<MeasurementHistory, String>,
+    },
+    MeasurementSaved {
+        day: NaiveDate,
+        result: Result<MeasurementEntry, String>,
+    },
+    MeasurementDeleted {
+        kind: String,
+        day: NaiveDate,
+        result: Result<(), String>,
+    },
+    MeasurementGraph(Result<MeasurementGraph, String>),
+    Profile(Result<Profile, String>),
+    ProfileSaved(Result<Profile, String>),
+    Strength(Result<StrengthReport, String>),
+    PasswordChanged(Result<(), String>),
+    AccountDeleted(Result<(), String>),
+    LoggedOutEverywhere(Result<(), String>),
+    RecoveryCodes(Result<RecoveryCodes, String>),
 }
 
 pub struct ApiClient {
@@ -190,6 +257,10 @@ impl ApiClient {
             let _ = fs::write(&self.cookie_path, persisted);
         }
     }
+
+    pub fn clear_cookie(&self) {
+        let _ = fs::remove_file(&self.cookie_path);
+    }
 }
 
 async fn run_worker(client: Client, mut rx: mpsc::Receiver<Request>, tx: mpsc::Sender<Response>) {
@@ -209,7 +280,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
             totp,
         } => {
             let result = client
-                .post(format!("{BASE_URL}/api/auth/login"))
+                .post(format!("{BASE_URL}/api/v1/auth/login"))
                 .json(&LoginInput {
                     username,
                     password,
@@ -232,7 +303,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
             totp_code,
         } => {
             let result = client
-                .post(format!("{BASE_URL}/api/auth/register"))
+                .post(format!("{BASE_URL}/api/v1/auth/register"))
                 .json(&RegisterInput {
                     username,
                     password,
@@ -251,7 +322,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::CheckSession => {
             let result = client
-                .get(format!("{BASE_URL}/api/auth/me"))
+                .get(format!("{BASE_URL}/api/v1/auth/me"))
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
@@ -264,7 +335,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::LoadPlans => {
             let result = client
-                .get(format!("{BASE_URL}/api/plans/daily"))
+                .get(format!("{BASE_URL}/api/v1/plans/daily"))
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
@@ -275,27 +346,63 @@ async fn handle_request(client: &Client, req: Request) -> Response {
             }
         }
 
-        Request::LoadGraph(id) => {
+        Request::LoadGraphs {
+            exercise_ids,
+            metric,
+        } => {
+            let query = MultiGraphQuery {
+                overlay: Some(Overlay::MovingAverage),
+                ..MultiGraphQuery::new(&exercise_ids, &[metric])
+            };
             let result = client
-                .get(format!("{BASE_URL}/api/exercises/{id}/graph"))
-                .query(&GraphQuery {
-                    start: None,
-                    end: None,
-                    metric: None,
+                .get(format!("{BASE_URL}/api/v1/graphs"))
+                .query(&query)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            let result = match result {
+                Ok(r) => r.json().await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            Response::Graphs {
+                exercise_ids,
+                metric,
+                result,
+            }
+        }
+
+        Request::LoadHistory {
+            exercise_id,
+            before,
+        } => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/exercises/{exercise_id}/history"))
+                .query(&HistoryQuery {
+                    before,
+                    limit: None,
                 })
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
 
             match result {
-                Ok(r) => Response::Graph(id, r.json().await.map_err(|e| e.to_string())),
-                Err(e) => Response::Graph(id, Err(e.to_string())),
+                Ok(r) => Response::History {
+                    exercise_id,
+                    before,
+                    result: r.json().await.map_err(|e| e.to_string()),
+                },
+                Err(e) => Response::History {
+                    exercise_id,
+                    before,
+                    result: Err(e.to_string()),
+                },
             }
         }
 
         Request::LoadActivity(query) => {
             let result = client
-                .get(format!("{BASE_URL}/api/activity/days"))
+                .get(format!("{BASE_URL}/api/v1/activity/days"))
                 .query(&query)
                 .send()
                 .await
@@ -310,7 +417,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
         Request::LoadSets { day, exercise_id } => {
             let result = client
                 .get(format!(
-                    "{BASE_URL}/api/days/{}/exercises/{exercise_id}/sets",
+                    "{BASE_URL}/api/v1/days/{}/exercises/{exercise_id}/sets",
                     day.format("%Y-%m-%d")
                 ))
                 .send()
@@ -339,7 +446,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
         } => {
             let result = client
                 .put(format!(
-                    "{BASE_URL}/api/days/{}/exercises/{exercise_id}/sets/{set_number}",
+                    "{BASE_URL}/api/v1/days/{}/exercises/{exercise_id}/sets/{set_number}",
                     day.format("%Y-%m-%d")
                 ))
                 .json(&input)
@@ -370,7 +477,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
         } => {
             let result = client
                 .delete(format!(
-                    "{BASE_URL}/api/days/{}/exercises/{exercise_id}/sets/{set_number}",
+                    "{BASE_URL}/api/v1/days/{}/exercises/{exercise_id}/sets/{set_number}",
                     day.format("%Y-%m-%d")
                 ))
                 .send()
@@ -387,7 +494,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::LoadExercises => {
             let result = client
-                .get(format!("{BASE_URL}/api/exercises"))
+                .get(format!("{BASE_URL}/api/v1/exercises"))
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
@@ -403,7 +510,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
             exercise_id,
         } => {
             let result = client
-                .post(format!("{BASE_URL}/api/plans/{template_id}/exercises"))
+                .post(format!("{BASE_URL}/api/v1/plans/{template_id}/exercises"))
                 .json(&serde_json::json!({ "exercise_id": exercise_id }))
                 .send()
                 .await
@@ -418,7 +525,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
         } => {
             let result = client
                 .delete(format!(
-                    "{BASE_URL}/api/plans/{template_id}/exercises/{exercise_id}"
+                    "{BASE_URL}/api/v1/plans/{template_id}/exercises/{exercise_id}"
                 ))
                 .send()
                 .await
@@ -429,7 +536,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::CreatePlan { name, day_of_week } => {
             let result = client
-                .post(format!("{BASE_URL}/api/plans"))
+                .post(format!("{BASE_URL}/api/v1/plans"))
                 .json(&serde_json::json!({ "name": name, "day_of_week": day_of_week }))
                 .send()
                 .await
@@ -443,7 +550,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::CreateExercise { name } => {
             let result = client
-                .post(format!("{BASE_URL}/api/exercises"))
+                .post(format!("{BASE_URL}/api/v1/exercises"))
                 .json(&serde_json::json!({ "name": name }))
                 .send()
                 .await
@@ -465,7 +572,7 @@ async fn handle_request(client: &Client, req: Request) -> Response {
             }
 
             let result = client
-                .patch(format!("{BASE_URL}/api/exercises/{id}"))
+                .patch(format!("{BASE_URL}/api/v1/exercises/{id}"))
                 .json(&body)
                 .send()
                 .await
@@ -479,7 +586,10 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::LoadWeight { day } => {
             let result = client
-                .get(format!("{BASE_URL}/api/weight/{}", day.format("%Y-%m-%d")))
+                .get(format!(
+                    "{BASE_URL}/api/v1/weight/{}",
+                    day.format("%Y-%m-%d")
+                ))
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
@@ -498,7 +608,10 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::SaveWeight { day, input } => {
             let result = client
-                .put(format!("{BASE_URL}/api/weight/{}", day.format("%Y-%m-%d")))
+                .put(format!(
+                    "{BASE_URL}/api/v1/weight/{}",
+                    day.format("%Y-%m-%d")
+                ))
                 .json(&input)
                 .send()
                 .await
@@ -518,7 +631,10 @@ async fn handle_request(client: &Client, req: Request) -> Response {
 
         Request::DeleteWeight { day } => {
             let result = client
-                .delete(format!("{BASE_URL}/api/weight/{}", day.format("%Y-%m-%d")))
+                .delete(format!(
+                    "{BASE_URL}/api/v1/weight/{}",
+                    day.format("%Y-%m-%d")
+                ))
                 .send()
                 .await
                 .and_then(|r| r.error_for_status());
@@ -528,5 +644,196 @@ async fn handle_request(client: &Client, req: Request) -> Response {
                 result: result.map(|_| ()).map_err(|e| e.to_string()),
             }
         }
+
+        Request::LoadWeightTrend(query) => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/weight/trend"))
+                .query(&query)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::WeightTrend(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::WeightTrend(Err(e.to_string())),
+            }
+        }
+
+        Request::LoadMeasurementKinds => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/measurements/kinds"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::MeasurementKinds(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::MeasurementKinds(Err(e.to_string())),
+            }
+        }
+
+        Request::LoadMeasurements { day } => {
+            let at = day.and_hms_opt(12, 0, 0).unwrap().and_utc();
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/measurements"))
+                .query(&MeasurementQuery {
+                    kind: None,
+                    start: Some(at),
+                    end: Some(at),
+                })
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::MeasurementsLoaded {
+                    day,
+                    result: r.json().await.map_err(|e| e.to_string()),
+                },
+                Err(e) => Response::MeasurementsLoaded {
+                    day,
+                    result: Err(e.to_string()),
+                },
+            }
+        }
+
+        Request::SaveMeasurement { kind, day, input } => {
+            let result = client
+                .put(format!(
+                    "{BASE_URL}/api/v1/measurements/{kind}/{}",
+                    day.format("%Y-%m-%d")
+                ))
+                .json(&input)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::MeasurementSaved {
+                    day,
+                    result: r.json().await.map_err(|e| e.to_string()),
+                },
+                Err(e) => Response::MeasurementSaved {
+                    day,
+                    result: Err(e.to_string()),
+                },
+            }
+        }
+
+        Request::DeleteMeasurement { kind, day } => {
+            let result = client
+                .delete(format!(
+                    "{BASE_URL}/api/v1/measurements/{kind}/{}",
+                    day.format("%Y-%m-%d")
+                ))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            Response::MeasurementDeleted {
+                kind,
+                day,
+                result: result.map(|_| ()).map_err(|e| e.to_string()),
+            }
+        }
+
+        Request::LoadMeasurementGraph { kind } => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/measurements/{kind}/graph"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::MeasurementGraph(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::MeasurementGraph(Err(e.to_string())),
+            }
+        }
+
+        Request::LoadProfile => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/profile"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::Profile(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::Profile(Err(e.to_string())),
+            }
+        }
+
+        Request::SaveProfile(profile) => {
+            let result = client
+                .put(format!("{BASE_URL}/api/v1/profile"))
+                .json(&profile)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::ProfileSaved(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::ProfileSaved(Err(e.to_string())),
+            }
+        }
+
+        Request::LoadStrength => {
+            let result = client
+                .get(format!("{BASE_URL}/api/v1/reports/strength"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::Strength(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::Strength(Err(e.to_string())),
+            }
+        }
+
+        Request::ChangePassword(input) => {
+            let result = client
+                .post(format!("{BASE_URL}/api/v1/auth/password"))
+                .json(&input)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            Response::PasswordChanged(result.map(|_| ()).map_err(|e| e.to_string()))
+        }
+
+        Request::DeleteAccount(input) => {
+            let result = client
+                .delete(format!("{BASE_URL}/api/v1/account"))
+                .json(&input)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            Response::AccountDeleted(result.map(|_| ()).map_err(|e| e.to_string()))
+        }
+
+        Request::LogoutEverywhere => {
+            let result = client
+                .delete(format!("{BASE_URL}/api/v1/auth/sessions"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            Response::LoggedOutEverywhere(result.map(|_| ()).map_err(|e| e.to_string()))
+        }
+
+        Request::RegenerateRecoveryCodes(input) => {
+            let result = client
+                .post(format!("{BASE_URL}/api/v1/auth/recovery-codes"))
+                .json(&input)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(r) => Response::RecoveryCodes(r.json().await.map_err(|e| e.to_string())),
+                Err(e) => Response::RecoveryCodes(Err(e.to_string())),
+            }
+        }
     }
 }
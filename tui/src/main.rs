@@ -46,6 +46,8 @@ fn run(app: &mut App, terminal: &mut ratatui::DefaultTerminal) -> color_eyre::Re
                 View::Workout => handle_workout_key(app, key.code, key.modifiers),
                 View::Manage => handle_manage_key(app, key.code, key.modifiers),
                 View::Exercises => handle_exercises_key(app, key.code, key.modifiers),
+                View::Body => handle_body_key(app, key.code, key.modifiers),
+                View::Settings => handle_settings_key(app, key.code, key.modifiers),
             }
         }
 
@@ -95,6 +97,14 @@ fn handle_workout_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
         app.switch_to_exercises();
         return;
     }
+    if code == F(4) {
+        app.switch_to_body();
+        return;
+    }
+    if code == F(5) {
+        app.switch_to_settings();
+        return;
+    }
 
     // Weight row is selected
     if app.weight_selected {
@@ -147,6 +157,9 @@ fn handle_workout_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
         // Delete set
         Char('d') => app.delete_current_set(),
 
+        // Cycle graph metric
+        Char('m') => app.cycle_graph_metric(),
+
         // Digit input
         Char(ch) if ch.is_ascii_digit() || ch == '.' => app.input_char(ch),
 
@@ -180,6 +193,16 @@ fn handle_manage_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
         return;
     }
 
+    if code == F(4) {
+        app.switch_to_body();
+        return;
+    }
+
+    if code == F(5) {
+        app.switch_to_settings();
+        return;
+    }
+
     if code == Char('q') && app.manage.mode == ManageMode::Browse {
         app.running = false;
         return;
@@ -252,6 +275,16 @@ fn handle_exercises_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
         return;
     }
 
+    if code == F(4) {
+        app.switch_to_body();
+        return;
+    }
+
+    if code == F(5) {
+        app.switch_to_settings();
+        return;
+    }
+
     if code == Char('q') && app.exercise_edit.mode == ExerciseEditMode::Browse {
         app.running = false;
         return;
@@ -280,6 +313,13 @@ fn handle_exercises_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
             // Toggle showing archived
             Char('h') => app.exercise_toggle_archived(),
 
+            // Designate as squat/bench/deadlift for strength scores
+            Char('l') => app.exercise_cycle_lift(),
+
+            // Scroll session history
+            PageDown => app.history_scroll(5),
+            PageUp => app.history_scroll(-5),
+
             _ => {}
         },
 
@@ -298,3 +338,137 @@ fn handle_exercises_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
         },
     }
 }
+
+fn handle_body_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
+    use KeyCode::*;
+
+    // Global keys
+    if code == Esc {
+        if app.body.adding_kind {
+            app.body_cancel_add_kind();
+        } else {
+            app.running = false;
+        }
+        return;
+    }
+
+    if code == F(1) {
+        app.switch_to_workout();
+        return;
+    }
+
+    if code == F(2) {
+        app.switch_to_manage();
+        return;
+    }
+
+    if code == F(3) {
+        app.switch_to_exercises();
+        return;
+    }
+
+    if code == F(5) {
+        app.switch_to_settings();
+        return;
+    }
+
+    if code == Char('c') && mods.contains(KeyModifiers::CONTROL) {
+        app.running = false;
+        return;
+    }
+
+    if app.body.adding_kind {
+        match code {
+            Enter => app.body_confirm_add_kind(),
+            Backspace if app.body.new_kind.is_empty() => app.body_cancel_add_kind(),
+            Backspace => app.body_kind_backspace(),
+            Char(ch) if !ch.is_control() => app.body_kind_input(ch),
+            _ => {}
+        }
+        return;
+    }
+
+    if code == Char('q') {
+        app.running = false;
+        return;
+    }
+
+    match code {
+        // Kind navigation
+        Down | Char('n') => app.body_select(1),
+        Up | Char('e') => app.body_select(-1),
+
+        // Day navigation
+        Char('a') => app.move_day(-1),
+        Char('s') => app.move_day(1),
+        Char('r') => app.jump_to_today(),
+
+        // Value entry
+        Char(ch) if ch.is_ascii_digit() || ch == '.' => app.body_input_char(ch),
+        Backspace => app.body_backspace(),
+        Enter => app.body_save(),
+        Char('d') => app.body_delete(),
+
+        // Custom kind
+        Char('k') => app.body_start_add_kind(),
+
+        // Profile sex for strength scores
+        Char('x') => app.cycle_sex(),
+
+        _ => {}
+    }
+}
+
+fn handle_settings_key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
+    use KeyCode::*;
+
+    // Global keys
+    if code == Esc {
+        if app.settings.confirm_delete {
+            app.settings.confirm_delete = false;
+            app.status.clear();
+        } else {
+            app.running = false;
+        }
+        return;
+    }
+
+    if code == F(1) {
+        app.switch_to_workout();
+        return;
+    }
+
+    if code == F(2) {
+        app.switch_to_manage();
+        return;
+    }
+
+    if code == F(3) {
+        app.switch_to_exercises();
+        return;
+    }
+
+    if code == F(4) {
+        app.switch_to_body();
+        return;
+    }
+
+    if code == Char('c') && mods.contains(KeyModifiers::CONTROL) {
+        app.running = false;
+        return;
+    }
+
+    match code {
+        Tab | Down => app.settings.next_field(),
+        BackTab | Up => app.settings.prev_field(),
+        Enter => app.settings_change_password(),
+        Char('x') if mods.contains(KeyModifiers::CONTROL) => app.settings_delete_account(),
+        Char('l') if mods.contains(KeyModifiers::CONTROL) => app.settings_logout_everywhere(),
+        Char('r') if mods.contains(KeyModifiers::CONTROL) => {
+            app.settings_regenerate_recovery_codes()
+        }
+        Backspace => app.settings.backspace(),
+        Char(ch) => app.settings.push_char(ch),
+        _ => {}
+    }
+}
@@ -2,9 +2,12 @@
 
 use base32::{Alphabet, encode as b32_encode};
 use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Utc};
-use ekman_core::{
-    ActivityDay, ActivityQuery, DaySets, Exercise, Graph, SetInput, Template, TemplateExercise,
-    WeightEntry, WeightInput, WorkoutSet,
+use ekman_core::v1::{
+    ActivityDay, ActivityQuery, ChangePasswordInput, DaySets, DeleteAccountInput, Exercise,
+    GraphSeries, HistorySession, Lift, MeasurementEntry, MeasurementGraph, MeasurementInput,
+    MeasurementKind, Metric, Profile, RegenerateRecoveryCodes, SetInput, Sex, StrengthReport,
+    Template, TemplateExercise, WeightEntry, WeightInput, WeightTrend, WeightTrendQuery,
+    WorkoutSet,
 };
 use rand::{RngCore, rngs::OsRng};
 use std::collections::HashSet;
@@ -15,6 +18,8 @@ use crate::api::{ApiClient, Request, Response};
 
 const INPUT_TIMEOUT: StdDuration = StdDuration::from_secs(1);
 const ACTIVITY_DAYS: i64 = 21;
+const HISTORY_PREFETCH: usize = 5;
+const WEIGHT_TREND_DAYS: i64 = 90;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum View {
@@ -22,6 +27,8 @@ pub enum View {
     Workout,
     Manage,
     Exercises,
+    Body,
+    Settings,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -31,6 +38,13 @@ pub enum AuthField {
     Totp,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    Password,
+    NewPassword,
+    Totp,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Focus {
     Weight,
@@ -56,7 +70,8 @@ pub struct App {
     pub auth: AuthState,
     pub day: NaiveDate,
     pub exercises: Vec<ExerciseState>,
-    pub graphs: Vec<Graph>,
+    pub graphs: Vec<GraphSeries>,
+    pub graph_metric: Metric,
     pub activity: Vec<ActivityDay>,
     pub selected: usize,
     pub status: String,
@@ -64,6 +79,10 @@ pub struct App {
     pub exercise_edit: ExerciseEditState,
     pub weight_selected: bool,
     pub weight: WeightState,
+    pub body: BodyState,
+    pub settings: SettingsState,
+    pub profile: Profile,
+    pub strength: Option<StrengthReport>,
     api: ApiClient,
     plans: Vec<Template>,
     all_exercises: Vec<Exercise>,
@@ -76,6 +95,19 @@ pub struct WeightState {
     pub input: String,
     pub default_weight: f64,
     pub pending: bool,
+    pub trend: Option<WeightTrend>,
+}
+
+/// Account settings: password change and account deletion.
+pub struct SettingsState {
+    pub field: SettingsField,
+    pub password: String,
+    pub new_password: String,
+    pub totp_code: String,
+    pub confirm_delete: bool,
+    pub submitting: bool,
+    /// Freshly issued codes; cleared when leaving the screen.
+    pub recovery_codes: Vec<String>,
 }
 
 pub struct AuthState {
@@ -168,6 +200,73 @@ pub struct ExerciseEditState {
     pub show_archived: bool,
     pub input: String,
     pub filtered: Vec<Exercise>,
+    pub history: HistoryState,
+}
+
+/// Paginated session log for the selected exercise.
+pub struct HistoryState {
+    pub exercise_id: Option<i64>,
+    pub sessions: Vec<HistorySession>,
+    pub next_before: Option<NaiveDate>,
+    pub scroll: usize,
+    pub loading: bool,
+}
+
+impl HistoryState {
+    pub fn new() -> Self {
+        Self {
+            exercise_id: None,
+            sessions: Vec::new(),
+            next_before: None,
+            scroll: 0,
+            loading: false,
+        }
+    }
+
+    pub fn reset(&mut self, exercise_id: Option<i64>) {
+        *self = Self::new();
+        self.exercise_id = exercise_id;
+    }
+
+    /// True when the view is close enough to the end to fetch the next page.
+    fn wants_more(&self) -> bool {
+        !self.loading
+            && self.next_before.is_some()
+            && self.scroll + HISTORY_PREFETCH >= self.sessions.len()
+    }
+}
+
+/// Body measurements for the current day.
+pub struct BodyState {
+    pub kinds: Vec<MeasurementKind>,
+    pub selected: usize,
+    pub entries: Vec<MeasurementEntry>,
+    pub input: String,
+    pub adding_kind: bool,
+    pub new_kind: String,
+    pub graph: Option<MeasurementGraph>,
+}
+
+impl BodyState {
+    pub fn new() -> Self {
+        Self {
+            kinds: Vec::new(),
+            selected: 0,
+            entries: Vec::new(),
+            input: String::new(),
+            adding_kind: false,
+            new_kind: String::new(),
+            graph: None,
+        }
+    }
+
+    pub fn selected_kind(&self) -> Option<&MeasurementKind> {
+        self.kinds.get(self.selected)
+    }
+
+    pub fn entry(&self, kind: &str) -> Option<&MeasurementEntry> {
+        self.entries.iter().find(|e| e.kind == kind)
+    }
 }
 
 impl WeightState {
@@ -177,6 +276,7 @@ impl WeightState {
             input: String::new(),
             default_weight: 70.0, // Reasonable default
             pending: false,
+            trend: None,
         }
     }
 
@@ -213,6 +313,7 @@ impl ExerciseEditState {
             show_archived: false,
             input: String::new(),
             filtered: Vec::new(),
+            history: HistoryState::new(),
         }
     }
 
@@ -259,6 +360,7 @@ impl App {
             day: Utc::now().date_naive(),
             exercises: Vec::new(),
             graphs: Vec::new(),
+            graph_metric: Metric::MaxWeight,
             activity: Vec::new(),
             selected: 0,
             status: String::new(),
@@ -266,6 +368,10 @@ impl App {
             exercise_edit: ExerciseEditState::new(),
             weight_selected: false,
             weight: WeightState::new(),
+            body: BodyState::new(),
+            settings: SettingsState::new(),
+            profile: Profile::default(),
+            strength: None,
             api,
             plans: Vec::new(),
             all_exercises: Vec::new(),
@@ -304,7 +410,12 @@ impl App {
             Response::LoggedIn(result) | Response::Registered(result) => {
                 self.auth.submitting = false;
                 match result {
-                    Ok(session) => self.on_logged_in(session.username),
+                    Ok(session) => {
+                        self.on_logged_in(session.username);
+                        if !session.recovery_codes.is_empty() {
+                            self.show_recovery_codes(session.recovery_codes);
+                        }
+                    }
                     Err(e) => self.auth.status = e,
                 }
             }
@@ -325,20 +436,50 @@ impl App {
                 }
             },
 
-            Response::Graph(id, result) => {
-                self.pending_graphs.remove(&id);
+            Response::Graphs {
+                exercise_ids,
+                metric,
+                result,
+            } => {
+                for id in &exercise_ids {
+                    self.pending_graphs.remove(id);
+                }
+                if metric != self.graph_metric {
+                    return;
+                }
                 match result {
                     Ok(graph) => {
-                        if let Some(g) = self.graphs.iter_mut().find(|g| g.exercise_id == id) {
-                            *g = graph;
-                        } else {
-                            self.graphs.push(graph);
-                        }
+                        self.graphs
+                            .retain(|g| !exercise_ids.contains(&g.exercise_id));
+                        self.graphs.extend(graph.series);
                     }
                     Err(e) => self.status = format!("Graph error: {e}"),
                 }
             }
 
+            Response::History {
+                exercise_id,
+                before,
+                result,
+            } => {
+                let history = &mut self.exercise_edit.history;
+                if history.exercise_id != Some(exercise_id) {
+                    return;
+                }
+                history.loading = false;
+                match result {
+                    Ok(page) => {
+                        if before.is_none() {
+                            history.sessions.clear();
+                            history.scroll = 0;
+                        }
+                        history.sessions.extend(page.sessions);
+                        history.next_before = page.next_before;
+                    }
+                    Err(e) => self.status = format!("History error: {e}"),
+                }
+            }
+
             Response::Activity(result) => match result {
                 Ok(activity) => self.activity = activity.days,
                 Err(e) => self.status = format!("Activity error: {e}"),
@@ -422,6 +563,7 @@ impl App {
                     }
                     if self.view == View::Exercises {
                         self.exercise_edit.update_filtered(&self.all_exercises);
+                        self.request_history();
                     }
                 }
                 Err(e) => self.status = format!("Load exercises error: {e}"),
@@ -430,6 +572,11 @@ impl App {
             Response::PlanCreated(result) => match result {
                 Ok(plan) => {
                     self.status = format!("Created plan: {}", plan.name);
+                    // Show the day the plan was created for, in case the
+                    // selection moved while the request was in flight
+                    if let Some(day) = plan.day_of_week.filter(|d| (0..7).contains(d)) {
+                        self.manage.selected_day = day as usize;
+                    }
                     // If we have a pending exercise to add, add it now
                     if let Some(exercise_id) = self.manage.pending_exercise_id.take() {
                         self.api.send(Request::AddExerciseToPlan {
@@ -503,6 +650,7 @@ impl App {
                         self.weight.entry = Some(entry);
                         self.weight.input.clear();
                         self.weight.pending = false;
+                        self.request_weight_trend();
                     }
                     Err(e) => {
                         self.status = format!("Save weight error: {e}");
@@ -521,13 +669,153 @@ impl App {
                         self.weight.entry = None;
                         self.weight.input.clear();
                         self.weight.pending = false;
+                        self.request_weight_trend();
                     }
                     Err(e) => self.status = format!("Delete weight error: {e}"),
                 }
             }
+
+            Response::WeightTrend(result) => match result {
+                Ok(trend) => self.weight.trend = Some(trend),
+                Err(e) => self.status = format!("Weight trend error: {e}"),
+            },
+
+            Response::MeasurementKinds(result) => match result {
+                Ok(kinds) => {
+                    // Keep custom kinds added locally but not yet logged
+                    let local: Vec<_> = self
+                        .body
+                        .kinds
+                        .drain(..)
+                        .filter(|k| !kinds.iter().any(|s| s.kind == k.kind))
+                        .collect();
+                    self.body.kinds = kinds;
+                    self.body.kinds.extend(local);
+                    self.body.selected = self
+                        .body
+                        .selected
+                        .min(self.body.kinds.len().saturating_sub(1));
+                    self.request_measurement_graph();
+                }
+                Err(e) => self.status = format!("Load measurements error: {e}"),
+            },
+
+            Response::MeasurementsLoaded { day, result } => {
+                if day != self.day {
+                    return;
+                }
+                match result {
+                    Ok(history) => {
+                        self.body.entries = history.entries;
+                        self.body.input.clear();
+                    }
+                    Err(e) => self.status = format!("Load measurements error: {e}"),
+                }
+            }
+
+            Response::MeasurementSaved { day, result } => match result {
+                Ok(entry) => {
+                    self.status = format!("Saved {}: {:.1}", entry.kind, entry.value);
+                    if day == self.day {
+                        self.body.entries.retain(|e| e.kind != entry.kind);
+                        self.body.entries.push(entry);
+                        self.body.input.clear();
+                    }
+                    self.request_measurement_graph();
+                }
+                Err(e) => self.status = format!("Save measurement error: {e}"),
+            },
+
+            Response::MeasurementDeleted { kind, day, result } => match result {
+                Ok(()) => {
+                    self.status = format!("Deleted {kind}");
+                    if day == self.day {
+                        self.body.entries.retain(|e| e.kind != kind);
+                    }
+                    self.request_measurement_graph();
+                }
+                Err(e) => self.status = format!("Delete measurement error: {e}"),
+            },
+
+            Response::MeasurementGraph(result) => match result {
+                Ok(graph) => {
+                    if self
+                        .body
+                        .selected_kind()
+                        .is_some_and(|k| k.kind == graph.kind)
+                    {
+                        self.body.graph = Some(graph);
+                    }
+                }
+                Err(e) => self.status = format!("Measurement graph error: {e}"),
+            },
+
+            Response::Profile(result) => match result {
+                Ok(profile) => self.profile = profile,
+                Err(e) => self.status = format!("Load profile error: {e}"),
+            },
+
+            Response::ProfileSaved(result) => match result {
+                Ok(profile) => {
+                    self.status = "Profile saved".into();
+                    self.profile = profile;
+                    self.api.send(Request::LoadStrength);
+                }
+                Err(e) => self.status = format!("Save profile error: {e}"),
+            },
+
+            Response::Strength(result) => match result {
+                Ok(report) => self.strength = Some(report),
+                Err(e) => self.status = format!("Strength report error: {e}"),
+            },
+
+            Response::PasswordChanged(result) => {
+                self.settings.submitting = false;
+                match result {
+                    Ok(()) => {
+                        self.settings.clear();
+                        self.status = "Password changed, other sessions signed out".into();
+                    }
+                    Err(e) => self.status = format!("Change password error: {e}"),
+                }
+            }
+
+            Response::AccountDeleted(result) => {
+                self.settings.submitting = false;
+                match result {
+                    Ok(()) => self.on_signed_out("Account deleted"),
+                    Err(e) => {
+                        self.settings.confirm_delete = false;
+                        self.status = format!("Delete account error: {e}");
+                    }
+                }
+            }
+
+            Response::LoggedOutEverywhere(result) => match result {
+                Ok(()) => self.on_signed_out("Signed out on all devices"),
+                Err(e) => self.status = format!("Logout error: {e}"),
+            },
+
+            Response::RecoveryCodes(result) => {
+                self.settings.submitting = false;
+                match result {
+                    Ok(codes) => self.show_recovery_codes(codes.codes),
+                    Err(e) => self.status = format!("Recovery codes error: {e}"),
+                }
+            }
         }
     }
 
+    fn on_signed_out(&mut self, message: &str) {
+        self.api.clear_cookie();
+        self.settings.clear();
+        self.profile = Profile::default();
+        self.strength = None;
+        self.auth = AuthState::new();
+        self.auth.status = message.into();
+        self.view = View::Auth;
+    }
+
     fn on_logged_in(&mut self, username: String) {
         self.view = View::Workout;
         self.auth.submitting = false;
@@ -537,12 +825,23 @@ impl App {
         self.api.send(Request::LoadExercises);
         self.request_activity();
         self.request_weight();
+        self.request_weight_trend();
+        self.api.send(Request::LoadProfile);
     }
 
     fn request_weight(&self) {
         self.api.send(Request::LoadWeight { day: self.day });
     }
 
+    fn request_weight_trend(&self) {
+        let end = Utc::now();
+        self.api.send(Request::LoadWeightTrend(WeightTrendQuery {
+            start: Some(end - Duration::days(WEIGHT_TREND_DAYS - 1)),
+            end: Some(end),
+            goal_kg: None,
+        }));
+    }
+
     fn request_activity(&self) {
         let end = Utc::now();
         let start = end - Duration::days(ACTIVITY_DAYS - 1);
@@ -552,13 +851,6 @@ impl App {
         }));
     }
 
-    fn request_graph(&mut self, id: i64) {
-        if !self.pending_graphs.contains(&id) && !self.graphs.iter().any(|g| g.exercise_id == id) {
-            self.pending_graphs.insert(id);
-            self.api.send(Request::LoadGraph(id));
-        }
-    }
-
     fn request_sets_for(&mut self, exercise_id: i64) {
         let key = (self.day, exercise_id);
         if !self.loading_sets.contains(&key) {
@@ -582,14 +874,32 @@ impl App {
     }
 
     fn request_all_graphs(&mut self) {
-        for id in self
+        let ids: Vec<i64> = self
             .exercises
             .iter()
             .filter_map(|e| e.id)
-            .collect::<Vec<_>>()
-        {
-            self.request_graph(id);
+            .filter(|id| {
+                !self.pending_graphs.contains(id)
+                    && !self.graphs.iter().any(|g| g.exercise_id == *id)
+            })
+            .collect();
+
+        if ids.is_empty() {
+            return;
         }
+        self.pending_graphs.extend(&ids);
+        self.api.send(Request::LoadGraphs {
+            exercise_ids: ids,
+            metric: self.graph_metric,
+        });
+    }
+
+    pub fn cycle_graph_metric(&mut self) {
+        self.graph_metric = self.graph_metric.next();
+        self.graphs.clear();
+        self.pending_graphs.clear();
+        self.status = format!("Graph metric: {}", self.graph_metric.label());
+        self.request_all_graphs();
     }
 
     fn apply_day(&mut self, day: NaiveDate) {
@@ -630,6 +940,10 @@ impl App {
         self.request_all_graphs();
         self.request_all_sets();
         self.request_weight();
+        if self.view == View::Body {
+            self.body.input.clear();
+            self.api.send(Request::LoadMeasurements { day });
+        }
     }
 
     // Auth actions
@@ -988,6 +1302,7 @@ impl App {
         self.view = View::Workout;
         self.manage.cancel_add();
         self.exercise_edit.cancel();
+        self.body.adding_kind = false;
     }
 
     pub fn switch_to_manage(&mut self) {
@@ -998,14 +1313,65 @@ impl App {
         self.api.send(Request::LoadExercises);
     }
 
+    pub fn switch_to_body(&mut self) {
+        self.view = View::Body;
+        self.manage.cancel_add();
+        self.exercise_edit.cancel();
+        self.body.adding_kind = false;
+        self.api.send(Request::LoadMeasurementKinds);
+        self.api.send(Request::LoadMeasurements { day: self.day });
+        self.api.send(Request::LoadStrength);
+    }
+
+    pub fn switch_to_settings(&mut self) {
+        self.view = View::Settings;
+        self.manage.cancel_add();
+        self.exercise_edit.cancel();
+        self.body.adding_kind = false;
+        self.settings.clear();
+    }
+
     pub fn switch_to_exercises(&mut self) {
         self.view = View::Exercises;
         self.manage.cancel_add();
         self.exercise_edit.mode = ExerciseEditMode::Browse;
         self.exercise_edit.update_filtered(&self.all_exercises);
+        self.request_history();
         self.api.send(Request::LoadExercises);
     }
 
+    /// Loads the first history page if the selected exercise changed.
+    fn request_history(&mut self) {
+        let id = self.exercise_edit.selected_exercise().map(|e| e.id);
+        if id == self.exercise_edit.history.exercise_id {
+            return;
+        }
+        self.exercise_edit.history.reset(id);
+        if let Some(exercise_id) = id {
+            self.exercise_edit.history.loading = true;
+            self.api.send(Request::LoadHistory {
+                exercise_id,
+                before: None,
+            });
+        }
+    }
+
+    pub fn history_scroll(&mut self, delta: i32) {
+        let history = &mut self.exercise_edit.history;
+        let max = history.sessions.len().saturating_sub(1) as i32;
+        history.scroll = (history.scroll as i32 + delta).clamp(0, max.max(0)) as usize;
+
+        if history.wants_more()
+            && let (Some(exercise_id), Some(before)) = (history.exercise_id, history.next_before)
+        {
+            history.loading = true;
+            self.api.send(Request::LoadHistory {
+                exercise_id,
+                before: Some(before),
+            });
+        }
+    }
+
     // Exercise edit methods
 
     pub fn exercise_select(&mut self, delta: i32) {
@@ -1018,6 +1384,7 @@ impl App {
         }
         let next = (self.exercise_edit.selected as i32 + delta).clamp(0, len - 1);
         self.exercise_edit.selected = next as usize;
+        self.request_history();
     }
 
     pub fn exercise_toggle_archived(&mut self) {
@@ -1026,6 +1393,7 @@ impl App {
         }
         self.exercise_edit.show_archived = !self.exercise_edit.show_archived;
         self.exercise_edit.update_filtered(&self.all_exercises);
+        self.request_history();
         self.status = if self.exercise_edit.show_archived {
             "Showing archived exercises".into()
         } else {
@@ -1321,6 +1689,314 @@ impl App {
     }
 }
 
+impl App {
+    // Body measurement methods
+
+    fn request_measurement_graph(&mut self) {
+        self.body.graph = None;
+        if let Some(kind) = self.body.selected_kind() {
+            self.api.send(Request::LoadMeasurementGraph {
+                kind: kind.kind.clone(),
+            });
+        }
+    }
+
+    pub fn body_select(&mut self, delta: i32) {
+        let len = self.body.kinds.len() as i32;
+        if len == 0 {
+            return;
+        }
+        let next = (self.body.selected as i32 + delta).clamp(0, len - 1) as usize;
+        if next != self.body.selected {
+            self.body.selected = next;
+            self.body.input.clear();
+            self.request_measurement_graph();
+        }
+    }
+
+    pub fn body_input_char(&mut self, ch: char) {
+        if ch.is_ascii_digit() || ch == '.' {
+            self.body.input.push(ch);
+        }
+    }
+
+    pub fn body_backspace(&mut self) {
+        self.body.input.pop();
+    }
+
+    pub fn body_save(&mut self) {
+        let Some(kind) = self.body.selected_kind().map(|k| k.kind.clone()) else {
+            return;
+        };
+        let Some(value) = self.body.input.parse::<f64>().ok().filter(|&v| v > 0.0) else {
+            self.status = "Enter a value first".into();
+            return;
+        };
+
+        self.status = format!("Saving {kind}...");
+        self.api.send(Request::SaveMeasurement {
+            kind,
+            day: self.day,
+            input: MeasurementInput {
+                value,
+                recorded_at: Some(Utc::now()),
+            },
+        });
+    }
+
+    pub fn body_delete(&mut self) {
+        let Some(kind) = self.body.selected_kind().map(|k| k.kind.clone()) else {
+            return;
+        };
+        if self.body.entry(&kind).is_some() {
+            self.status = format!("Deleting {kind}...");
+            self.api.send(Request::DeleteMeasurement {
+                kind,
+                day: self.day,
+            });
+        } else {
+            self.body.input.clear();
+        }
+    }
+
+    pub fn body_start_add_kind(&mut self) {
+        self.body.adding_kind = true;
+        self.body.new_kind.clear();
+    }
+
+    pub fn body_cancel_add_kind(&mut self) {
+        self.body.adding_kind = false;
+        self.body.new_kind.clear();
+    }
+
+    pub fn body_kind_input(&mut self, ch: char) {
+        let ch = ch.to_ascii_lowercase();
+        if ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '_' {
+            self.body.new_kind.push(ch);
+        } else if ch == ' ' || ch == '-' {
+            self.body.new_kind.push('_');
+        }
+    }
+
+    pub fn body_kind_backspace(&mut self) {
+        self.body.new_kind.pop();
+    }
+
+    pub fn body_confirm_add_kind(&mut self) {
+        let kind = std::mem::take(&mut self.body.new_kind);
+        if let Err(e) = ekman_core::v1::validate_measurement_kind(&kind) {
+            self.status = e;
+            return;
+        }
+
+        self.body.adding_kind = false;
+        let idx = match self.body.kinds.iter().position(|k| k.kind == kind) {
+            Some(idx) => idx,
+            None => {
+                self.body.kinds.push(MeasurementKind {
+                    unit: ekman_core::v1::measurement_unit(&kind).into(),
+                    kind,
+                });
+                self.body.kinds.len() - 1
+            }
+        };
+        self.body.selected = idx;
+        self.body.input.clear();
+        self.request_measurement_graph();
+    }
+
+    // Profile methods
+
+    /// Lift the exercise is designated for in the strength report.
+    pub fn designated_lift(&self, exercise_id: i64) -> Option<Lift> {
+        Lift::ALL
+            .into_iter()
+            .find(|&lift| self.profile.lift_exercise(lift) == Some(exercise_id))
+    }
+
+    /// Cycle the selected exercise through squat, bench, deadlift and none.
+    pub fn exercise_cycle_lift(&mut self) {
+        if self.exercise_edit.mode != ExerciseEditMode::Browse {
+            return;
+        }
+        let Some(id) = self.exercise_edit.selected_exercise().map(|ex| ex.id) else {
+            return;
+        };
+
+        let next = match self.designated_lift(id) {
+            None => Some(Lift::Squat),
+            Some(Lift::Squat) => Some(Lift::Bench),
+            Some(Lift::Bench) => Some(Lift::Deadlift),
+            Some(Lift::Deadlift) => None,
+        };
+
+        let mut profile = self.profile.clone();
+        for lift in Lift::ALL {
+            if profile.lift_exercise(lift) == Some(id) {
+                profile.set_lift_exercise(lift, None);
+            }
+        }
+        if let Some(lift) = next {
+            profile.set_lift_exercise(lift, Some(id));
+        }
+
+        self.status = "Saving profile...".into();
+        self.api.send(Request::SaveProfile(profile));
+    }
+
+    pub fn cycle_sex(&mut self) {
+        let mut profile = self.profile.clone();
+        profile.sex = match profile.sex {
+            None => Some(Sex::Male),
+            Some(Sex::Male) => Some(Sex::Female),
+            Some(Sex::Female) => None,
+        };
+
+        self.status = "Saving profile...".into();
+        self.api.send(Request::SaveProfile(profile));
+    }
+}
+
+impl App {
+    // Settings methods
+
+    pub fn settings_change_password(&mut self) {
+        if self.settings.submitting {
+            return;
+        }
+        let settings = &self.settings;
+        if settings.password.is_empty() || settings.new_password.is_empty() {
+            self.status = "Current and new password required".into();
+            return;
+        }
+        if settings.totp_code.is_empty() {
+            self.status = "TOTP code required".into();
+            return;
+        }
+
+        self.settings.submitting = true;
+        self.status = "Changing password...".into();
+        self.api.send(Request::ChangePassword(ChangePasswordInput {
+            old_password: self.settings.password.clone(),
+            new_password: self.settings.new_password.clone(),
+            totp: self.settings.totp_code.clone(),
+        }));
+    }
+
+    pub fn settings_regenerate_recovery_codes(&mut self) {
+        if self.settings.submitting {
+            return;
+        }
+        if self.settings.password.is_empty() || self.settings.totp_code.is_empty() {
+            self.status = "Current password and TOTP code required".into();
+            return;
+        }
+
+        self.settings.submitting = true;
+        self.status = "Generating recovery codes...".into();
+        self.api
+            .send(Request::RegenerateRecoveryCodes(RegenerateRecoveryCodes {
+                password: self.settings.password.clone(),
+                totp: self.settings.totp_code.clone(),
+            }));
+    }
+
+    fn show_recovery_codes(&mut self, codes: Vec<String>) {
+        self.switch_to_settings();
+        self.settings.recovery_codes = codes;
+        self.status = "Write these recovery codes down, they won't be shown again".into();
+    }
+
+    pub fn settings_logout_everywhere(&mut self) {
+        self.status = "Signing out everywhere...".into();
+        self.api.send(Request::LogoutEverywhere);
+    }
+
+    /// First call arms the deletion, the second one sends it.
+    pub fn settings_delete_account(&mut self) {
+        if self.settings.submitting {
+            return;
+        }
+        if self.settings.password.is_empty() || self.settings.totp_code.is_empty() {
+            self.status = "Current password and TOTP code required".into();
+            return;
+        }
+        if !self.settings.confirm_delete {
+            self.settings.confirm_delete = true;
+            self.status = "Press Ctrl+X again to permanently delete your account".into();
+            return;
+        }
+
+        self.settings.submitting = true;
+        self.status = "Deleting account...".into();
+        self.api.send(Request::DeleteAccount(DeleteAccountInput {
+            password: self.settings.password.clone(),
+            totp: self.settings.totp_code.clone(),
+        }));
+    }
+}
+
+impl SettingsState {
+    pub fn new() -> Self {
+        Self {
+            field: SettingsField::Password,
+            password: String::new(),
+            new_password: String::new(),
+            totp_code: String::new(),
+            confirm_delete: false,
+            submitting: false,
+            recovery_codes: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            SettingsField::Password => SettingsField::NewPassword,
+            SettingsField::NewPassword => SettingsField::Totp,
+            SettingsField::Totp => SettingsField::Password,
+        };
+    }
+
+    pub fn prev_field(&mut self) {
+        self.field = match self.field {
+            SettingsField::Password => SettingsField::Totp,
+            SettingsField::NewPassword => SettingsField::Password,
+            SettingsField::Totp => SettingsField::NewPassword,
+        };
+    }
+
+    pub fn push_char(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.confirm_delete = false;
+        match self.field {
+            SettingsField::Password => self.password.push(ch),
+            SettingsField::NewPassword => self.new_password.push(ch),
+            SettingsField::Totp => self.totp_code.push(ch),
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.confirm_delete = false;
+        match self.field {
+            SettingsField::Password => {
+                self.password.pop();
+            }
+            SettingsField::NewPassword => {
+                self.new_password.pop();
+            }
+            SettingsField::Totp => {
+                self.totp_code.pop();
+            }
+        }
+    }
+}
+
 impl AuthState {
     pub fn new() -> Self {
         let secret = generate_totp_secret();
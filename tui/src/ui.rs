@@ -1,8 +1,13 @@
 //! UI rendering.
 
-use crate::state::{App, AuthField, ExerciseEditMode, ExerciseState, Focus, ManageMode, View};
+use crate::state::{
+    App, AuthField, ExerciseEditMode, ExerciseState, Focus, HistoryState, ManageMode,
+    SettingsField, View,
+};
 use chrono::Utc;
-use ekman_core::{ActivityDay, Graph};
+use ekman_core::v1::{
+    ActivityDay, GraphSeries, Lift, MeasurementGraph, SeriesKind, Sex, StrengthReport, WeightTrend,
+};
 use qrcode::QrCode;
 use ratatui::{
     Frame,
@@ -17,12 +22,14 @@ use ratatui::{
 use std::fmt::Write;
 use tui_qrcode::{Colors, QrCodeWidget};
 
-const WORKOUT_HINTS: &str = "←/→: set • Tab: nav • ↑/↓: field • W/F: ±2.5kg (±0.1 for weight) • N/E: row • A/S: day • R: today • D: del • F2: plans • q: quit";
-const MANAGE_HINTS: &str =
-    "N/E: day • ↑/↓: exercise • A: add • D: remove • F1: workout • F3: exercises • q: quit";
+const WORKOUT_HINTS: &str = "←/→: set • Tab: nav • ↑/↓: field • W/F: ±2.5kg (±0.1 for weight) • N/E: row • A/S: day • R: today • D: del • M: graph metric • F2: plans • F4: body • F5: settings • q: quit";
+const MANAGE_HINTS: &str = "N/E: day • ↑/↓: exercise • A: add • D: remove • F1: workout • F3: exercises • F4: body • F5: settings • q: quit";
 const MANAGE_ADD_HINTS: &str = "Type to search • ↑/↓: select • Enter: confirm • Esc: cancel";
-const EXERCISES_HINTS: &str = "↑/↓: select • A: add • R: rename • X: archive • H: show archived • F1: workout • F2: plans • q: quit";
+const EXERCISES_HINTS: &str = "↑/↓: select • PgUp/PgDn: history • A: add • R: rename • X: archive • H: show archived • L: lift • F1: workout • F2: plans • F4: body • F5: settings • q: quit";
 const EXERCISES_EDIT_HINTS: &str = "Type name • Enter: confirm • Esc: cancel";
+const BODY_HINTS: &str = "↑/↓: kind • type value • Enter: save • D: delete • K: custom kind • X: sex • A/S: day • R: today • F1: workout • F5: settings • q: quit";
+const SETTINGS_HINTS: &str = "Tab/↑/↓: field • Enter: change password • Ctrl+R: new recovery codes • Ctrl+L: log out everywhere • Ctrl+X: delete account • F1: workout • Esc: quit";
+const BODY_ADD_HINTS: &str = "Type kind name (a-z, 0-9, _) • Enter: confirm • Esc: cancel";
 
 const WEEKDAYS: [&str; 7] = [
     "Monday",
@@ -40,6 +47,8 @@ pub fn render(app: &App, frame: &mut Frame) {
         View::Workout => render_workout(app, frame),
         View::Manage => render_manage(app, frame),
         View::Exercises => render_exercises_view(app, frame),
+        View::Body => render_body_view(app, frame),
+        View::Settings => render_settings_view(app, frame),
     }
 }
 
@@ -68,7 +77,11 @@ fn render_workout(app: &App, frame: &mut Frame) {
     render_day(frame, day_area, app);
     render_activity(frame, activity_area, &app.activity, &app.day.to_string());
     render_weight(frame, weight_area, app);
-    render_graphs(frame, graph_area, &app.graphs);
+    if app.weight_selected {
+        render_weight_chart(frame, graph_area, app.weight.trend.as_ref());
+    } else {
+        render_graphs(frame, graph_area, &app.graphs);
+    }
     render_exercises(frame, exercise_area, &app.exercises, app.selected);
     render_status(frame, status, &app.status, WORKOUT_HINTS);
 }
@@ -172,12 +185,24 @@ fn render_weight(frame: &mut Frame, area: Rect, app: &App) {
         format!(" ({:.1})", app.weight.default_weight)
     };
 
+    let trend_text = match app.weight.trend.as_ref() {
+        Some(WeightTrend {
+            trend_kg: Some(trend),
+            weekly_rate_kg,
+            ..
+        }) => match weekly_rate_kg {
+            Some(rate) => format!("Trend {trend:.1} kg • {rate:+.2} kg/wk"),
+            None => format!("Trend {trend:.1} kg"),
+        },
+        _ => String::new(),
+    };
+
     let lines = vec![
         Line::from(vec![
             Span::styled(weight_display, value_style),
             Span::styled(status_text, Style::default().dim()),
         ]),
-        Line::from(""),
+        Line::from(Span::styled(trend_text, Style::default().dim())),
         Line::from(if is_selected {
             "W/F: ±0.1kg • D: delete • Enter: save"
         } else {
@@ -192,8 +217,92 @@ fn render_weight(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(Paragraph::new(lines).block(block), area);
 }
 
-fn render_graphs(frame: &mut Frame, area: Rect, graphs: &[Graph]) {
-    if graphs.is_empty() {
+fn render_weight_chart(frame: &mut Frame, area: Rect, trend: Option<&WeightTrend>) {
+    let title = match trend.and_then(|t| t.weekly_rate_kg) {
+        Some(rate) => format!("Body weight • {rate:+.2} kg/week"),
+        None => "Body weight".to_string(),
+    };
+
+    let Some(trend) = trend.filter(|t| !t.points.is_empty()) else {
+        frame.render_widget(
+            Paragraph::new("No weight data").block(Block::bordered().title(title)),
+            area,
+        );
+        return;
+    };
+
+    // Calendar days on x so gaps between weigh-ins stay visible
+    let first_day = trend.points[0].day;
+    let x = |day: chrono::NaiveDate| (day - first_day).num_days() as f64;
+    let raw: Vec<(f64, f64)> = trend
+        .points
+        .iter()
+        .map(|p| (x(p.day), p.weight_kg))
+        .collect();
+    let smoothed: Vec<(f64, f64)> = trend
+        .points
+        .iter()
+        .map(|p| (x(p.day), p.trend_kg))
+        .collect();
+
+    let (min_y, max_y) = raw
+        .iter()
+        .chain(&smoothed)
+        .map(|(_, v)| *v)
+        .fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (v.min(min), v.max(max))
+        });
+    let pad = ((max_y - min_y) * 0.1).max(0.5);
+    let (min_y, max_y) = (min_y - pad, max_y + pad);
+
+    let last_day = trend.points[trend.points.len() - 1].day;
+    let x_max = x(last_day).max(1.0);
+
+    let datasets = vec![
+        Dataset::default()
+            .name("Weigh-ins")
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().dim())
+            .data(&raw),
+        Dataset::default()
+            .name("Trend")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().green())
+            .data(&smoothed),
+    ];
+
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title(title))
+        .x_axis(Axis::default().title("Date").bounds([0.0, x_max]).labels([
+            first_day.format("%Y-%m-%d").to_string(),
+            last_day.format("%Y-%m-%d").to_string(),
+        ]))
+        .y_axis(
+            Axis::default()
+                .title("Weight (kg)")
+                .bounds([min_y, max_y])
+                .labels([format!("{min_y:.1}"), format!("{max_y:.1}")]),
+        );
+
+    frame.render_widget(chart, area);
+}
+
+fn render_graphs(frame: &mut Frame, area: Rect, graphs: &[GraphSeries]) {
+    // Group series per exercise, keeping arrival order
+    let mut groups: Vec<Vec<&GraphSeries>> = Vec::new();
+    for series in graphs {
+        match groups
+            .iter_mut()
+            .find(|g| g[0].exercise_id == series.exercise_id)
+        {
+            Some(group) => group.push(series),
+            None => groups.push(vec![series]),
+        }
+    }
+
+    if groups.is_empty() {
         frame.render_widget(
             Paragraph::new("No graph data").block(Block::bordered().title("Progress")),
             area,
@@ -201,32 +310,55 @@ fn render_graphs(frame: &mut Frame, area: Rect, graphs: &[Graph]) {
         return;
     }
 
-    let constraints = vec![Constraint::Ratio(1, graphs.len() as u32); graphs.len()];
+    let constraints = vec![Constraint::Ratio(1, groups.len() as u32); groups.len()];
     let rows = Layout::vertical(constraints).split(area);
 
-    for (graph, chunk) in graphs.iter().zip(rows.iter()) {
-        render_graph(frame, *chunk, graph);
+    for (group, chunk) in groups.iter().zip(rows.iter()) {
+        render_graph(frame, *chunk, group);
     }
 }
 
-fn render_graph(frame: &mut Frame, area: Rect, graph: &Graph) {
-    let data: Vec<(f64, f64)> = graph
-        .points
-        .iter()
-        .enumerate()
-        .map(|(i, p)| (i as f64, p.value))
-        .collect();
+fn series_style(kind: SeriesKind) -> (&'static str, Style) {
+    match kind {
+        SeriesKind::Raw => ("Sessions", Style::default().cyan()),
+        SeriesKind::MovingAverage => ("Moving avg", Style::default().yellow()),
+        SeriesKind::Trend => ("Trend", Style::default().magenta()),
+    }
+}
+
+fn render_graph(frame: &mut Frame, area: Rect, series: &[&GraphSeries]) {
+    let first = series[0];
+    let title = format!("Progress • {} (m: metric)", first.exercise_name);
 
-    if data.is_empty() {
+    // The raw series defines the x positions; overlays share its dates
+    let Some(base) = series
+        .iter()
+        .find(|s| s.kind == SeriesKind::Raw)
+        .filter(|s| !s.points.is_empty())
+    else {
         frame.render_widget(
-            Paragraph::new("No data").block(Block::bordered().title(graph.exercise_name.as_str())),
+            Paragraph::new("No data").block(Block::bordered().title(title)),
             area,
         );
         return;
-    }
+    };
+
+    let data: Vec<Vec<(f64, f64)>> = series
+        .iter()
+        .map(|s| {
+            s.points
+                .iter()
+                .filter_map(|p| {
+                    let x = base.points.iter().position(|b| b.date == p.date)?;
+                    Some((x as f64, p.value))
+                })
+                .collect()
+        })
+        .collect();
 
     let (min_y, max_y) = data
         .iter()
+        .flatten()
         .map(|(_, v)| *v)
         .fold((f64::MAX, f64::MIN), |(min, max), v| {
             (v.min(min), v.max(max))
@@ -239,50 +371,41 @@ fn render_graph(frame: &mut Frame, area: Rect, graph: &Graph) {
         (min_y - pad, max_y + pad)
     };
 
-    let x_max = (data.len().saturating_sub(1) as f64).max(1.0);
+    let x_max = (base.points.len().saturating_sub(1) as f64).max(1.0);
 
-    let x_labels: Vec<String> = match graph.points.len() {
+    let x_labels: Vec<String> = match base.points.len() {
         0 | 1 => vec!["".into(); 3],
-        len => {
-            let mid = len / 2;
-            vec![
-                graph
-                    .points
-                    .first()
-                    .map(|p| p.date.clone())
-                    .unwrap_or_default(),
-                graph
-                    .points
-                    .get(mid)
-                    .map(|p| p.date.clone())
-                    .unwrap_or_default(),
-                graph
-                    .points
-                    .last()
-                    .map(|p| p.date.clone())
-                    .unwrap_or_default(),
-            ]
-        }
+        len => [0, len / 2, len - 1]
+            .iter()
+            .map(|&i| base.points[i].date.clone())
+            .collect(),
     };
 
-    let dataset = Dataset::default()
-        .name(graph.exercise_name.as_str())
-        .marker(symbols::Marker::Braille)
-        .graph_type(GraphType::Line)
-        .style(Style::default().cyan())
-        .data(&data);
+    let datasets = series
+        .iter()
+        .zip(&data)
+        .map(|(s, points)| {
+            let (name, style) = series_style(s.kind);
+            Dataset::default()
+                .name(name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(style)
+                .data(points)
+        })
+        .collect();
 
-    let chart = Chart::new(vec![dataset])
-        .block(Block::bordered().title(format!("Progress • {}", graph.exercise_name)))
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title(title))
         .x_axis(
             Axis::default()
-                .title("Sessions")
+                .title("Date")
                 .bounds([0.0, x_max])
                 .labels(x_labels),
         )
         .y_axis(
             Axis::default()
-                .title("Weight")
+                .title(first.metric.label())
                 .bounds([min_y, max_y])
                 .labels([format!("{min_y:.0}"), format!("{max_y:.0}")]),
         );
@@ -574,7 +697,12 @@ fn render_exercise_list(frame: &mut Frame, area: Rect, app: &App) {
 
 fn render_exercise_detail(frame: &mut Frame, area: Rect, app: &App) {
     match app.exercise_edit.mode {
-        ExerciseEditMode::Browse => render_exercise_info(frame, area, app),
+        ExerciseEditMode::Browse => {
+            let [info_area, history_area] =
+                Layout::vertical([Constraint::Length(9), Constraint::Min(0)]).areas(area);
+            render_exercise_info(frame, info_area, app);
+            render_exercise_history(frame, history_area, &app.exercise_edit.history);
+        }
         ExerciseEditMode::Add => {
             render_exercise_input(frame, area, "Add New Exercise", &app.exercise_edit.input)
         }
@@ -588,8 +716,8 @@ fn render_exercise_info(frame: &mut Frame, area: Rect, app: &App) {
     let content = if let Some(ex) = app.exercise_edit.selected_exercise() {
         let status = if ex.archived { "Archived" } else { "Active" };
         let owner = match &ex.owner {
-            ekman_core::Owner::User => "You",
-            ekman_core::Owner::Admin => "System",
+            ekman_core::v1::Owner::User => "You",
+            ekman_core::v1::Owner::Admin => "System",
         };
 
         vec![
@@ -610,7 +738,10 @@ fn render_exercise_info(frame: &mut Frame, area: Rect, app: &App) {
                 ),
             ]),
             Line::from(vec![Span::raw("Owner: "), Span::raw(owner)]),
-            Line::from(""),
+            Line::from(vec![
+                Span::raw("Lift: "),
+                Span::raw(app.designated_lift(ex.id).map_or("--", lift_label)),
+            ]),
             Line::from(""),
             Line::from(vec![
                 Span::styled("R", Style::default().cyan().bold()),
@@ -639,6 +770,57 @@ fn render_exercise_info(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(paragraph, area);
 }
 
+fn render_exercise_history(frame: &mut Frame, area: Rect, history: &HistoryState) {
+    let block = Block::bordered().title(format!("History ({} sessions)", history.sessions.len()));
+
+    if history.sessions.is_empty() {
+        let text = if history.loading {
+            "Loading..."
+        } else {
+            "No sessions logged"
+        };
+        frame.render_widget(
+            Paragraph::new(Span::styled(text, Style::default().dim())).block(block),
+            area,
+        );
+        return;
+    }
+
+    let mut lines: Vec<Line> = history
+        .sessions
+        .iter()
+        .skip(history.scroll)
+        .map(|session| {
+            let sets = session
+                .sets
+                .iter()
+                .map(|s| format!("{:.1}×{}", s.weight, s.reps))
+                .collect::<Vec<_>>()
+                .join(" ");
+            Line::from(vec![
+                Span::styled(
+                    session.date.format("%Y-%m-%d").to_string(),
+                    Style::default().bold(),
+                ),
+                Span::raw(format!("  {sets}  ")),
+                Span::styled(
+                    format!("e1RM {:.1} • vol {:.0}", session.est_1rm, session.volume),
+                    Style::default().dim(),
+                ),
+            ])
+        })
+        .collect();
+
+    if history.loading {
+        lines.push(Line::from(Span::styled(
+            "Loading...",
+            Style::default().dim(),
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
 fn render_exercise_input(frame: &mut Frame, area: Rect, title: &str, input: &str) {
     let display_text = if input.is_empty() {
         "Type exercise name...".to_string()
@@ -670,6 +852,285 @@ fn render_exercise_input(frame: &mut Frame, area: Rect, title: &str, input: &str
     frame.render_widget(paragraph, area);
 }
 
+// ============================================================================
+// Body View
+// ============================================================================
+
+fn render_body_view(app: &App, frame: &mut Frame) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(4)]).areas(frame.area());
+
+    let [list_area, graph_area] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(main);
+
+    let hints = if app.body.adding_kind {
+        BODY_ADD_HINTS
+    } else {
+        BODY_HINTS
+    };
+
+    let [graph_area, stats_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(10)]).areas(graph_area);
+
+    render_measurement_list(frame, list_area, app);
+    render_measurement_graph(frame, graph_area, app.body.graph.as_ref());
+    render_strength_stats(frame, stats_area, app.strength.as_ref());
+    render_status(frame, status, &app.status, hints);
+}
+
+fn render_measurement_list(frame: &mut Frame, area: Rect, app: &App) {
+    let body = &app.body;
+
+    let mut items: Vec<ListItem> = body
+        .kinds
+        .iter()
+        .enumerate()
+        .map(|(i, kind)| {
+            let selected = i == body.selected && !body.adding_kind;
+            let value = if selected && !body.input.is_empty() {
+                format!("{}▌ {}", body.input, kind.unit)
+            } else {
+                match body.entry(&kind.kind) {
+                    Some(entry) => format!("{:.1} {}", entry.value, kind.unit),
+                    None => format!("-- {}", kind.unit),
+                }
+            };
+
+            let style = if selected {
+                Style::default().yellow().bold()
+            } else {
+                Style::default()
+            };
+            ListItem::new(format!("{}: {value}", kind.kind)).style(style)
+        })
+        .collect();
+
+    if body.adding_kind {
+        items.push(
+            ListItem::new(format!("New kind: {}▌", body.new_kind))
+                .style(Style::default().green().bold()),
+        );
+    }
+
+    let title = format!("Measurements • {}", app.day.format("%a %Y-%m-%d"));
+    frame.render_widget(List::new(items).block(Block::bordered().title(title)), area);
+}
+
+fn render_measurement_graph(frame: &mut Frame, area: Rect, graph: Option<&MeasurementGraph>) {
+    let Some(graph) = graph.filter(|g| !g.points.is_empty()) else {
+        frame.render_widget(
+            Paragraph::new("No measurement data").block(Block::bordered().title("History")),
+            area,
+        );
+        return;
+    };
+
+    let data: Vec<(f64, f64)> = graph
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i as f64, p.value))
+        .collect();
+
+    let (min_y, max_y) = data
+        .iter()
+        .map(|(_, v)| *v)
+        .fold((f64::MAX, f64::MIN), |(min, max), v| {
+            (v.min(min), v.max(max))
+        });
+    let pad = ((max_y - min_y) * 0.1).max(0.5);
+    let (min_y, max_y) = (min_y - pad, max_y + pad);
+
+    let x_max = (data.len().saturating_sub(1) as f64).max(1.0);
+    let first = graph.points.first().map(|p| p.date.clone());
+    let last = graph.points.last().map(|p| p.date.clone());
+
+    let dataset = Dataset::default()
+        .name(graph.kind.as_str())
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().cyan())
+        .data(&data);
+
+    let chart = Chart::new(vec![dataset])
+        .block(Block::bordered().title(format!("History • {}", graph.kind)))
+        .x_axis(
+            Axis::default()
+                .title("Date")
+                .bounds([0.0, x_max])
+                .labels([first.unwrap_or_default(), last.unwrap_or_default()]),
+        )
+        .y_axis(
+            Axis::default()
+                .title(graph.unit.as_str())
+                .bounds([min_y, max_y])
+                .labels([format!("{min_y:.1}"), format!("{max_y:.1}")]),
+        );
+
+    frame.render_widget(chart, area);
+}
+
+fn lift_label(lift: Lift) -> &'static str {
+    match lift {
+        Lift::Squat => "Squat",
+        Lift::Bench => "Bench",
+        Lift::Deadlift => "Deadlift",
+    }
+}
+
+fn render_strength_stats(frame: &mut Frame, area: Rect, report: Option<&StrengthReport>) {
+    let block = Block::bordered().title("Strength");
+    let Some(report) = report else {
+        frame.render_widget(Paragraph::new("Loading...").block(block), area);
+        return;
+    };
+
+    let fmt = |v: Option<f64>, decimals: usize| {
+        v.map_or_else(|| "--".to_string(), |v| format!("{v:.decimals$}"))
+    };
+
+    let sex = match report.sex {
+        Some(Sex::Male) => "M",
+        Some(Sex::Female) => "F",
+        None => "--",
+    };
+    let age = report
+        .age
+        .map_or_else(|| "--".to_string(), |a| a.to_string());
+
+    let mut lines = vec![Line::from(format!(
+        "Sex: {sex} • Age: {age} • BW: {} kg",
+        fmt(report.body_weight_kg, 1)
+    ))];
+
+    for lift in Lift::ALL {
+        let line = match report.lifts.iter().find(|l| l.lift == lift) {
+            Some(best) => Line::from(vec![
+                Span::styled(format!("{:<9}", lift_label(lift)), Style::default().cyan()),
+                Span::raw(format!(
+                    "{:.1} kg e1RM • {}x BW • {}",
+                    best.est_1rm,
+                    fmt(best.bodyweight_multiple, 2),
+                    best.exercise_name
+                )),
+            ]),
+            None => Line::from(vec![
+                Span::styled(format!("{:<9}", lift_label(lift)), Style::default().cyan()),
+                Span::styled("not set (L in exercises)", Style::default().dim()),
+            ]),
+        };
+        lines.push(line);
+    }
+
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::raw("Total: "),
+        Span::styled(
+            format!("{} kg", fmt(report.total_kg, 1)),
+            Style::default().bold(),
+        ),
+    ]));
+    lines.push(Line::from(format!(
+        "Wilks {} • DOTS {} • IPF GL {}",
+        fmt(report.wilks, 1),
+        fmt(report.dots, 1),
+        fmt(report.ipf_gl, 1)
+    )));
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+// ============================================================================
+// Settings View
+// ============================================================================
+
+fn render_settings_view(app: &App, frame: &mut Frame) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(4)]).areas(frame.area());
+
+    let [form_area, help_area] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(main);
+
+    let settings = &app.settings;
+    let lines = vec![
+        field_line(
+            "Current password",
+            &settings.password,
+            settings.field == SettingsField::Password,
+            true,
+        ),
+        field_line(
+            "New password",
+            &settings.new_password,
+            settings.field == SettingsField::NewPassword,
+            true,
+        ),
+        field_line(
+            "TOTP code",
+            &settings.totp_code,
+            settings.field == SettingsField::Totp,
+            false,
+        ),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Account")),
+        form_area,
+    );
+
+    if !settings.recovery_codes.is_empty() {
+        render_recovery_codes(frame, help_area, &settings.recovery_codes);
+        render_status(frame, status, &app.status, SETTINGS_HINTS);
+        return;
+    }
+
+    let mut help = vec![
+        Line::from("Change password: fill all three fields, Enter."),
+        Line::from("Other sessions are signed out."),
+        Line::from(""),
+        Line::from("Ctrl+R with password and TOTP issues new"),
+        Line::from("recovery codes; the old ones stop working."),
+        Line::from(""),
+        Line::from("Ctrl+L signs out every device, this one too."),
+        Line::from(""),
+        Line::from("Delete account: current password and TOTP,"),
+        Line::from("then Ctrl+X twice. All data is removed."),
+    ];
+    if settings.confirm_delete {
+        help.push(Line::from(""));
+        help.push(Line::from(Span::styled(
+            "Ctrl+X again deletes everything • Esc cancels",
+            Style::default().red().bold(),
+        )));
+    }
+    frame.render_widget(
+        Paragraph::new(help).block(Block::bordered().title("Help")),
+        help_area,
+    );
+
+    render_status(frame, status, &app.status, SETTINGS_HINTS);
+}
+
+fn render_recovery_codes(frame: &mut Frame, area: Rect, codes: &[String]) {
+    let mut lines = vec![
+        Line::from(Span::styled(
+            "Shown once. Each code works for one login",
+            Style::default().yellow().bold(),
+        )),
+        Line::from("in place of a TOTP code."),
+        Line::from(""),
+    ];
+    lines.extend(
+        codes
+            .iter()
+            .map(|code| Line::from(Span::styled(format!("  {code}"), Style::default().bold()))),
+    );
+
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Recovery codes")),
+        area,
+    );
+}
+
 // ============================================================================
 // Auth
 // ============================================================================
@@ -765,7 +1226,11 @@ fn render_auth_form(frame: &mut Frame, area: Rect, app: &App) {
             true,
         ),
         field_line(
-            "TOTP code",
+            if auth.register_mode {
+                "TOTP code"
+            } else {
+                "TOTP or recovery code"
+            },
             &auth.totp_code,
             auth.field == AuthField::Totp,
             false,
@@ -817,6 +1282,7 @@ fn render_auth_qr(frame: &mut Frame, area: Rect, app: &App) {
 fn render_auth_help(frame: &mut Frame, area: Rect) {
     let help = Paragraph::new(vec![
         Line::from("Enter username, password, and TOTP code."),
+        Line::from("Lost your phone? Use a recovery code instead."),
         Line::from("Ctrl+R to register instead."),
         Line::from("Tab to move fields, Enter to submit."),
     ])
@@ -2,7 +2,8 @@
 // Ekman - Workout Tracker Web App
 // ============================================================================
 
-const API_BASE = "http://localhost:3000"; //window.location.origin;
+// Served by ekman-server, so the API is on the same origin
+const API_BASE = window.location.origin;
 
 // ============================================================================
 // State
@@ -36,8 +37,12 @@ async function api(method, path, body = null) {
     }
     const res = await fetch(`${API_BASE}${path}`, options);
     if (!res.ok) {
-        const text = await res.text();
-        throw new Error(text || res.statusText);
+        // ErrorBody: { code, error, details?, request_id? }
+        const body = await res.json().catch(() => null);
+        const err = new Error(body?.error || res.statusText);
+        err.code = body?.code;
+        err.details = body?.details || [];
+        throw err;
     }
     if (res.status === 204) return null;
     return res.json();
@@ -82,7 +87,7 @@ function generateQRCode(secret, username) {
 
 async function checkSession() {
     try {
-        const user = await api('GET', '/api/auth/me');
+        const user = await api('GET', '/api/v1/auth/me');
         state.user = user;
         showMainView();
         loadAllData();
@@ -92,14 +97,14 @@ async function checkSession() {
 }
 
 async function login(username, password, totp) {
-    const session = await api('POST', '/api/auth/login', { username, password, totp });
+    const session = await api('POST', '/api/v1/auth/login', { username, password, totp });
     state.user = { username: session.username };
     showMainView();
     loadAllData();
 }
 
 async function register(username, password, totpSecret, totpCode) {
-    const session = await api('POST', '/api/auth/register', {
+    const session = await api('POST', '/api/v1/auth/register', {
         username,
         password,
         totp_secret: totpSecret,
@@ -110,6 +115,78 @@ async function register(username, password, totpSecret, totpCode) {
     loadAllData();
 }
 
+// ============================================================================
+// Passkeys
+// ============================================================================
+
+function passkeysSupported() {
+    return !!window.PublicKeyCredential;
+}
+
+function toBase64Url(buffer) {
+    let binary = '';
+    for (const b of new Uint8Array(buffer)) {
+        binary += String.fromCharCode(b);
+    }
+    return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+function fromBase64Url(str) {
+    const base64 = str.replace(/-/g, '+').replace(/_/g, '/');
+    const binary = atob(base64 + '='.repeat((4 - base64.length % 4) % 4));
+    return Uint8Array.from(binary, c => c.charCodeAt(0));
+}
+
+async function loginWithPasskey() {
+    const options = await api('POST', '/api/v1/auth/passkeys/login/start');
+    const credential = await navigator.credentials.get({
+        publicKey: {
+            challenge: fromBase64Url(options.challenge),
+            rpId: options.rp_id,
+            userVerification: 'required',
+        },
+    });
+    const { response } = credential;
+    const session = await api('POST', '/api/v1/auth/passkeys/login/finish', {
+        credential_id: toBase64Url(credential.rawId),
+        client_data_json: toBase64Url(response.clientDataJSON),
+        authenticator_data: toBase64Url(response.authenticatorData),
+        signature: toBase64Url(response.signature),
+        user_handle: response.userHandle ? toBase64Url(response.userHandle) : null,
+    });
+    state.user = { username: session.username };
+    showMainView();
+    loadAllData();
+}
+
+async function addPasskey(name, password, totp) {
+    const options = await api('POST', '/api/v1/auth/passkeys/register/start', { password, totp });
+    const credential = await navigator.credentials.create({
+        publicKey: {
+            challenge: fromBase64Url(options.challenge),
+            rp: { id: options.rp_id, name: options.rp_name },
+            user: {
+                id: fromBase64Url(options.user_id),
+                name: options.user_name,
+                displayName: options.user_name,
+            },
+            pubKeyCredParams: [{ type: 'public-key', alg: -7 }],
+            excludeCredentials: options.exclude_credentials.map(id => ({
+                type: 'public-key',
+                id: fromBase64Url(id),
+            })),
+            authenticatorSelection: { residentKey: 'required', userVerification: 'required' },
+            attestation: 'none',
+        },
+    });
+    await api('POST', '/api/v1/auth/passkeys/register/finish', {
+        name,
+        credential_id: toBase64Url(credential.rawId),
+        client_data_json: toBase64Url(credential.response.clientDataJSON),
+        attestation_object: toBase64Url(credential.response.attestationObject),
+    });
+}
+
 // ============================================================================
 // Data Loading
 // ============================================================================
@@ -125,7 +202,7 @@ async function loadAllData() {
 
 async function loadPlans() {
     try {
-        state.plans = await api('GET', '/api/plans/daily');
+        state.plans = await api('GET', '/api/v1/plans/daily');
         renderWeekdayList();
     } catch (e) {
         showStatus('Failed to load plans', 'error');
@@ -134,7 +211,7 @@ async function loadPlans() {
 
 async function loadExercises() {
     try {
-        state.exercises = await api('GET', '/api/exercises');
+        state.exercises = await api('GET', '/api/v1/exercises');
         renderAllExercises();
     } catch (e) {
         showStatus('Failed to load exercises', 'error');
@@ -146,7 +223,7 @@ async function loadActivity() {
         const end = new Date();
         const start = new Date();
         start.setDate(start.getDate() - 20);
-        const activity = await api('GET', `/api/activity/days?start=${start.toISOString()}&end=${end.toISOString()}`);
+        const activity = await api('GET', `/api/v1/activity/days?start=${start.toISOString()}&end=${end.toISOString()}`);
         state.activity = activity.days || [];
         renderActivityBar();
     } catch (e) {
@@ -168,7 +245,7 @@ async function loadDaySets() {
     
     for (const ex of plan.exercises) {
         try {
-            const data = await api('GET', `/api/days/${dateStr}/exercises/${ex.exercise_id}/sets`);
+            const data = await api('GET', `/api/v1/days/${dateStr}/exercises/${ex.exercise_id}/sets`);
             state.todaySets[ex.exercise_id] = data.sets || [];
         } catch (e) {
             state.todaySets[ex.exercise_id] = [];
@@ -188,17 +265,17 @@ function getPlanForDate(date) {
 }
 
 async function createPlan(name, dayOfWeek) {
-    return api('POST', '/api/plans', { name, day_of_week: dayOfWeek });
+    return api('POST', '/api/v1/plans', { name, day_of_week: dayOfWeek });
 }
 
 async function addExerciseToPlan(templateId, exerciseId) {
-    await api('POST', `/api/plans/${templateId}/exercises`, { exercise_id: exerciseId });
+    await api('POST', `/api/v1/plans/${templateId}/exercises`, { exercise_id: exerciseId });
     await loadPlans();
     showStatus('Exercise added to plan', 'success');
 }
 
 async function removeExerciseFromPlan(templateId, exerciseId) {
-    await api('DELETE', `/api/plans/${templateId}/exercises/${exerciseId}`);
+    await api('DELETE', `/api/v1/plans/${templateId}/exercises/${exerciseId}`);
     await loadPlans();
     showStatus('Exercise removed from plan', 'success');
 }
@@ -208,7 +285,7 @@ async function removeExerciseFromPlan(templateId, exerciseId) {
 // ============================================================================
 
 async function createExercise(name) {
-    const exercise = await api('POST', '/api/exercises', { name });
+    const exercise = await api('POST', '/api/v1/exercises', { name });
     state.exercises.push(exercise);
     renderAllExercises();
     showStatus('Exercise created', 'success');
@@ -216,7 +293,7 @@ async function createExercise(name) {
 }
 
 async function updateExercise(id, updates) {
-    const exercise = await api('PATCH', `/api/exercises/${id}`, updates);
+    const exercise = await api('PATCH', `/api/v1/exercises/${id}`, updates);
     const idx = state.exercises.findIndex(e => e.id === id);
     if (idx >= 0) state.exercises[idx] = exercise;
     renderAllExercises();
@@ -229,7 +306,7 @@ async function updateExercise(id, updates) {
 
 async function saveSet(exerciseId, setNumber, weight, reps) {
     const dateStr = formatDate(state.currentDate);
-    const set = await api('PUT', `/api/days/${dateStr}/exercises/${exerciseId}/sets/${setNumber}`, {
+    const set = await api('PUT', `/api/v1/days/${dateStr}/exercises/${exerciseId}/sets/${setNumber}`, {
         weight: weight || null,
         reps: reps || null,
         completed_at: new Date().toISOString(),
@@ -252,7 +329,7 @@ async function saveSet(exerciseId, setNumber, weight, reps) {
 
 async function deleteSet(exerciseId, setNumber) {
     const dateStr = formatDate(state.currentDate);
-    await api('DELETE', `/api/days/${dateStr}/exercises/${exerciseId}/sets/${setNumber}`);
+    await api('DELETE', `/api/v1/days/${dateStr}/exercises/${exerciseId}/sets/${setNumber}`);
     
     // Update local state
     if (state.todaySets[exerciseId]) {
@@ -647,6 +724,51 @@ function initEventHandlers() {
         }
     });
     
+    // Passkeys
+    document.getElementById('passkey-login').classList.toggle('hidden', !passkeysSupported());
+    document.getElementById('add-passkey-btn').classList.toggle('hidden', !passkeysSupported());
+
+    document.getElementById('passkey-login').addEventListener('click', async () => {
+        const status = document.getElementById('auth-status');
+        try {
+            await loginWithPasskey();
+        } catch (e) {
+            status.textContent = e.message;
+            status.className = 'status error';
+        }
+    });
+
+    document.getElementById('add-passkey-btn').addEventListener('click', () => {
+        document.getElementById('passkey-name').value = '';
+        document.getElementById('passkey-password').value = '';
+        document.getElementById('passkey-totp').value = '';
+        document.getElementById('passkey-modal').classList.remove('hidden');
+        document.getElementById('passkey-name').focus();
+    });
+
+    document.getElementById('close-passkey-modal').addEventListener('click', () => {
+        closeModal('passkey-modal');
+    });
+
+    document.getElementById('cancel-passkey').addEventListener('click', () => {
+        closeModal('passkey-modal');
+    });
+
+    document.getElementById('save-passkey').addEventListener('click', async () => {
+        const name = document.getElementById('passkey-name').value.trim();
+        const password = document.getElementById('passkey-password').value;
+        const totp = document.getElementById('passkey-totp').value;
+        if (!name) return;
+
+        try {
+            await addPasskey(name, password, totp);
+            closeModal('passkey-modal');
+            showStatus('Passkey added', 'success');
+        } catch (e) {
+            showStatus(e.message, 'error');
+        }
+    });
+
     // Navigation
     document.querySelectorAll('.nav-btn').forEach(btn => {
         btn.addEventListener('click', () => switchTab(btn.dataset.view));